@@ -0,0 +1,128 @@
+//! Compares global registry lookups against the previous implementation, which kept registered types
+//! and type names in separate `RwLock`ed maps, and cloned the looked-up type.
+//!
+//! Run with `cargo bench --features registry`.
+
+use std::any::TypeId;
+use std::collections::HashMap;
+use std::sync::{Arc, Barrier, RwLock};
+use std::borrow::Cow;
+use std::hint::black_box;
+use std::thread;
+use std::time::{Duration, Instant};
+use structural_reflection::{PrimitiveType, RustType, RustTypeName, TypeRegistry, TypeStructure, TypeStructureBody, TypeStructureBodyField};
+
+const NUM_TYPES: usize = 100;
+const NUM_THREADS: usize = 8;
+const ITERS: u64 = 1_000_000;
+
+/// The previous implementation's global registry: lookups by name read-lock `types`,
+/// lookups by id read-lock `names` then `types`, and both clone the type
+struct LockedRegistry {
+    types: RwLock<HashMap<RustTypeName, RustType>>,
+    names: RwLock<HashMap<TypeId, RustTypeName>>
+}
+
+impl LockedRegistry {
+    fn of(registry: &TypeRegistry) -> Self {
+        LockedRegistry {
+            types: RwLock::new(registry.types().map(|rust_type| (rust_type.type_name.clone(), RustType::clone(rust_type))).collect()),
+            names: RwLock::new(registry.type_names().map(|(type_id, type_name)| (type_id, type_name.clone())).collect())
+        }
+    }
+
+    fn lookup(&self, type_name: &RustTypeName) -> Option<RustType> {
+        self.types.read().unwrap().get(type_name).cloned()
+    }
+
+    fn lookup_from_id(&self, type_id: TypeId) -> Option<RustType> {
+        let names = self.names.read().unwrap();
+        let types = self.types.read().unwrap();
+        names.get(&type_id).and_then(|type_name| types.get(type_name)).cloned()
+    }
+}
+
+fn bench_type_name(index: usize) -> RustTypeName {
+    RustTypeName::simple(format!("BenchType{}", index))
+}
+
+/// Registers types with a few fields, so cloning them costs about as much as a typical struct
+fn register_bench_types() {
+    TypeRegistry::with_global_mut(|registry| for index in 0..NUM_TYPES {
+        let fields = ["x", "y", "z", "w"].into_iter().map(|name| TypeStructureBodyField {
+            name: name.to_string(),
+            rust_type: PrimitiveType::F32.rust_type()
+        }).collect();
+        registry.register(Cow::Owned(RustType {
+            type_id: None,
+            type_name: bench_type_name(index),
+            size: 16,
+            align: 4,
+            structure: TypeStructure::CReprStruct { body: TypeStructureBody::Fields(fields) }
+        }), None);
+    });
+}
+
+/// Run `lookup` `iters` times on each of [NUM_THREADS] threads at once, cycling through `keys`,
+/// returning the total time
+fn run_contended<K: Send + Sync + 'static>(iters: u64, keys: Arc<Vec<K>>, lookup: impl Fn(&K) + Send + Sync + 'static) -> Duration {
+    let lookup = Arc::new(lookup);
+    let barrier = Arc::new(Barrier::new(NUM_THREADS + 1));
+    let threads = (0..NUM_THREADS).map(|thread_index| {
+        let lookup = lookup.clone();
+        let keys = keys.clone();
+        let barrier = barrier.clone();
+        thread::spawn(move || {
+            barrier.wait();
+            for iter in 0..iters as usize {
+                lookup(&keys[(iter + thread_index) % keys.len()]);
+            }
+        })
+    }).collect::<Vec<_>>();
+    barrier.wait();
+    let start = Instant::now();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    start.elapsed()
+}
+
+/// Run `lookup` `ITERS` times on one thread, then on [NUM_THREADS] threads at once, and print the time per lookup
+fn bench<K: Send + Sync + 'static>(name: &str, keys: Vec<K>, lookup: impl Fn(&K) + Send + Sync + 'static) {
+    let keys = Arc::new(keys);
+    let key = &keys[keys.len() / 2];
+    let start = Instant::now();
+    for _ in 0..ITERS {
+        lookup(black_box(key));
+    }
+    let uncontended = start.elapsed();
+    let contended = run_contended(ITERS, keys, lookup);
+    println!(
+        "{:<16} {:>10.1?}/lookup {:>10.1?}/lookup contended",
+        name,
+        uncontended / ITERS as u32,
+        contended / (ITERS as u32 * NUM_THREADS as u32)
+    );
+}
+
+fn main() {
+    register_bench_types();
+    let names = (0..NUM_TYPES).map(bench_type_name).collect::<Vec<_>>();
+    // The bench types have no ids, so lookups by id use the builtins
+    let ids = TypeRegistry::global().type_names().map(|(type_id, _)| type_id).collect::<Vec<_>>();
+    let locked = Arc::new(LockedRegistry::of(&TypeRegistry::global()));
+
+    let locked_by_name = locked.clone();
+    bench("rwlock_clone", names.clone(), move |name| {
+        black_box(locked_by_name.lookup(name));
+    });
+    bench("arc_swap", names, |name| {
+        black_box(RustType::lookup(name));
+    });
+    bench("rwlock_clone_id", ids.clone(), move |type_id| {
+        black_box(locked.lookup_from_id(*type_id));
+    });
+    bench("arc_swap_id", ids, |type_id| {
+        black_box(RustType::lookup_from_id(*type_id));
+    });
+}
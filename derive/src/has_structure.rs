@@ -1,7 +1,7 @@
 use proc_macro2::{Literal, TokenStream};
 
 use quote::quote;
-use syn::{Data, DataStruct, DataEnum, Fields, parse_quote, Generics, WhereClause, TypeGenerics};
+use syn::{Data, DataStruct, DataEnum, Field, Fields, parse_quote, Generics, WhereClause, TypeGenerics};
 use syn::spanned::Spanned;
 use crate::common::{common_derive, recursive_impl_generics};
 
@@ -10,19 +10,54 @@ pub(crate) fn derive_impl(input: syn::DeriveInput) -> syn::Result<TokenStream> {
         .find(|a| a.path.segments.last().unwrap().ident == "repr")
         .and_then(|a| a.parse_args::<syn::Ident>().ok())
         .map_or_else(|| String::from("Rust"), |ident| ident.to_string());
-    match repr.as_str() {
-        "C" | "transparent" => derive_c_impl(input),
-        _ => Err(syn::Error::new(
+    let impl_ = match repr.as_str() {
+        "C" | "transparent" => derive_c_impl(&input)?,
+        _ => return Err(syn::Error::new(
             input.span(),
             "HasStructure can only be derived for types with `repr(C)` or `repr(transparent)`",
         )),
+    };
+    let register = if has_register_attr(&input)? {
+        derive_register(&input)?
+    } else {
+        quote!()
+    };
+    Ok(quote! {
+        #impl_
+        #register
+    })
+}
+
+/// Whether the type has `#[has_structure(register)]`
+fn has_register_attr(input: &syn::DeriveInput) -> syn::Result<bool> {
+    let mut register = false;
+    for attr in input.attrs.iter().filter(|a| a.path.is_ident("has_structure")) {
+        let arg = attr.parse_args::<syn::Ident>()?;
+        if arg != "register" {
+            return Err(syn::Error::new(arg.span(), "unknown has_structure attribute, expected `register`"));
+        }
+        register = true;
     }
+    Ok(register)
+}
+
+fn derive_register(input: &syn::DeriveInput) -> syn::Result<TokenStream> {
+    if let Some(param) = input.generics.params.first() {
+        return Err(syn::Error::new(
+            param.span(),
+            "`has_structure(register)` isn't supported for generic types, register their instantiations instead",
+        ));
+    }
+    let ident = &input.ident;
+    Ok(quote! {
+        structural_reflection::inventory::submit!(structural_reflection::AutoRegister::of::<#ident>());
+    })
 }
 
-fn derive_c_impl(input: syn::DeriveInput) -> syn::Result<TokenStream> {
+fn derive_c_impl(input: &syn::DeriveInput) -> syn::Result<TokenStream> {
     match &input.data {
-        Data::Struct(s) => derive_c_struct(&input, s),
-        Data::Enum(e) => derive_c_enum(&input, e),
+        Data::Struct(s) => derive_c_struct(input, s),
+        Data::Enum(e) => derive_c_enum(input, e),
         Data::Union(u) => Err(syn::Error::new(
             u.union_token.span(),
             "HasStructure cannot be derived for unions (maybe in the future, submit an issue or PR)",
@@ -34,6 +69,7 @@ fn derive_c_struct(input: &syn::DeriveInput, s: &DataStruct) -> syn::Result<Toke
     let ident = &input.ident;
     let (impl_generics, type_generics, where_clause) = common_derive_has_structure(&input.generics);
     let body = derive_body(&s.fields)?;
+    let valid_impl = derive_valid_structure(input, s.fields.iter());
     Ok(quote! {
         #[automatically_derived]
         impl #impl_generics structural_reflection::HasStructure for #ident #type_generics #where_clause {
@@ -43,6 +79,8 @@ fn derive_c_struct(input: &syn::DeriveInput, s: &DataStruct) -> syn::Result<Toke
                 }
             }
         }
+
+        #valid_impl
     })
 }
 
@@ -57,6 +95,7 @@ fn derive_c_enum(input: &syn::DeriveInput, s: &DataEnum) -> syn::Result<TokenStr
             body: #body
         }))
     }).try_collect::<Vec<TokenStream>>()?;
+    let valid_impl = derive_valid_structure(input, s.variants.iter().flat_map(|variant| variant.fields.iter()));
     Ok(quote! {
         #[automatically_derived]
         impl #impl_generics structural_reflection::HasStructure for #ident #type_generics #where_clause {
@@ -66,9 +105,27 @@ fn derive_c_enum(input: &syn::DeriveInput, s: &DataEnum) -> syn::Result<TokenStr
                 }
             }
         }
+
+        #valid_impl
     })
 }
 
+/// `HasValidStructure` impl, which holds if every field's type has a valid structure:
+/// the type is `repr(C)` or `repr(transparent)`, so the derived structure has its layout
+fn derive_valid_structure<'a>(input: &syn::DeriveInput, fields: impl Iterator<Item=&'a Field>) -> TokenStream {
+    let ident = &input.ident;
+    let (impl_generics, type_generics, where_clause) = common_derive_has_structure(&input.generics);
+    let mut where_clause = where_clause.unwrap_or_else(|| parse_quote!(where));
+    for field in fields {
+        let ty = &field.ty;
+        where_clause.predicates.push(parse_quote!(#ty: structural_reflection::HasValidStructure));
+    }
+    quote! {
+        #[automatically_derived]
+        unsafe impl #impl_generics structural_reflection::HasValidStructure for #ident #type_generics #where_clause {}
+    }
+}
+
 fn common_derive_has_structure(generics: &Generics) -> (TokenStream, TypeGenerics<'_>, Option<WhereClause>) {
     let (_, type_generics, _) = generics.split_for_impl();
     let (_, where_clause) = common_derive(generics);
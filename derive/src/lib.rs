@@ -30,10 +30,12 @@ pub fn derive_has_type_name(input: TokenStream) -> TokenStream {
         .into()
 }
 
-/// Generates an implementation of the `HasStructure` trait.
+/// Generates an implementation of the `HasStructure` trait, and of `HasValidStructure` where every field's type implements it.
 ///
 /// - `#[has_structure(opaque)]`: causes the field to be considered an opaque type.
 /// - `#[has_structure(name = alternate)]`: causes the field to be named `alternate`.
+/// - `#[has_structure(register)]` on the type: registers it in the global registry before the registry
+///   is first accessed. Requires the `auto-register` feature, and the type must not have generic parameters.
 ///
 /// The struct must have `repr(C)` or `repr(transparent)`, otherwise this will not compile.
 /// Additionally, the fields must all derive `HasStructure` and be sized.
@@ -0,0 +1,80 @@
+use std::borrow::Cow;
+use std::cell::Cell;
+use std::sync::Once;
+use crate::{HasStructure, IntrinsicRustType, RustType, TypeRegistry};
+
+#[doc(hidden)]
+pub use inventory;
+
+/// A type which is registered in the global registry before it's first accessed.
+///
+/// You usually don't submit these yourself: `#[derive(HasStructure)]` with `#[has_structure(register)]` does.
+/// To submit one manually, use `inventory::submit!(AutoRegister::of::<T>())`.
+pub struct AutoRegister {
+    rust_type: fn() -> RustType,
+    intrinsic_rust_type: fn() -> IntrinsicRustType
+}
+
+inventory::collect!(AutoRegister);
+
+impl AutoRegister {
+    /// Registers `T` (like [RustType::of])
+    pub const fn of<T: HasStructure>() -> Self where T::StaticId: Sized {
+        AutoRegister {
+            rust_type: RustType::of_dont_register::<T>,
+            intrinsic_rust_type: IntrinsicRustType::of::<T>
+        }
+    }
+
+    /// Iterate all submitted types
+    pub fn iter() -> impl Iterator<Item=&'static AutoRegister> {
+        inventory::iter::<AutoRegister>.into_iter()
+    }
+}
+
+impl TypeRegistry {
+    /// Registers all submitted [AutoRegister] types. The global registry does this automatically
+    /// before it's first accessed.
+    ///
+    /// Computing the types registers their fields in the global registry, so this must not be called
+    /// while the global registry is locked (e.g. in [TypeRegistry::with_global_mut]).
+    pub fn register_auto_registered(&mut self) {
+        for (rust_type, intrinsic_rust_type) in compute_auto_registered() {
+            self.register(Cow::Owned(rust_type), Some(intrinsic_rust_type));
+        }
+    }
+}
+
+/// Computes all submitted types before any are registered
+fn compute_auto_registered() -> Vec<(RustType, IntrinsicRustType)> {
+    AutoRegister::iter().map(|auto_register| {
+        ((auto_register.rust_type)(), (auto_register.intrinsic_rust_type)())
+    }).collect()
+}
+
+thread_local! {
+    /// Whether this thread is registering the submitted types in the global registry
+    static IS_AUTO_REGISTERING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Registers the submitted types in the global registry if they haven't been yet.
+///
+/// Other threads wait until they are registered. Accesses during the registration (when computing
+/// the types registers their fields) see a partially-registered registry.
+pub(crate) fn ensure_auto_registered() {
+    static AUTO_REGISTERED: Once = Once::new();
+    if AUTO_REGISTERED.is_completed() || IS_AUTO_REGISTERING.with(Cell::get) {
+        return;
+    }
+    AUTO_REGISTERED.call_once(|| {
+        IS_AUTO_REGISTERING.with(|is_auto_registering| is_auto_registering.set(true));
+        // Registering each type separately would copy the global registry once per type
+        let auto_registered = compute_auto_registered();
+        TypeRegistry::with_global_mut(|registry| {
+            for (rust_type, intrinsic_rust_type) in auto_registered {
+                registry.register(Cow::Owned(rust_type), Some(intrinsic_rust_type));
+            }
+        });
+        IS_AUTO_REGISTERING.with(|is_auto_registering| is_auto_registering.set(false));
+    });
+}
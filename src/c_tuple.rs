@@ -1,4 +1,4 @@
-use crate::{HasStructure, HasTypeName, RustType, RustTypeName, TypeStructure};
+use crate::{HasStructure, HasTypeName, HasValidStructure, RustType, RustTypeName, TypeStructure};
 
 pub trait CTuple {
     type AsReg;
@@ -79,6 +79,7 @@ macro impl_c_tuple($name:ident, $($t:ident $x:ident),+) {
             }
         }
     }
+    unsafe impl<$($t: HasValidStructure),+> HasValidStructure for $name<$($t),+> where $($t::StaticId: Sized),+ {}
 }
 
 impl_c_tuple!(CTuple1, A a);
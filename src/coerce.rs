@@ -0,0 +1,234 @@
+use std::iter::zip;
+use std::ptr;
+use crate::{align_up, discriminant_size, infer_c_tuple_elem_offsets, infer_enum_payload_offset, IsSubtypeOf, RustType, TypeStructure, TypeStructureBody};
+
+/// Instructions to convert a value of one type into a value of a structural supertype,
+/// e.g. when the fields are reordered or the source has extra fields.
+/// Created by [RustType::coercion_plan] and applied by [CoercionPlan::execute].
+///
+/// Bytes in the target which aren't written by any step (padding) are left unchanged.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CoercionPlan {
+    /// Size of the source value
+    pub source_size: usize,
+    /// Size of the target value
+    pub target_size: usize,
+    pub steps: Vec<CoercionStep>
+}
+
+/// Single instruction in a [CoercionPlan]. Offsets are relative to the start of the values the plan applies to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CoercionStep {
+    /// Copy `length` bytes as-is
+    Copy { source_offset: usize, target_offset: usize, length: usize },
+    /// Coerce a nested value (e.g. a field whose type is a different subtype)
+    Nested { source_offset: usize, target_offset: usize, plan: CoercionPlan },
+    /// Remap the discriminant of an enum and coerce the payload of the corresponding variant
+    Enum(EnumCoercion)
+}
+
+/// How to coerce a `#[repr(C)]` enum into another with a superset of its variants
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnumCoercion {
+    pub source_discriminant_size: usize,
+    pub source_payload_offset: usize,
+    pub target_discriminant_size: usize,
+    pub target_payload_offset: usize,
+    /// Indexed by the source discriminant
+    pub variants: Vec<VariantCoercion>
+}
+
+/// How to coerce one enum variant: see [EnumCoercion]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VariantCoercion {
+    pub target_discriminant: usize,
+    /// Coerces the payload, offsets are relative to the source and target payload offsets
+    pub payload: CoercionPlan
+}
+
+impl RustType {
+    /// Returns instructions to convert a value of this type into a value of `target`,
+    /// or `None` if this type isn't definitely a subtype of `target` (see [RustType::is_rough_subtype_of])
+    /// or either type's layout is unknown.
+    ///
+    /// Fields are matched by name and tuple elements by position, like in [TypeStructure::is_structural_subtype_of];
+    /// types with the same type id, primitives, and pointers are copied as-is.
+    /// Enum discriminants are assumed to be the variant indices.
+    pub fn coercion_plan(&self, target: &RustType) -> Option<CoercionPlan> {
+        if self.is_rough_subtype_of(target) != IsSubtypeOf::Yes {
+            return None;
+        }
+        coercion_plan(self, target)
+    }
+}
+
+impl CoercionPlan {
+    fn new(source_size: usize, target_size: usize) -> Self {
+        CoercionPlan {
+            source_size,
+            target_size,
+            steps: Vec::new()
+        }
+    }
+
+    /// Does this plan only copy bytes?
+    pub fn is_copy_only(&self) -> bool {
+        self.steps.iter().all(|step| matches!(step, CoercionStep::Copy { .. }))
+    }
+
+    /// Apply the plan, converting the value at `source` into a value at `target`.
+    ///
+    /// # Safety
+    /// `source` must point to a valid value of the plan's source type (at least `source_size` readable bytes),
+    /// `target` must point to at least `target_size` writable bytes, and they must not overlap.
+    pub unsafe fn execute(&self, source: *const u8, target: *mut u8) {
+        for step in &self.steps {
+            match step {
+                CoercionStep::Copy { source_offset, target_offset, length } => {
+                    ptr::copy_nonoverlapping(source.add(*source_offset), target.add(*target_offset), *length);
+                }
+                CoercionStep::Nested { source_offset, target_offset, plan } => {
+                    plan.execute(source.add(*source_offset), target.add(*target_offset));
+                }
+                CoercionStep::Enum(enum_coercion) => {
+                    let discriminant = read_discriminant(source, enum_coercion.source_discriminant_size);
+                    let variant = enum_coercion.variants.get(discriminant)
+                        .unwrap_or_else(|| panic!("invalid source discriminant {}", discriminant));
+                    write_discriminant(target, enum_coercion.target_discriminant_size, variant.target_discriminant);
+                    variant.payload.execute(
+                        source.add(enum_coercion.source_payload_offset),
+                        target.add(enum_coercion.target_payload_offset)
+                    );
+                }
+            }
+        }
+    }
+
+    fn copy(&mut self, source_offset: usize, target_offset: usize, length: usize) {
+        if length == 0 {
+            return;
+        }
+        // Merge with the previous copy if contiguous
+        if let Some(CoercionStep::Copy { source_offset: prev_source_offset, target_offset: prev_target_offset, length: prev_length }) = self.steps.last_mut() {
+            if *prev_source_offset + *prev_length == source_offset && *prev_target_offset + *prev_length == target_offset {
+                *prev_length += length;
+                return;
+            }
+        }
+        self.steps.push(CoercionStep::Copy { source_offset, target_offset, length });
+    }
+
+    fn nested(&mut self, source_offset: usize, target_offset: usize, plan: CoercionPlan) {
+        if plan.is_copy_only() {
+            for step in plan.steps {
+                if let CoercionStep::Copy { source_offset: nested_source_offset, target_offset: nested_target_offset, length } = step {
+                    self.copy(source_offset + nested_source_offset, target_offset + nested_target_offset, length);
+                }
+            }
+        } else {
+            self.steps.push(CoercionStep::Nested { source_offset, target_offset, plan });
+        }
+    }
+}
+
+fn coercion_plan(source: &RustType, target: &RustType) -> Option<CoercionPlan> {
+    if source.size == usize::MAX || target.size == usize::MAX {
+        return None;
+    }
+    let mut plan = CoercionPlan::new(source.size, target.size);
+    if source.type_id.is_some() && source.type_id == target.type_id {
+        plan.copy(0, 0, source.size);
+        return Some(plan);
+    }
+    match (&source.structure, &target.structure) {
+        (TypeStructure::Primitive(_), TypeStructure::Primitive(_)) |
+        (TypeStructure::Pointer { .. }, TypeStructure::Pointer { .. }) => {
+            plan.copy(0, 0, target.size);
+        }
+        (TypeStructure::CReprStruct { body }, TypeStructure::CReprStruct { body: target_body }) => {
+            plan_body(&mut plan, body, target_body)?;
+        }
+        (TypeStructure::CTuple { elements }, TypeStructure::CTuple { elements: target_elements }) => {
+            plan_tuple(&mut plan, elements, target_elements)?;
+        }
+        (TypeStructure::Array { elem, length }, TypeStructure::Array { elem: target_elem, length: target_length }) if length == target_length => {
+            let elem_plan = coercion_plan(elem, target_elem)?;
+            if elem.size != 0 && target_elem.size != 0 {
+                let stride = align_up(elem.size, elem.align);
+                let target_stride = align_up(target_elem.size, target_elem.align);
+                for index in 0..*length {
+                    plan.nested(index * stride, index * target_stride, elem_plan.clone());
+                }
+            }
+        }
+        (TypeStructure::CReprEnum { variants }, TypeStructure::CReprEnum { variants: target_variants }) => {
+            let variant_coercions = variants.iter().map(|variant| {
+                let target_discriminant = target_variants.iter().position(|target_variant| variant.variant_name == target_variant.variant_name)?;
+                let target_body = &target_variants[target_discriminant].body;
+                let mut payload = CoercionPlan::new(variant.body.infer_size(), target_body.infer_size());
+                plan_body(&mut payload, &variant.body, target_body)?;
+                Some(VariantCoercion { target_discriminant, payload })
+            }).collect::<Option<Vec<_>>>()?;
+            plan.steps.push(CoercionStep::Enum(EnumCoercion {
+                source_discriminant_size: discriminant_size(variants.len()),
+                source_payload_offset: infer_enum_payload_offset(variants),
+                target_discriminant_size: discriminant_size(target_variants.len()),
+                target_payload_offset: infer_enum_payload_offset(target_variants),
+                variants: variant_coercions
+            }));
+        }
+        _ => return None
+    }
+    Some(plan)
+}
+
+fn plan_body(plan: &mut CoercionPlan, body: &TypeStructureBody, target_body: &TypeStructureBody) -> Option<()> {
+    match (body, target_body) {
+        (TypeStructureBody::None, TypeStructureBody::None) => Some(()),
+        (TypeStructureBody::Tuple(elements), TypeStructureBody::Tuple(target_elements)) => {
+            plan_tuple(plan, elements, target_elements)
+        }
+        (TypeStructureBody::Fields(fields), TypeStructureBody::Fields(target_fields)) => {
+            let offsets = infer_c_tuple_elem_offsets(fields.iter().map(|field| &field.rust_type)).collect::<Vec<_>>();
+            let target_offsets = infer_c_tuple_elem_offsets(target_fields.iter().map(|field| &field.rust_type));
+            for (target_field, target_offset) in zip(target_fields, target_offsets) {
+                let index = fields.iter().position(|field| field.name == target_field.name)?;
+                plan.nested(offsets[index], target_offset, coercion_plan(&fields[index].rust_type, &target_field.rust_type)?);
+            }
+            Some(())
+        }
+        _ => None
+    }
+}
+
+fn plan_tuple(plan: &mut CoercionPlan, elements: &[RustType], target_elements: &[RustType]) -> Option<()> {
+    if elements.len() != target_elements.len() {
+        return None;
+    }
+    let offsets = infer_c_tuple_elem_offsets(elements);
+    let target_offsets = infer_c_tuple_elem_offsets(target_elements);
+    for ((element, offset), (target_element, target_offset)) in zip(zip(elements, offsets), zip(target_elements, target_offsets)) {
+        plan.nested(offset, target_offset, coercion_plan(element, target_element)?);
+    }
+    Some(())
+}
+
+unsafe fn read_discriminant(source: *const u8, size: usize) -> usize {
+    match size {
+        1 => ptr::read_unaligned(source) as usize,
+        2 => ptr::read_unaligned(source as *const u16) as usize,
+        4 => ptr::read_unaligned(source as *const u32) as usize,
+        8 => ptr::read_unaligned(source as *const u64) as usize,
+        _ => panic!("unsupported discriminant size {}", size)
+    }
+}
+
+unsafe fn write_discriminant(target: *mut u8, size: usize, discriminant: usize) {
+    match size {
+        1 => ptr::write_unaligned(target, discriminant as u8),
+        2 => ptr::write_unaligned(target as *mut u16, discriminant as u16),
+        4 => ptr::write_unaligned(target as *mut u32, discriminant as u32),
+        8 => ptr::write_unaligned(target as *mut u64, discriminant as u64),
+        _ => panic!("unsupported discriminant size {}", size)
+    }
+}
@@ -0,0 +1,212 @@
+use std::alloc::{alloc, alloc_zeroed, dealloc, Layout};
+use std::borrow::Cow;
+use std::ptr::NonNull;
+use std::sync::Arc;
+use crate::{HasValidStructure, RustType, TypePath, TypeStructure, TypeStructureBody, ValueError, ValueMut, ValueRef};
+use crate::value::{can_reinterpret, check_layout, is_type};
+
+/// An owned value of a [RustType], e.g. one built at runtime by a script.
+///
+/// The value is stored in a buffer with the type's size and alignment. It's created from a concrete value
+/// ([DynValue::new]) or zero-initialized ([DynValue::zeroed]), then its fields can be set ([DynValue::set])
+/// and it can be extracted into a concrete type with the same layout ([DynValue::downcast]).
+///
+/// The value's drop glue isn't known, so it's never dropped: values which own resources (e.g. a `String`)
+/// and aren't extracted are leaked, as are fields which are overwritten.
+pub struct DynValue {
+    ptr: NonNull<u8>,
+    rust_type: Arc<RustType>
+}
+
+impl DynValue {
+    /// Moves the value into a buffer, with the type [RustType::of] `T`.
+    ///
+    /// Panics if the type's structure doesn't match its size and alignment, see [ValueRef::of]
+    pub fn new<T: HasValidStructure>(value: T) -> Self where T::StaticId: Sized {
+        let rust_type = Arc::new(RustType::of::<T>());
+        if let Err(error) = check_layout(&rust_type) {
+            panic!("HasStructure impl is wrong: {}", error);
+        }
+        let ptr = allocate(&rust_type, false).expect("layout of a sized type is known");
+        // SAFETY: the buffer has `T`'s layout
+        unsafe { ptr.as_ptr().cast::<T>().write(value) };
+        DynValue { ptr, rust_type }
+    }
+
+    /// A value of the type with all bytes zero: numbers are `0`, `bool`s are `false`, `char`s are `'\0'`,
+    /// and enums are their first variant.
+    ///
+    /// Fails if the type contains something besides primitives, e.g. pointers or opaque types,
+    /// its layout is unknown, or its structure doesn't match its size and alignment.
+    pub fn zeroed(rust_type: impl Into<Arc<RustType>>) -> Result<Self, ValueError> {
+        let rust_type = rust_type.into();
+        if !is_zeroable(&rust_type) {
+            return Err(ValueError::NotZeroable { type_name: rust_type.type_name.clone() });
+        }
+        check_layout(&rust_type)?;
+        let ptr = allocate(&rust_type, true)?;
+        Ok(DynValue { ptr, rust_type })
+    }
+
+    /// The value's type
+    pub fn rust_type(&self) -> &Arc<RustType> {
+        &self.rust_type
+    }
+
+    /// Reference to the value
+    pub fn as_ref(&self) -> ValueRef<'_> {
+        // SAFETY: the buffer contains a value of the type, and we're borrowed
+        unsafe { ValueRef::from_raw(self.ptr, Cow::Borrowed(self.rust_type.as_ref())) }
+    }
+
+    /// Mutable reference to the value
+    pub fn as_mut(&mut self) -> ValueMut<'_> {
+        // SAFETY: the buffer contains a value of the type, and we're mutably borrowed
+        unsafe { ValueMut::from_raw(self.ptr, Cow::Borrowed(self.rust_type.as_ref())) }
+    }
+
+    /// Overwrites the nested value at the path (see [ValueRef::at]) with `value`, whose type must be
+    /// the nested value's type (see [DynValue::downcast]). The old nested value isn't dropped.
+    pub fn set<T: HasValidStructure>(&mut self, path: &TypePath, value: T) -> Result<(), ValueError> where T::StaticId: Sized {
+        let mut dest = self.as_mut();
+        let mut dest = dest.at_mut(path)?;
+        if !is_type::<T>(dest.rust_type()) {
+            return Err(ValueError::IncompatibleType {
+                type_name: Box::new(T::type_name()),
+                other_type_name: Box::new(dest.rust_type().type_name.clone())
+            });
+        }
+        // SAFETY: the destination has `T`'s layout
+        unsafe { dest.as_mut_ptr().cast::<T>().write(value) };
+        Ok(())
+    }
+
+    /// Moves `value` into the nested value at the path, like [DynValue::set]
+    pub fn set_dyn(&mut self, path: &TypePath, value: DynValue) -> Result<(), ValueError> {
+        let mut dest = self.as_mut();
+        let mut dest = dest.at_mut(path)?;
+        let dest_ptr = dest.as_mut_ptr();
+        // SAFETY: the destination is a value of its type, in a separate buffer
+        unsafe { value.move_to(dest_ptr, dest.rust_type()) }
+    }
+
+    /// Whether the value can be extracted as `T`: the types are the same, or the value's type has no id,
+    /// its layout is identical to `T`'s, and its nested types which have ids are the same as `T`'s
+    pub fn is<T: HasValidStructure>(&self) -> bool where T::StaticId: Sized {
+        is_type::<T>(&self.rust_type)
+    }
+
+    /// Extracts the value as `T`, if it can be (see [DynValue::is]). Otherwise returns it back.
+    ///
+    /// If the value was built at runtime, layout compatibility can't check that it upholds `T`'s
+    /// invariants besides validity of its fields.
+    pub fn downcast<T: HasValidStructure>(self) -> Result<T, Self> where T::StaticId: Sized {
+        if !self.is::<T>() {
+            return Err(self);
+        }
+        // SAFETY: the buffer contains a valid `T`
+        Ok(unsafe { self.ptr.as_ptr().cast::<T>().read() })
+    }
+
+    /// Reference to the value as `T`, if it can be extracted as `T` (see [DynValue::is])
+    pub fn downcast_ref<T: HasValidStructure>(&self) -> Option<&T> where T::StaticId: Sized {
+        // SAFETY: the buffer contains a valid `T` and has its alignment
+        self.is::<T>().then(|| unsafe { &*self.ptr.as_ptr().cast::<T>() })
+    }
+
+    /// Mutable reference to the value as `T`, if it can be extracted as `T` (see [DynValue::is])
+    pub fn downcast_mut<T: HasValidStructure>(&mut self) -> Option<&mut T> where T::StaticId: Sized {
+        // SAFETY: the buffer contains a valid `T` and has its alignment
+        self.is::<T>().then(|| unsafe { &mut *self.ptr.as_ptr().cast::<T>() })
+    }
+}
+
+/// Only used to deserialize values in place
+#[cfg(feature = "serde")]
+impl DynValue {
+    /// An uninitialized buffer for a value of the type, which must be initialized before it's exposed.
+    /// Fails if the type's structure doesn't match its size and alignment
+    pub(crate) fn uninit(rust_type: Arc<RustType>) -> Result<Self, ValueError> {
+        check_layout(&rust_type)?;
+        let ptr = allocate(&rust_type, false)?;
+        Ok(DynValue { ptr, rust_type })
+    }
+
+    pub(crate) fn as_mut_ptr(&mut self) -> *mut u8 {
+        self.ptr.as_ptr()
+    }
+}
+
+impl DynValue {
+    /// Moves the value to `dest`, which has the type `dest_type` (see [DynValue::set])
+    ///
+    /// # Safety
+    /// `dest` must point to a buffer of `dest_type`'s size, separate from this one
+    pub(crate) unsafe fn move_to(self, dest: *mut u8, dest_type: &RustType) -> Result<(), ValueError> {
+        check_can_reinterpret(&self.rust_type, dest_type)?;
+        if self.rust_type.size > dest_type.size {
+            return Err(ValueError::OutOfBounds { type_name: dest_type.type_name.clone(), offset: 0, size: self.rust_type.size });
+        }
+        dest.copy_from_nonoverlapping(self.ptr.as_ptr(), self.rust_type.size);
+        // The value was moved, and dropping only frees its buffer
+        drop(self);
+        Ok(())
+    }
+}
+
+impl Drop for DynValue {
+    /// Frees the buffer without dropping the value
+    fn drop(&mut self) {
+        // SAFETY: the buffer was allocated with this layout
+        unsafe { dealloc(self.ptr.as_ptr(), buffer_layout(&self.rust_type).unwrap()) };
+    }
+}
+
+/// Fails unless a value of `rust_type` can be reinterpreted as one of `other` (see [can_reinterpret])
+fn check_can_reinterpret(rust_type: &RustType, other: &RustType) -> Result<(), ValueError> {
+    match can_reinterpret(rust_type, other) {
+        true => Ok(()),
+        false => Err(ValueError::IncompatibleType {
+            type_name: Box::new(rust_type.type_name.clone()),
+            other_type_name: Box::new(other.type_name.clone())
+        })
+    }
+}
+
+/// Layout of a buffer for the type (buffers are never zero-sized)
+fn buffer_layout(rust_type: &RustType) -> Option<Layout> {
+    Layout::from_size_align(rust_type.size.max(1), rust_type.align).ok()
+}
+
+fn allocate(rust_type: &RustType, zeroed: bool) -> Result<NonNull<u8>, ValueError> {
+    let layout = buffer_layout(rust_type).ok_or_else(|| ValueError::UnknownLayout { type_name: rust_type.type_name.clone() })?;
+    // SAFETY: the layout isn't zero-sized
+    let ptr = unsafe { if zeroed { alloc_zeroed(layout) } else { alloc(layout) } };
+    Ok(NonNull::new(ptr).unwrap_or_else(|| std::alloc::handle_alloc_error(layout)))
+}
+
+/// Whether all bytes zero is a valid value of the type
+pub(crate) fn is_zeroable(rust_type: &RustType) -> bool {
+    match &rust_type.structure {
+        TypeStructure::Primitive(_) => true,
+        TypeStructure::CReprStruct { body } => is_body_zeroable(body),
+        // Discriminant 0 is the first variant
+        TypeStructure::CReprEnum { variants } => variants.first().is_some_and(|variant| is_body_zeroable(&variant.body)),
+        TypeStructure::CTuple { elements } => elements.iter().all(is_zeroable),
+        TypeStructure::Array { elem, length: _ } => is_zeroable(elem),
+        TypeStructure::Opaque |
+        TypeStructure::OpaqueTuple { .. } |
+        TypeStructure::OpaqueFields { .. } |
+        TypeStructure::Slice { .. } |
+        TypeStructure::Var(_) |
+        TypeStructure::Pointer { .. } => false
+    }
+}
+
+fn is_body_zeroable(body: &TypeStructureBody) -> bool {
+    match body {
+        TypeStructureBody::None => true,
+        TypeStructureBody::Tuple(elements) => elements.iter().all(is_zeroable),
+        TypeStructureBody::Fields(fields) => fields.iter().all(|field| is_zeroable(&field.rust_type))
+    }
+}
@@ -1,5 +1,7 @@
 use std::any::TypeId;
+use std::borrow::Cow;
 use std::marker::PhantomData;
+use std::ptr::NonNull;
 use crate::{TypeStructure, RustTypeName, RustPointerKind, PrimitiveType, RustType, TypeStructureBody, qualifier};
 
 pub trait HasTypeName {
@@ -21,6 +23,16 @@ pub trait HasStructure: HasTypeName {
     fn structure() -> TypeStructure;
 }
 
+/// A [HasStructure] whose structure is guaranteed to describe the type's actual layout, so values
+/// can be read through it ([ValueRef::of](crate::ValueRef::of), [DynValue::new](crate::DynValue::new)).
+/// Implemented for the types this crate implements [HasStructure] for, and by the derive
+/// if every field's type implements it.
+///
+/// # Safety
+/// Every primitive, field, and element in the structure must be at the offset the structure gives it,
+/// and always be initialized and a valid value of its type
+pub unsafe trait HasValidStructure: HasStructure {}
+
 impl HasTypeName for () {
     type StaticId = ();
 
@@ -39,6 +51,8 @@ impl HasStructure for () {
     }
 }
 
+unsafe impl HasValidStructure for () {}
+
 impl<T: HasTypeName> HasTypeName for PhantomData<T> {
     type StaticId = PhantomData<T::StaticId>;
 
@@ -59,6 +73,8 @@ impl<T: HasStructure> HasStructure for PhantomData<T> where T::StaticId: Sized {
     }
 }
 
+unsafe impl<T: HasStructure> HasValidStructure for PhantomData<T> where T::StaticId: Sized {}
+
 impl HasTypeName for str {
     type StaticId = str;
 
@@ -89,6 +105,8 @@ impl<T: HasStructure> HasStructure for [T] where T::StaticId: Sized {
     }
 }
 
+unsafe impl<T: HasValidStructure> HasValidStructure for [T] where T::StaticId: Sized {}
+
 impl<T: HasTypeName, const LEN: usize> HasTypeName for [T; LEN] where T::StaticId: Sized {
     type StaticId = [T::StaticId; LEN];
 
@@ -109,8 +127,10 @@ impl<T: HasStructure, const LEN: usize> HasStructure for [T; LEN] where T::Stati
     }
 }
 
+unsafe impl<T: HasValidStructure, const LEN: usize> HasValidStructure for [T; LEN] where T::StaticId: Sized {}
+
 macro impl_opaque($($qualifier:ident::)*[$ty:ident]$(<$($tparam:ident),+>)?) {
-impl $(<$($tparam: HasTypeName),+>)? HasTypeName for $($qualifier::)*$ty$(<$($tparam),+>)? {
+impl $(<$($tparam: HasTypeName),+>)? HasTypeName for $($qualifier::)*$ty$(<$($tparam),+>)? $(where $($tparam::StaticId: Sized),+)? {
     type StaticId = $($qualifier::)*$ty $(<$($tparam::StaticId),+>)?;
 
     fn type_name() -> RustTypeName {
@@ -122,11 +142,59 @@ impl $(<$($tparam: HasTypeName),+>)? HasTypeName for $($qualifier::)*$ty$(<$($tp
     }
 }
 
-impl $(<$($tparam: HasStructure),+>)? HasStructure for $($qualifier::)*$ty$(<$($tparam),+>)? {
+impl $(<$($tparam: HasStructure),+>)? HasStructure for $($qualifier::)*$ty$(<$($tparam),+>)? $(where $($tparam::StaticId: Sized),+)? {
+    fn structure() -> TypeStructure {
+        TypeStructure::Opaque
+    }
+}
+// Opaque values can't be read
+unsafe impl $(<$($tparam: HasStructure),+>)? HasValidStructure for $($qualifier::)*$ty$(<$($tparam),+>)? $(where $($tparam::StaticId: Sized),+)? {}
+}
+
+/// Opaque smart pointer whose pointee may be unsized
+macro impl_opaque_pointer($($qualifier:ident::)*[$ty:ident]) {
+impl<T: HasTypeName + ?Sized> HasTypeName for $($qualifier::)*$ty<T> {
+    type StaticId = $($qualifier::)*$ty<T::StaticId>;
+
+    fn type_name() -> RustTypeName {
+        RustTypeName::Ident {
+            qualifier: qualifier![$(stringify!($qualifier)),*],
+            simple_name: stringify!($ty).to_string(),
+            generic_args: vec![T::type_name()],
+        }
+    }
+}
+
+impl<T: HasTypeName + ?Sized> HasStructure for $($qualifier::)*$ty<T> {
     fn structure() -> TypeStructure {
         TypeStructure::Opaque
     }
 }
+unsafe impl<T: HasTypeName + ?Sized> HasValidStructure for $($qualifier::)*$ty<T> {}
+}
+
+/// `repr(transparent)` wrapper, so its structure is a tuple struct containing the wrapped type
+macro impl_transparent($($qualifier:ident::)*[$ty:ident]) {
+impl<T: HasTypeName> HasTypeName for $($qualifier::)*$ty<T> where T::StaticId: Sized {
+    type StaticId = $($qualifier::)*$ty<T::StaticId>;
+
+    fn type_name() -> RustTypeName {
+        RustTypeName::Ident {
+            qualifier: qualifier![$(stringify!($qualifier)),*],
+            simple_name: stringify!($ty).to_string(),
+            generic_args: vec![T::type_name()],
+        }
+    }
+}
+
+impl<T: HasStructure> HasStructure for $($qualifier::)*$ty<T> where T::StaticId: Sized {
+    fn structure() -> TypeStructure {
+        TypeStructure::CReprStruct {
+            body: TypeStructureBody::Tuple(vec![RustType::of::<T>()])
+        }
+    }
+}
+unsafe impl<T: HasValidStructure> HasValidStructure for $($qualifier::)*$ty<T> where T::StaticId: Sized {}
 }
 
 macro impl_primitive($prim_tt:tt, $prim_type:ident) {
@@ -147,6 +215,7 @@ impl HasStructure for $prim_tt {
         TypeStructure::Primitive(PrimitiveType::$prim_type)
     }
 }
+unsafe impl HasValidStructure for $prim_tt {}
 }
 
 macro impl_pointer(($($ptr_tt:tt)+), ($($static_ptr_tt:tt)+), $ptr_kind:ident) {
@@ -171,6 +240,7 @@ impl<T: HasTypeName + ?Sized> HasStructure for $($ptr_tt)+ T {
         }
     }
 }
+unsafe impl<T: HasTypeName + ?Sized> HasValidStructure for $($ptr_tt)+ T {}
 }
 
 impl_primitive!(u8, U8);
@@ -198,4 +268,74 @@ impl_pointer!((*mut), (*mut), MutRaw);
 impl_opaque!(std::path::[PathBuf]);
 impl_opaque!(std::fs::[File]);
 impl_opaque!(std::string::[String]);
-impl_opaque!(std::boxed::[Box]<T>);
\ No newline at end of file
+impl_opaque!(std::ffi::[OsString]);
+impl_opaque!(std::time::[Duration]);
+impl_opaque!(std::cmp::[Ordering]);
+impl_opaque!(std::vec::[Vec]<T>);
+impl_opaque!(std::collections::[VecDeque]<T>);
+impl_opaque!(std::collections::[BTreeSet]<T>);
+impl_opaque!(std::collections::[HashSet]<T>);
+impl_opaque!(std::collections::[BTreeMap]<K, V>);
+impl_opaque!(std::collections::[HashMap]<K, V>);
+impl_opaque!(std::option::[Option]<T>);
+impl_opaque!(std::result::[Result]<T, E>);
+impl_opaque!(std::cell::[RefCell]<T>);
+impl_opaque!(std::sync::[Mutex]<T>);
+// Same layout as `T`, but may be uninitialized
+impl_opaque!(std::mem::[MaybeUninit]<T>);
+impl_opaque!(std::ops::[Range]<Idx>);
+impl_opaque!(std::ops::[RangeInclusive]<Idx>);
+impl_opaque!(std::ops::[RangeFrom]<Idx>);
+impl_opaque!(std::ops::[RangeTo]<Idx>);
+impl_opaque!(std::ops::[RangeToInclusive]<Idx>);
+impl_opaque!(std::ops::[RangeFull]);
+
+impl_opaque_pointer!(std::boxed::[Box]);
+impl_opaque_pointer!(std::rc::[Rc]);
+impl_opaque_pointer!(std::sync::[Arc]);
+
+impl_transparent!(std::cell::[Cell]);
+impl_transparent!(std::mem::[ManuallyDrop]);
+
+impl<T: HasTypeName + ?Sized> HasTypeName for NonNull<T> {
+    type StaticId = NonNull<T::StaticId>;
+
+    fn type_name() -> RustTypeName {
+        RustTypeName::Ident {
+            qualifier: qualifier!["std", "ptr"],
+            simple_name: "NonNull".to_string(),
+            generic_args: vec![T::type_name()],
+        }
+    }
+}
+
+/// `repr(transparent)` wrapper of `*const T`
+impl<T: HasTypeName + ?Sized> HasStructure for NonNull<T> {
+    fn structure() -> TypeStructure {
+        TypeStructure::CReprStruct {
+            body: TypeStructureBody::Tuple(vec![RustType::of::<*const T>()])
+        }
+    }
+}
+
+unsafe impl<T: HasTypeName + ?Sized> HasValidStructure for NonNull<T> {}
+
+impl<'a, B: HasTypeName + ToOwned + ?Sized> HasTypeName for Cow<'a, B> where B::StaticId: ToOwned, <B::StaticId as ToOwned>::Owned: 'static {
+    type StaticId = Cow<'static, B::StaticId>;
+
+    fn type_name() -> RustTypeName {
+        RustTypeName::Ident {
+            qualifier: qualifier!["std", "borrow"],
+            simple_name: "Cow".to_string(),
+            generic_args: vec![B::type_name()],
+        }
+    }
+}
+
+impl<'a, B: HasTypeName + ToOwned + ?Sized> HasStructure for Cow<'a, B> where B::StaticId: ToOwned, <B::StaticId as ToOwned>::Owned: 'static {
+    fn structure() -> TypeStructure {
+        TypeStructure::Opaque
+    }
+}
+
+unsafe impl<'a, B: HasTypeName + ToOwned + ?Sized> HasValidStructure for Cow<'a, B> where B::StaticId: ToOwned, <B::StaticId as ToOwned>::Owned: 'static {}
@@ -63,6 +63,7 @@ impl TryIndex<usize> for TypeStructure {
         match self {
             TypeStructure::Opaque |
             TypeStructure::Primitive(_) |
+            TypeStructure::Var(_) |
             TypeStructure::Pointer { .. } => Err(NotFound { index }),
             TypeStructure::OpaqueTuple { elements } => elements.try_index(index),
             TypeStructure::OpaqueFields { fields } => fields.try_index(index).map(|field| &field.rust_type),
@@ -106,6 +107,7 @@ impl TryIndexMut<usize> for TypeStructure {
         match self {
             TypeStructure::Opaque |
             TypeStructure::Primitive(_) |
+            TypeStructure::Var(_) |
             TypeStructure::Pointer { .. } => Err(NotFound { index }),
             TypeStructure::OpaqueTuple { elements } => elements.try_index_mut(index),
             TypeStructure::OpaqueFields { fields } => fields.try_index_mut(index).map(|field| &field.rust_type),
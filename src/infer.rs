@@ -0,0 +1,482 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{Display, Formatter};
+use std::iter::zip;
+use derive_more::{Display, Error};
+use crate::{IsSubtypeOf, PrimitiveType, RustType, RustTypeName, SubtypeReport, TypeEnumVariant, TypeStructure, TypeStructureBody};
+
+/// Identifies a type variable ([TypeStructure::Var]) within an [InferenceContext]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TypeVarId(pub(crate) usize);
+
+/// Assignment of type variables to types
+#[derive(Debug, Clone, Default)]
+pub struct Substitution(HashMap<TypeVarId, RustType>);
+
+/// Collects subtype constraints between types which may contain type variables,
+/// then solves them so that every variable can be resolved to a known type.
+///
+/// Solving works like Hindley-Milner inference, except constraints are subtyping instead of equality:
+/// - A variable constrained by another variable is the same variable
+/// - Otherwise, a variable with upper bounds (`var <: T`) becomes their [meet](RustType::meet),
+///   and a variable with only lower bounds (`T <: var`) becomes their [join](RustType::join)
+/// - Compound types are decomposed field-wise, element-wise, and variant-wise like in
+///   [TypeStructure::is_structural_subtype_of], so variables nested inside are also constrained.
+///   A pointer to a variable constrains the variable to be the other pointer's pointee, if it's a primitive or registered
+/// - Once every variable is assigned, each constraint must not definitely fail [RustType::is_rough_subtype_of]
+#[derive(Debug, Clone, Default)]
+pub struct InferenceContext {
+    next_var: usize,
+    /// `(sub, sup)`
+    constraints: Vec<(RustType, RustType)>,
+    substitution: Substitution
+}
+
+#[derive(Debug, Display, Error)]
+pub enum InferenceError {
+    /// A constraint doesn't hold after substituting the solved variables
+    #[display(fmt = "{} is not a subtype of {}: {}", "sub.qualified()", "sup.qualified()", report)]
+    Mismatch {
+        sub: Box<RustTypeName>,
+        sup: Box<RustTypeName>,
+        #[error(not(source))]
+        report: Box<SubtypeReport>
+    },
+    /// A variable would be assigned to a type containing itself
+    #[display(fmt = "{} occurs in its own solution {}", var, "type_name.qualified()")]
+    InfiniteType {
+        var: TypeVarId,
+        type_name: RustTypeName
+    },
+    /// A variable wasn't assigned, because no constraint affected it
+    #[display(fmt = "{} is unconstrained", var)]
+    Unresolved {
+        var: TypeVarId
+    }
+}
+
+#[derive(Default)]
+struct VarBounds {
+    lower: Vec<RustType>,
+    upper: Vec<RustType>
+}
+
+impl RustType {
+    /// The type of a type variable: anonymous with unknown layout
+    pub fn var(var: TypeVarId) -> Self {
+        RustType {
+            type_id: None,
+            type_name: var.type_name(),
+            size: usize::MAX,
+            align: usize::MAX,
+            structure: TypeStructure::Var(var)
+        }
+    }
+
+    /// Does this type have a type variable anywhere in its structure?
+    pub fn has_vars(&self) -> bool {
+        let mut has_vars = false;
+        self.visit_vars(&mut |_| has_vars = true);
+        has_vars
+    }
+
+    fn visit_vars(&self, f: &mut impl FnMut(TypeVarId)) {
+        match &self.structure {
+            TypeStructure::Var(var) => f(*var),
+            TypeStructure::Opaque | TypeStructure::Primitive(_) => {}
+            TypeStructure::Pointer { refd_name, .. } => refd_name.visit_vars(f),
+            TypeStructure::OpaqueTuple { elements } | TypeStructure::CTuple { elements } => {
+                for element in elements {
+                    element.visit_vars(f);
+                }
+            }
+            TypeStructure::OpaqueFields { fields } => {
+                for field in fields {
+                    field.rust_type.visit_vars(f);
+                }
+            }
+            TypeStructure::CReprEnum { variants } => {
+                for variant in variants {
+                    variant.body.visit_vars(f);
+                }
+            }
+            TypeStructure::CReprStruct { body } => body.visit_vars(f),
+            TypeStructure::Array { elem, length: _ } | TypeStructure::Slice { elem } => elem.visit_vars(f)
+        }
+    }
+}
+
+impl TypeStructureBody {
+    fn visit_vars(&self, f: &mut impl FnMut(TypeVarId)) {
+        match self {
+            TypeStructureBody::None => {}
+            TypeStructureBody::Tuple(elements) => {
+                for element in elements {
+                    element.visit_vars(f);
+                }
+            }
+            TypeStructureBody::Fields(fields) => {
+                for field in fields {
+                    field.rust_type.visit_vars(f);
+                }
+            }
+        }
+    }
+}
+
+impl RustTypeName {
+    fn visit_vars(&self, f: &mut impl FnMut(TypeVarId)) {
+        match self {
+            RustTypeName::Var { var } => f(*var),
+            RustTypeName::Ident { generic_args: elems, .. } | RustTypeName::Tuple { elems } => {
+                for elem in elems {
+                    elem.visit_vars(f);
+                }
+            }
+            RustTypeName::Pointer { refd: elem, .. } |
+            RustTypeName::Array { elem, .. } |
+            RustTypeName::Slice { elem } => elem.visit_vars(f),
+            RustTypeName::Anonymous { .. } | RustTypeName::ConstExpr { .. } => {}
+        }
+    }
+}
+
+impl TypeVarId {
+    fn type_name(&self) -> RustTypeName {
+        RustTypeName::Var { var: *self }
+    }
+}
+
+impl Display for TypeVarId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "?{}", self.0)
+    }
+}
+
+impl Substitution {
+    pub fn new() -> Self {
+        Substitution(HashMap::new())
+    }
+
+    /// The type assigned to the variable, if any. It may contain other variables
+    pub fn get(&self, var: TypeVarId) -> Option<&RustType> {
+        self.0.get(&var)
+    }
+
+    /// Assign the variable. Returns the previous assignment
+    pub fn insert(&mut self, var: TypeVarId, rust_type: RustType) -> Option<RustType> {
+        self.0.insert(var, rust_type)
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Replace every assigned variable in the type (transitively), including in type names,
+    /// and infer the layout of types whose layout was unknown because of a variable.
+    pub fn apply(&self, rust_type: &mut RustType) {
+        if let TypeStructure::Var(var) = &rust_type.structure {
+            if let Some(assigned) = self.get(*var) {
+                *rust_type = assigned.clone();
+                self.apply(rust_type);
+            }
+            return;
+        }
+        self.apply_to_name(&mut rust_type.type_name);
+        self.apply_to_structure(&mut rust_type.structure);
+        if rust_type.type_id.is_none() {
+            if rust_type.size == usize::MAX {
+                rust_type.size = rust_type.structure.infer_size().unwrap_or(usize::MAX);
+            }
+            if rust_type.align == usize::MAX {
+                rust_type.align = rust_type.structure.infer_align().unwrap_or(usize::MAX);
+            }
+        }
+    }
+
+    fn apply_to_structure(&self, structure: &mut TypeStructure) {
+        match structure {
+            TypeStructure::Var(_) => unreachable!("variable structures are substituted with their type"),
+            TypeStructure::Opaque | TypeStructure::Primitive(_) => {}
+            TypeStructure::OpaqueTuple { elements } | TypeStructure::CTuple { elements } => {
+                for element in elements {
+                    self.apply(element);
+                }
+            }
+            TypeStructure::OpaqueFields { fields } => {
+                for field in fields {
+                    self.apply(&mut field.rust_type);
+                }
+            }
+            TypeStructure::CReprEnum { variants } => {
+                for TypeEnumVariant { variant_name: _, body } in variants {
+                    self.apply_to_body(body);
+                }
+            }
+            TypeStructure::CReprStruct { body } => self.apply_to_body(body),
+            TypeStructure::Array { elem, length: _ } | TypeStructure::Slice { elem } => self.apply(elem),
+            TypeStructure::Pointer { refd_name, .. } => self.apply_to_name(refd_name)
+        }
+    }
+
+    fn apply_to_body(&self, body: &mut TypeStructureBody) {
+        match body {
+            TypeStructureBody::None => {}
+            TypeStructureBody::Tuple(elements) => {
+                for element in elements {
+                    self.apply(element);
+                }
+            }
+            TypeStructureBody::Fields(fields) => {
+                for field in fields {
+                    self.apply(&mut field.rust_type);
+                }
+            }
+        }
+    }
+
+    fn apply_to_name(&self, type_name: &mut RustTypeName) {
+        match type_name {
+            RustTypeName::Var { var } => {
+                if let Some(assigned) = self.0.get(var) {
+                    *type_name = assigned.type_name.clone();
+                    self.apply_to_name(type_name);
+                }
+            }
+            RustTypeName::Ident { generic_args: elems, .. } | RustTypeName::Tuple { elems } => {
+                for elem in elems {
+                    self.apply_to_name(elem);
+                }
+            }
+            RustTypeName::Pointer { refd: elem, .. } |
+            RustTypeName::Array { elem, .. } |
+            RustTypeName::Slice { elem } => self.apply_to_name(elem),
+            RustTypeName::Anonymous { .. } | RustTypeName::ConstExpr { .. } => {}
+        }
+    }
+}
+
+impl InferenceContext {
+    pub fn new() -> Self {
+        InferenceContext::default()
+    }
+
+    /// Create a new type variable, and return its type ([RustType::var])
+    pub fn fresh_var(&mut self) -> RustType {
+        let var = TypeVarId(self.next_var);
+        self.next_var += 1;
+        RustType::var(var)
+    }
+
+    /// Require `sub` to be a subtype of `sup`
+    pub fn constrain_subtype(&mut self, sub: RustType, sup: RustType) {
+        self.constraints.push((sub, sup));
+    }
+
+    /// Require `a` and `b` to be the same type (each is a subtype of the other)
+    pub fn constrain_equal(&mut self, a: RustType, b: RustType) {
+        self.constraints.push((a.clone(), b.clone()));
+        self.constraints.push((b, a));
+    }
+
+    /// Variable assignments found so far by [InferenceContext::solve]
+    pub fn substitution(&self) -> &Substitution {
+        &self.substitution
+    }
+
+    /// Assign variables so that the constraints hold (see [InferenceContext] for how).
+    /// Can be called again after adding more constraints.
+    pub fn solve(&mut self) -> Result<(), InferenceError> {
+        loop {
+            let mut var_bounds = BTreeMap::<TypeVarId, VarBounds>::new();
+            let mut same_vars = Vec::new();
+            for (sub, sup) in &self.constraints {
+                let mut sub = sub.clone();
+                let mut sup = sup.clone();
+                self.substitution.apply(&mut sub);
+                self.substitution.apply(&mut sup);
+                decompose(sub, sup, &mut var_bounds, &mut same_vars);
+            }
+
+            if let Some((var, other_var)) = same_vars.into_iter().next() {
+                self.substitution.insert(var, RustType::var(other_var));
+                continue;
+            }
+            if var_bounds.is_empty() {
+                break;
+            }
+            for (var, bounds) in var_bounds {
+                let solution = match bounds.upper.split_first() {
+                    Some((first, upper)) => upper.iter().fold(first.clone(), |solution, upper| solution.meet(upper)),
+                    None => bounds.lower.iter().fold(RustType::bottom(), |solution, lower| solution.join(lower))
+                };
+                // Other variables in the solution may have been assigned this round
+                let mut solution = solution;
+                self.substitution.apply(&mut solution);
+                let mut occurs = false;
+                solution.visit_vars(&mut |solution_var| occurs |= solution_var == var);
+                if occurs {
+                    return Err(InferenceError::InfiniteType { var, type_name: solution.type_name });
+                }
+                self.substitution.insert(var, solution);
+            }
+        }
+
+        for (sub, sup) in &self.constraints {
+            let sub = self.resolve_partially(sub);
+            let sup = self.resolve_partially(sup);
+            let report = sub.explain_subtype(&sup);
+            if report.result == IsSubtypeOf::No {
+                return Err(InferenceError::Mismatch {
+                    sub: Box::new(sub.type_name),
+                    sup: Box::new(sup.type_name),
+                    report: Box::new(report)
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Apply the substitution to the type, failing if any variables are still unassigned.
+    /// Call after [InferenceContext::solve].
+    pub fn resolve(&self, rust_type: &RustType) -> Result<RustType, InferenceError> {
+        let resolved = self.resolve_partially(rust_type);
+        let mut unresolved = None;
+        resolved.visit_vars(&mut |var| { unresolved.get_or_insert(var); });
+        match unresolved {
+            None => Ok(resolved),
+            Some(var) => Err(InferenceError::Unresolved { var })
+        }
+    }
+
+    /// Apply the substitution to the type, leaving unassigned variables
+    pub fn resolve_partially(&self, rust_type: &RustType) -> RustType {
+        let mut resolved = rust_type.clone();
+        self.substitution.apply(&mut resolved);
+        resolved
+    }
+}
+
+/// Add bounds on the variables in `sub` and `sup` so that `sub` is a subtype of `sup`.
+/// Pairs of variables which must be the same are added to `same_vars`.
+fn decompose(sub: RustType, sup: RustType, var_bounds: &mut BTreeMap<TypeVarId, VarBounds>, same_vars: &mut Vec<(TypeVarId, TypeVarId)>) {
+    match (sub.structure, sup.structure) {
+        (TypeStructure::Var(var), TypeStructure::Var(other_var)) => {
+            if var != other_var {
+                same_vars.push((var, other_var));
+            }
+        }
+        (TypeStructure::Var(var), structure) => {
+            var_bounds.entry(var).or_default().upper.push(RustType { structure, ..sup });
+        }
+        (structure, TypeStructure::Var(var)) => {
+            var_bounds.entry(var).or_default().lower.push(RustType { structure, ..sub });
+        }
+        (TypeStructure::OpaqueTuple { elements }, TypeStructure::OpaqueTuple { elements: other_elements }) |
+        (TypeStructure::OpaqueTuple { elements }, TypeStructure::CTuple { elements: other_elements }) |
+        (TypeStructure::CTuple { elements }, TypeStructure::OpaqueTuple { elements: other_elements }) |
+        (TypeStructure::CTuple { elements }, TypeStructure::CTuple { elements: other_elements }) => {
+            decompose_tuple(elements, other_elements, var_bounds, same_vars);
+        }
+        (TypeStructure::OpaqueTuple { elements }, TypeStructure::CReprStruct { body: other_body }) => {
+            decompose_body(TypeStructureBody::Tuple(elements), other_body, var_bounds, same_vars);
+        }
+        (TypeStructure::CReprStruct { body }, TypeStructure::OpaqueTuple { elements: other_elements }) => {
+            decompose_body(body, TypeStructureBody::Tuple(other_elements), var_bounds, same_vars);
+        }
+        (TypeStructure::OpaqueFields { fields }, TypeStructure::OpaqueFields { fields: other_fields }) => {
+            decompose_body(TypeStructureBody::Fields(fields), TypeStructureBody::Fields(other_fields), var_bounds, same_vars);
+        }
+        (TypeStructure::OpaqueFields { fields }, TypeStructure::CReprStruct { body: other_body }) => {
+            decompose_body(TypeStructureBody::Fields(fields), other_body, var_bounds, same_vars);
+        }
+        (TypeStructure::CReprStruct { body }, TypeStructure::OpaqueFields { fields: other_fields }) => {
+            decompose_body(body, TypeStructureBody::Fields(other_fields), var_bounds, same_vars);
+        }
+        (TypeStructure::CReprStruct { body }, TypeStructure::CReprStruct { body: other_body }) => {
+            decompose_body(body, other_body, var_bounds, same_vars);
+        }
+        (TypeStructure::CReprEnum { variants }, TypeStructure::CReprEnum { variants: mut other_variants }) => {
+            for variant in variants {
+                if let Some(other_variant_idx) = other_variants.iter().position(|other_variant| variant.variant_name == other_variant.variant_name) {
+                    let other_variant = other_variants.remove(other_variant_idx);
+                    decompose_body(variant.body, other_variant.body, var_bounds, same_vars);
+                }
+            }
+        }
+        (TypeStructure::Array { elem, length: _ }, TypeStructure::Array { elem: other_elem, length: _ }) |
+        (TypeStructure::Array { elem, length: _ }, TypeStructure::Slice { elem: other_elem }) |
+        (TypeStructure::Slice { elem }, TypeStructure::Slice { elem: other_elem }) => {
+            decompose(*elem, *other_elem, var_bounds, same_vars);
+        }
+        (TypeStructure::Pointer { refd_name, .. }, TypeStructure::Pointer { refd_name: other_refd_name, .. }) => {
+            decompose_pointee(refd_name, other_refd_name, var_bounds, same_vars);
+        }
+        // Other structures have no nested types, or definitely aren't subtypes (checked after solving)
+        _ => {}
+    }
+}
+
+/// Pointees are invariant, so a variable pointee is the same as the other pointee.
+/// We only know the other pointee's type if it's registered
+fn decompose_pointee(refd_name: RustTypeName, other_refd_name: RustTypeName, var_bounds: &mut BTreeMap<TypeVarId, VarBounds>, same_vars: &mut Vec<(TypeVarId, TypeVarId)>) {
+    match (refd_name, other_refd_name) {
+        (RustTypeName::Var { var }, RustTypeName::Var { var: other_var }) => {
+            if var != other_var {
+                same_vars.push((var, other_var));
+            }
+        }
+        (RustTypeName::Var { var }, refd_name) | (refd_name, RustTypeName::Var { var }) => {
+            if let Some(refd) = lookup_pointee(&refd_name) {
+                let bounds = var_bounds.entry(var).or_default();
+                bounds.upper.push(refd.clone());
+                bounds.lower.push(refd);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Primitives aren't registered, so they're resolved by name first
+fn lookup_pointee(refd_name: &RustTypeName) -> Option<RustType> {
+    if let RustTypeName::Ident { qualifier, simple_name, generic_args } = refd_name {
+        if qualifier.is_local() && generic_args.is_empty() {
+            if let Some(primitive) = PrimitiveType::from_name(simple_name) {
+                return Some(primitive.rust_type());
+            }
+        }
+    }
+    #[cfg(feature = "registry")]
+    if let Some(refd) = RustType::lookup(refd_name) {
+        return Some(RustType::clone(&refd));
+    }
+    None
+}
+
+fn decompose_body(body: TypeStructureBody, other_body: TypeStructureBody, var_bounds: &mut BTreeMap<TypeVarId, VarBounds>, same_vars: &mut Vec<(TypeVarId, TypeVarId)>) {
+    match (body, other_body) {
+        (TypeStructureBody::Tuple(elements), TypeStructureBody::Tuple(other_elements)) => {
+            decompose_tuple(elements, other_elements, var_bounds, same_vars);
+        }
+        (TypeStructureBody::Fields(fields), TypeStructureBody::Fields(mut other_fields)) => {
+            for field in fields {
+                if let Some(other_field_idx) = other_fields.iter().position(|other_field| field.name == other_field.name) {
+                    let other_field = other_fields.remove(other_field_idx);
+                    decompose(field.rust_type, other_field.rust_type, var_bounds, same_vars);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn decompose_tuple(elements: Vec<RustType>, other_elements: Vec<RustType>, var_bounds: &mut BTreeMap<TypeVarId, VarBounds>, same_vars: &mut Vec<(TypeVarId, TypeVarId)>) {
+    if elements.len() == other_elements.len() {
+        for (element, other_element) in zip(elements, other_elements) {
+            decompose(element, other_element, var_bounds, same_vars);
+        }
+    }
+}
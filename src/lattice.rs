@@ -0,0 +1,352 @@
+use std::borrow::Cow;
+use std::iter::zip;
+use crate::{IsSubtypeOf, RustPointerKind, RustType, RustTypeName, TypeEnumVariant, TypeStructure, TypeStructureBody, TypeStructureBodyField};
+
+impl RustType {
+    /// **Meet** (greatest lower bound): the most general type which is a subtype of both `self` and `other`.
+    /// Unlike [RustType::unify] this is symmetric (up to field and variant order).
+    ///
+    /// - If either type is bottom, or both have different type ids and no structural meet exists, returns [RustType::bottom]
+    /// - If either type is unknown, returns the other
+    /// - If one type is a subtype of the other, returns it
+    /// - Otherwise, if either type is opaque (but not unknown), returns [RustType::bottom]
+    /// - Otherwise returns an anonymous type without a type id whose structure is [TypeStructure::meet]
+    pub fn meet(&self, other: &RustType) -> RustType {
+        if self.type_name.is_bottom() || other.type_name.is_bottom() {
+            return RustType::bottom();
+        }
+        if self.type_name.is_unknown() {
+            return other.clone();
+        }
+        if other.type_name.is_unknown() || self.is_rough_subtype_of(other) == IsSubtypeOf::Yes {
+            return self.clone();
+        }
+        if other.is_rough_subtype_of(self) == IsSubtypeOf::Yes {
+            return other.clone();
+        }
+        // A named opaque type (e.g. `String`) is only a supertype of itself, not of everything
+        if matches!(self.structure, TypeStructure::Opaque) || matches!(other.structure, TypeStructure::Opaque) {
+            return RustType::bottom();
+        }
+        match self.structure.meet(&other.structure) {
+            None => RustType::bottom(),
+            Some(structure) => bound_type(self, other, "&", structure)
+        }
+    }
+
+    /// **Join** (least upper bound): the most specific type which is a supertype of both `self` and `other`.
+    /// Unlike [RustType::unify] this is symmetric (up to field and variant order).
+    ///
+    /// - If either type is unknown, returns [RustType::unknown] (the opaque top)
+    /// - If either type is bottom, returns the other
+    /// - If one type is a subtype of the other, returns the other
+    /// - Otherwise returns an anonymous type without a type id whose structure is [TypeStructure::join],
+    ///   or [RustType::unknown] if that is opaque
+    pub fn join(&self, other: &RustType) -> RustType {
+        if self.type_name.is_unknown() || other.type_name.is_unknown() {
+            return RustType::unknown();
+        }
+        if self.type_name.is_bottom() {
+            return other.clone();
+        }
+        if other.type_name.is_bottom() || other.is_rough_subtype_of(self) == IsSubtypeOf::Yes {
+            return self.clone();
+        }
+        if self.is_rough_subtype_of(other) == IsSubtypeOf::Yes {
+            return other.clone();
+        }
+        match self.structure.join(&other.structure) {
+            TypeStructure::Opaque => RustType::unknown(),
+            structure => bound_type(self, other, "|", structure)
+        }
+    }
+}
+
+impl TypeStructure {
+    /// **Meet** (greatest lower bound) of the structures, see [TypeStructure::is_structural_subtype_of]:
+    /// field structures get the union of fields, enums the intersection of variants
+    /// (variants whose bodies have no meet are dropped), and everything else is met element-wise.
+    /// An opaque structure or type variable is treated as top.
+    ///
+    /// Returns `None` if the only common subtype is bottom
+    /// (e.g. different primitives, different tuple lengths, or enums without common variants).
+    /// Structures and arrays with a bottom element are also bottom, since they are uninhabited.
+    pub fn meet(&self, other: &TypeStructure) -> Option<TypeStructure> {
+        match (self, other) {
+            (TypeStructure::Opaque, other) => Some(other.clone()),
+            (this, TypeStructure::Opaque) => Some(this.clone()),
+            (TypeStructure::Var(_), other) => Some(other.clone()),
+            (this, TypeStructure::Var(_)) => Some(this.clone()),
+            (TypeStructure::OpaqueTuple { elements }, TypeStructure::OpaqueTuple { elements: other_elements }) => {
+                meet_tuple(elements, other_elements).map(|elements| TypeStructure::OpaqueTuple { elements })
+            }
+            (TypeStructure::OpaqueTuple { elements }, TypeStructure::CTuple { elements: other_elements }) |
+            (TypeStructure::CTuple { elements }, TypeStructure::OpaqueTuple { elements: other_elements }) |
+            (TypeStructure::CTuple { elements }, TypeStructure::CTuple { elements: other_elements }) => {
+                meet_tuple(elements, other_elements).map(|elements| TypeStructure::CTuple { elements })
+            }
+            (TypeStructure::OpaqueTuple { elements }, TypeStructure::CReprStruct { body }) |
+            (TypeStructure::CReprStruct { body }, TypeStructure::OpaqueTuple { elements }) => match body {
+                TypeStructureBody::Tuple(body_elements) => meet_tuple(body_elements, elements)
+                    .map(|elements| TypeStructure::CReprStruct { body: TypeStructureBody::Tuple(elements) }),
+                _ => None
+            },
+            (TypeStructure::OpaqueFields { fields }, TypeStructure::OpaqueFields { fields: other_fields }) => {
+                meet_fields(fields, other_fields).map(|fields| TypeStructure::OpaqueFields { fields })
+            }
+            (TypeStructure::OpaqueFields { fields }, TypeStructure::CReprStruct { body }) |
+            (TypeStructure::CReprStruct { body }, TypeStructure::OpaqueFields { fields }) => match body {
+                TypeStructureBody::Fields(body_fields) => meet_fields(body_fields, fields)
+                    .map(|fields| TypeStructure::CReprStruct { body: TypeStructureBody::Fields(fields) }),
+                _ => None
+            },
+            (TypeStructure::Primitive(primitive), TypeStructure::Primitive(other_primitive)) => {
+                (primitive == other_primitive).then_some(TypeStructure::Primitive(*primitive))
+            }
+            (TypeStructure::CReprEnum { variants }, TypeStructure::CReprEnum { variants: other_variants }) => {
+                let met_variants = variants.iter().filter_map(|variant| {
+                    let other_variant = other_variants.iter().find(|other_variant| variant.variant_name == other_variant.variant_name)?;
+                    Some(TypeEnumVariant {
+                        variant_name: variant.variant_name.clone(),
+                        body: variant.body.meet(&other_variant.body)?
+                    })
+                }).collect::<Vec<_>>();
+                if met_variants.is_empty() && !variants.is_empty() && !other_variants.is_empty() {
+                    None
+                } else {
+                    Some(TypeStructure::CReprEnum { variants: met_variants })
+                }
+            }
+            (TypeStructure::CReprStruct { body }, TypeStructure::CReprStruct { body: other_body }) => {
+                body.meet(other_body).map(|body| TypeStructure::CReprStruct { body })
+            }
+            (TypeStructure::Pointer { ptr_kind, ptr_size, refd_id, refd_name }, TypeStructure::Pointer { ptr_kind: other_ptr_kind, ptr_size: other_ptr_size, refd_id: other_refd_id, refd_name: other_refd_name }) => {
+                let refd_equal = match (refd_id, other_refd_id) {
+                    (Some(refd_id), Some(other_refd_id)) => refd_id == other_refd_id,
+                    _ => refd_name == other_refd_name
+                };
+                (refd_equal && ptr_size == other_ptr_size).then(|| TypeStructure::Pointer {
+                    ptr_kind: ptr_kind.meet(*other_ptr_kind),
+                    ptr_size: *ptr_size,
+                    refd_id: refd_id.or(*other_refd_id),
+                    refd_name: refd_name.clone()
+                })
+            }
+            (TypeStructure::Array { elem, length }, TypeStructure::Array { elem: other_elem, length: other_length }) => {
+                if length != other_length {
+                    return None;
+                }
+                let elem = meet_non_bottom(elem, other_elem)?;
+                Some(TypeStructure::Array { elem: Box::new(elem), length: *length })
+            }
+            (TypeStructure::Array { elem, length }, TypeStructure::Slice { elem: other_elem }) |
+            (TypeStructure::Slice { elem: other_elem }, TypeStructure::Array { elem, length }) => {
+                let elem = meet_non_bottom(elem, other_elem)?;
+                Some(TypeStructure::Array { elem: Box::new(elem), length: *length })
+            }
+            (TypeStructure::Slice { elem }, TypeStructure::Slice { elem: other_elem }) => {
+                Some(TypeStructure::Slice { elem: Box::new(elem.meet(other_elem)) })
+            }
+            _ => None
+        }
+    }
+
+    /// **Join** (least upper bound) of the structures, see [TypeStructure::is_structural_subtype_of]:
+    /// field structures get the intersection of fields, enums the union of variants,
+    /// arrays of different lengths become slices, and everything else is joined element-wise.
+    ///
+    /// Returns [TypeStructure::Opaque] (top) if there is no other common supertype.
+    pub fn join(&self, other: &TypeStructure) -> TypeStructure {
+        let joined = match (self, other) {
+            (TypeStructure::Opaque, _) | (_, TypeStructure::Opaque) => None,
+            (TypeStructure::Var(var), TypeStructure::Var(other_var)) if var == other_var => Some(TypeStructure::Var(*var)),
+            (TypeStructure::OpaqueTuple { elements }, TypeStructure::OpaqueTuple { elements: other_elements }) |
+            (TypeStructure::OpaqueTuple { elements }, TypeStructure::CTuple { elements: other_elements }) |
+            (TypeStructure::CTuple { elements }, TypeStructure::OpaqueTuple { elements: other_elements }) => {
+                join_tuple(elements, other_elements).map(|elements| TypeStructure::OpaqueTuple { elements })
+            }
+            (TypeStructure::CTuple { elements }, TypeStructure::CTuple { elements: other_elements }) => {
+                join_tuple(elements, other_elements).map(|elements| TypeStructure::CTuple { elements })
+            }
+            (TypeStructure::OpaqueTuple { elements }, TypeStructure::CReprStruct { body: TypeStructureBody::Tuple(other_elements) }) |
+            (TypeStructure::CReprStruct { body: TypeStructureBody::Tuple(elements) }, TypeStructure::OpaqueTuple { elements: other_elements }) => {
+                join_tuple(elements, other_elements).map(|elements| TypeStructure::OpaqueTuple { elements })
+            }
+            (TypeStructure::OpaqueFields { fields }, TypeStructure::OpaqueFields { fields: other_fields }) |
+            (TypeStructure::OpaqueFields { fields }, TypeStructure::CReprStruct { body: TypeStructureBody::Fields(other_fields) }) |
+            (TypeStructure::CReprStruct { body: TypeStructureBody::Fields(fields) }, TypeStructure::OpaqueFields { fields: other_fields }) => {
+                Some(TypeStructure::OpaqueFields { fields: join_fields(fields, other_fields) })
+            }
+            (TypeStructure::Primitive(primitive), TypeStructure::Primitive(other_primitive)) => {
+                (primitive == other_primitive).then_some(TypeStructure::Primitive(*primitive))
+            }
+            (TypeStructure::CReprEnum { variants }, TypeStructure::CReprEnum { variants: other_variants }) => {
+                let mut joined_variants = Vec::with_capacity(variants.len() + other_variants.len());
+                let mut bodies_join = true;
+                for variant in variants {
+                    let body = match other_variants.iter().find(|other_variant| variant.variant_name == other_variant.variant_name) {
+                        None => Some(variant.body.clone()),
+                        Some(other_variant) => variant.body.join(&other_variant.body)
+                    };
+                    match body {
+                        None => bodies_join = false,
+                        Some(body) => joined_variants.push(TypeEnumVariant {
+                            variant_name: variant.variant_name.clone(),
+                            body
+                        })
+                    }
+                }
+                joined_variants.extend(other_variants.iter()
+                    .filter(|other_variant| !variants.iter().any(|variant| variant.variant_name == other_variant.variant_name))
+                    .cloned());
+                bodies_join.then_some(TypeStructure::CReprEnum { variants: joined_variants })
+            }
+            (TypeStructure::CReprStruct { body }, TypeStructure::CReprStruct { body: other_body }) => {
+                body.join(other_body).map(|body| TypeStructure::CReprStruct { body })
+            }
+            (TypeStructure::Pointer { ptr_kind, ptr_size, refd_id, refd_name }, TypeStructure::Pointer { ptr_kind: other_ptr_kind, ptr_size: other_ptr_size, refd_id: other_refd_id, refd_name: other_refd_name }) => {
+                let refd_equal = match (refd_id, other_refd_id) {
+                    (Some(refd_id), Some(other_refd_id)) => refd_id == other_refd_id,
+                    _ => refd_name == other_refd_name
+                };
+                (refd_equal && ptr_size == other_ptr_size).then(|| TypeStructure::Pointer {
+                    ptr_kind: ptr_kind.join(*other_ptr_kind),
+                    ptr_size: *ptr_size,
+                    refd_id: refd_id.or(*other_refd_id),
+                    refd_name: refd_name.clone()
+                })
+            }
+            (TypeStructure::Array { elem, length }, TypeStructure::Array { elem: other_elem, length: other_length }) => {
+                let elem = Box::new(elem.join(other_elem));
+                Some(if length == other_length {
+                    TypeStructure::Array { elem, length: *length }
+                } else {
+                    TypeStructure::Slice { elem }
+                })
+            }
+            (TypeStructure::Array { elem, length: _ }, TypeStructure::Slice { elem: other_elem }) |
+            (TypeStructure::Slice { elem }, TypeStructure::Array { elem: other_elem, length: _ }) |
+            (TypeStructure::Slice { elem }, TypeStructure::Slice { elem: other_elem }) => {
+                Some(TypeStructure::Slice { elem: Box::new(elem.join(other_elem)) })
+            }
+            _ => None
+        };
+        joined.unwrap_or(TypeStructure::Opaque)
+    }
+}
+
+impl TypeStructureBody {
+    fn meet(&self, other: &TypeStructureBody) -> Option<TypeStructureBody> {
+        match (self, other) {
+            (TypeStructureBody::None, TypeStructureBody::None) => Some(TypeStructureBody::None),
+            (TypeStructureBody::Tuple(elements), TypeStructureBody::Tuple(other_elements)) => {
+                meet_tuple(elements, other_elements).map(TypeStructureBody::Tuple)
+            }
+            (TypeStructureBody::Fields(fields), TypeStructureBody::Fields(other_fields)) => {
+                meet_fields(fields, other_fields).map(TypeStructureBody::Fields)
+            }
+            _ => None
+        }
+    }
+
+    fn join(&self, other: &TypeStructureBody) -> Option<TypeStructureBody> {
+        match (self, other) {
+            (TypeStructureBody::None, TypeStructureBody::None) => Some(TypeStructureBody::None),
+            (TypeStructureBody::Tuple(elements), TypeStructureBody::Tuple(other_elements)) => {
+                join_tuple(elements, other_elements).map(TypeStructureBody::Tuple)
+            }
+            (TypeStructureBody::Fields(fields), TypeStructureBody::Fields(other_fields)) => {
+                Some(TypeStructureBody::Fields(join_fields(fields, other_fields)))
+            }
+            _ => None
+        }
+    }
+}
+
+impl RustPointerKind {
+    const ALL: [RustPointerKind; 4] = [RustPointerKind::ImmRef, RustPointerKind::MutRef, RustPointerKind::ImmRaw, RustPointerKind::MutRaw];
+
+    /// Greatest pointer kind which is a subtype of both
+    fn meet(self, other: RustPointerKind) -> RustPointerKind {
+        let lower_bounds = RustPointerKind::ALL.into_iter()
+            .filter(|kind| kind.is_subtype_of(&self) && kind.is_subtype_of(&other))
+            .collect::<Vec<_>>();
+        *lower_bounds.iter()
+            .find(|kind| lower_bounds.iter().all(|lower_bound| lower_bound.is_subtype_of(kind)))
+            .expect("pointer kinds form a lattice")
+    }
+
+    /// Least pointer kind which is a supertype of both
+    fn join(self, other: RustPointerKind) -> RustPointerKind {
+        let upper_bounds = RustPointerKind::ALL.into_iter()
+            .filter(|kind| self.is_subtype_of(kind) && other.is_subtype_of(kind))
+            .collect::<Vec<_>>();
+        *upper_bounds.iter()
+            .find(|kind| upper_bounds.iter().all(|upper_bound| kind.is_subtype_of(upper_bound)))
+            .expect("pointer kinds form a lattice")
+    }
+}
+
+/// Meet, or `None` if it's bottom
+fn meet_non_bottom(this: &RustType, other: &RustType) -> Option<RustType> {
+    let meet = this.meet(other);
+    (!meet.type_name.is_bottom()).then_some(meet)
+}
+
+fn meet_tuple(elements: &[RustType], other_elements: &[RustType]) -> Option<Vec<RustType>> {
+    if elements.len() != other_elements.len() {
+        return None;
+    }
+    zip(elements, other_elements).map(|(element, other_element)| meet_non_bottom(element, other_element)).collect()
+}
+
+fn join_tuple(elements: &[RustType], other_elements: &[RustType]) -> Option<Vec<RustType>> {
+    (elements.len() == other_elements.len()).then(|| {
+        zip(elements, other_elements).map(|(element, other_element)| element.join(other_element)).collect()
+    })
+}
+
+/// Union of fields: shared fields are met, and `other`'s extra fields go after `fields`
+fn meet_fields(fields: &[TypeStructureBodyField], other_fields: &[TypeStructureBodyField]) -> Option<Vec<TypeStructureBodyField>> {
+    let mut met_fields = fields.iter().map(|field| Some(TypeStructureBodyField {
+        name: field.name.clone(),
+        rust_type: match other_fields.iter().find(|other_field| field.name == other_field.name) {
+            None => field.rust_type.clone(),
+            Some(other_field) => meet_non_bottom(&field.rust_type, &other_field.rust_type)?
+        }
+    })).collect::<Option<Vec<_>>>()?;
+    met_fields.extend(other_fields.iter()
+        .filter(|other_field| !fields.iter().any(|field| field.name == other_field.name))
+        .cloned());
+    Some(met_fields)
+}
+
+/// Intersection of fields, in `fields`' order
+fn join_fields(fields: &[TypeStructureBodyField], other_fields: &[TypeStructureBodyField]) -> Vec<TypeStructureBodyField> {
+    fields.iter().filter_map(|field| {
+        let other_field = other_fields.iter().find(|other_field| field.name == other_field.name)?;
+        Some(TypeStructureBodyField {
+            name: field.name.clone(),
+            rust_type: field.rust_type.join(&other_field.rust_type)
+        })
+    }).collect()
+}
+
+/// Type of a meet or join which isn't one of the operands: keeps the name if both are the same,
+/// otherwise it's anonymous (e.g. `A & B`)
+fn bound_type(this: &RustType, other: &RustType, operator: &str, structure: TypeStructure) -> RustType {
+    let type_name = if this.type_name == other.type_name {
+        this.type_name.clone()
+    } else {
+        RustTypeName::Anonymous {
+            desc: Cow::Owned(format!("{} {} {}", this.type_name.qualified(), operator, other.type_name.qualified()))
+        }
+    };
+    RustType {
+        type_id: None,
+        type_name,
+        size: structure.infer_size().unwrap_or(usize::MAX),
+        align: structure.infer_align().unwrap_or(usize::MAX),
+        structure
+    }
+}
@@ -0,0 +1,248 @@
+use std::fmt::{Display, Formatter};
+use std::iter::zip;
+use crate::{discriminant_size, infer_c_tuple_elem_offsets, infer_enum_payload_offset, infer_slice_offsets, PrimitiveType, RustPointerKind, RustType, RustTypeName, TypePath, TypePathElem, TypeStructure, TypeStructureBody};
+
+/// Whether a value of one type can be reinterpreted (transmuted) as a value of another.
+/// Returned by [RustType::is_layout_compatible_with]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LayoutCompat {
+    /// Same size, alignment, and bytes: the types can be transmuted into each other
+    Identical,
+    /// The layout starts with the other type's layout and is at least as aligned:
+    /// a pointer to this type can be reinterpreted as a pointer to the other
+    PrefixCompatible,
+    /// Reinterpreting is unsafe (or we don't know the layout)
+    Incompatible {
+        /// Path to the incompatible part, which is the same in both types
+        /// (fields and variants are compared by position, so they are [TypePathElem::Index] and [TypePathElem::Variant] of `self`)
+        path: TypePath,
+        reason: LayoutIncompatibility
+    }
+}
+
+/// Why a type's layout isn't compatible with another's.
+/// `self` refers to the type being reinterpreted, `other` to the type it's reinterpreted as.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LayoutIncompatibility {
+    /// One of the types is opaque (including opaque tuples and fields, whose fields may be reordered), unsized, or a type variable
+    UnknownLayout { type_name: RustTypeName },
+    /// `self` is smaller than `other`
+    Size { size: usize, other_size: usize },
+    /// `self` is less aligned than `other`
+    Align { align: usize, other_align: usize },
+    /// Different kinds of structures (e.g. a primitive and a pointer)
+    KindMismatch { kind: &'static str, other_kind: &'static str },
+    /// Different primitives
+    PrimitiveMismatch { primitive: PrimitiveType, other_primitive: PrimitiveType },
+    /// `other` has a field or element which `self` doesn't
+    MissingElement { index: usize },
+    /// The field or element is at a different offset
+    Offset { index: usize, offset: usize, other_offset: usize },
+    /// Array or slice elements have different layouts, so the stride or contents differ
+    ElemNotIdentical,
+    /// `self` can't be reinterpreted as a pointer of `other`'s kind (e.g. `&T` as `&mut T`)
+    PointerKind { ptr_kind: RustPointerKind, other_ptr_kind: RustPointerKind },
+    /// Pointers have different sizes (e.g. one is thin and the other is fat)
+    PointerSize { ptr_size: usize, other_ptr_size: usize },
+    /// Pointers point to different types
+    PointeeMismatch { refd_name: RustTypeName, other_refd_name: RustTypeName },
+    /// Enum discriminants have different sizes
+    DiscriminantSize { discriminant_size: usize, other_discriminant_size: usize },
+    /// `self` has more enum variants, so some of its discriminants are invalid in `other`
+    ExtraVariants { num_variants: usize, other_num_variants: usize },
+    /// Enum variant payloads start at different offsets
+    PayloadOffset { payload_offset: usize, other_payload_offset: usize }
+}
+
+impl RustType {
+    /// Checks if a value of this type can be reinterpreted as a value of `other` by comparing
+    /// sizes, alignments, field offsets, primitives, pointer kinds, and enum discriminants.
+    ///
+    /// Unlike subtyping, fields and variants are compared by *position*, not name.
+    /// Types with the same type id, size, alignment, and structure are identical,
+    /// and types with unknown layouts are incompatible.
+    pub fn is_layout_compatible_with(&self, other: &RustType) -> LayoutCompat {
+        let mut path = TypePath::empty();
+        match layout_compat(self, other, &mut path) {
+            Ok(true) => LayoutCompat::Identical,
+            Ok(false) => LayoutCompat::PrefixCompatible,
+            Err(reason) => LayoutCompat::Incompatible { path, reason: *reason }
+        }
+    }
+}
+
+impl LayoutCompat {
+    /// Is [LayoutCompat::Identical] or [LayoutCompat::PrefixCompatible]?
+    pub fn is_compatible(&self) -> bool {
+        !matches!(self, LayoutCompat::Incompatible { .. })
+    }
+}
+
+/// Returns whether the layouts are identical, or why they are incompatible.
+/// On error, `path` is the path to the incompatible part
+fn layout_compat(this: &RustType, other: &RustType, path: &mut TypePath) -> Result<bool, Box<LayoutIncompatibility>> {
+    // Anyone can construct a type with any id, so the id alone doesn't mean the layout is the same
+    if this.type_id.is_some() && this.type_id == other.type_id && this.size == other.size && this.align == other.align && this.structure == other.structure {
+        return Ok(true);
+    }
+    if let (TypeStructure::Slice { elem }, TypeStructure::Slice { elem: other_elem }) = (&this.structure, &other.structure) {
+        // Length is in the pointer metadata
+        path.push(TypePathElem::Elem);
+        let identical = layout_compat(elem, other_elem, path)?;
+        path.pop();
+        return if identical { Ok(true) } else { Err(Box::new(LayoutIncompatibility::ElemNotIdentical)) };
+    }
+    for rust_type in [this, other] {
+        if rust_type.size == usize::MAX || rust_type.align == usize::MAX || matches!(rust_type.structure, TypeStructure::Opaque | TypeStructure::OpaqueTuple { .. } | TypeStructure::OpaqueFields { .. } | TypeStructure::Var(_)) {
+            return Err(Box::new(LayoutIncompatibility::UnknownLayout { type_name: rust_type.type_name.clone() }));
+        }
+    }
+    if this.size < other.size {
+        return Err(Box::new(LayoutIncompatibility::Size { size: this.size, other_size: other.size }));
+    }
+    if this.align < other.align {
+        return Err(Box::new(LayoutIncompatibility::Align { align: this.align, other_align: other.align }));
+    }
+    let same_size = this.size == other.size && this.align == other.align;
+
+    let structures_identical = match (&this.structure, &other.structure) {
+        (TypeStructure::Primitive(primitive), TypeStructure::Primitive(other_primitive)) => {
+            if primitive != other_primitive {
+                return Err(Box::new(LayoutIncompatibility::PrimitiveMismatch { primitive: *primitive, other_primitive: *other_primitive }));
+            }
+            true
+        }
+        (TypeStructure::Pointer { ptr_kind, ptr_size, refd_id, refd_name }, TypeStructure::Pointer { ptr_kind: other_ptr_kind, ptr_size: other_ptr_size, refd_id: other_refd_id, refd_name: other_refd_name }) => {
+            if !ptr_kind.can_reinterpret_as(other_ptr_kind) {
+                return Err(Box::new(LayoutIncompatibility::PointerKind { ptr_kind: *ptr_kind, other_ptr_kind: *other_ptr_kind }));
+            }
+            if ptr_size != other_ptr_size {
+                return Err(Box::new(LayoutIncompatibility::PointerSize { ptr_size: *ptr_size, other_ptr_size: *other_ptr_size }));
+            }
+            let refd_equal = match (refd_id, other_refd_id) {
+                (Some(refd_id), Some(other_refd_id)) => refd_id == other_refd_id,
+                _ => refd_name == other_refd_name
+            };
+            if !refd_equal {
+                return Err(Box::new(LayoutIncompatibility::PointeeMismatch { refd_name: refd_name.clone(), other_refd_name: other_refd_name.clone() }));
+            }
+            ptr_kind == other_ptr_kind
+        }
+        (TypeStructure::CReprEnum { variants }, TypeStructure::CReprEnum { variants: other_variants }) => {
+            let other_discriminant_size = discriminant_size(other_variants.len());
+            let discriminant_size = discriminant_size(variants.len());
+            if discriminant_size != other_discriminant_size {
+                return Err(Box::new(LayoutIncompatibility::DiscriminantSize { discriminant_size, other_discriminant_size }));
+            }
+            // Discriminants are assigned by position
+            if variants.len() > other_variants.len() {
+                return Err(Box::new(LayoutIncompatibility::ExtraVariants { num_variants: variants.len(), other_num_variants: other_variants.len() }));
+            }
+            let payload_offset = infer_enum_payload_offset(variants);
+            let other_payload_offset = infer_enum_payload_offset(other_variants);
+            if payload_offset != other_payload_offset && variants.iter().any(|variant| !matches!(variant.body, TypeStructureBody::None)) {
+                return Err(Box::new(LayoutIncompatibility::PayloadOffset { payload_offset, other_payload_offset }));
+            }
+            let mut identical = variants.len() == other_variants.len();
+            for (variant, other_variant) in zip(variants, other_variants) {
+                path.push(TypePathElem::Variant(variant.variant_name.clone()));
+                identical &= elems_layout_compat(&body_layout_elems(&variant.body), &body_layout_elems(&other_variant.body), path)?;
+                path.pop();
+            }
+            identical
+        }
+        (this_structure, other_structure) => match (layout_elems(this_structure), layout_elems(other_structure)) {
+            (Some(elems), Some(other_elems)) => elems_layout_compat(&elems, &other_elems, path)?,
+            _ => return Err(Box::new(LayoutIncompatibility::KindMismatch {
+                kind: this_structure.kind_name(),
+                other_kind: other_structure.kind_name()
+            }))
+        }
+    };
+    Ok(same_size && structures_identical)
+}
+
+/// Elements of `other` must be at the same offsets in `this`, with compatible layouts.
+/// Returns whether they're identical
+fn elems_layout_compat(elems: &[(usize, &RustType)], other_elems: &[(usize, &RustType)], path: &mut TypePath) -> Result<bool, Box<LayoutIncompatibility>> {
+    let mut identical = elems.len() == other_elems.len();
+    for (index, (other_offset, other_elem)) in other_elems.iter().enumerate() {
+        let Some((offset, elem)) = elems.get(index) else {
+            return Err(Box::new(LayoutIncompatibility::MissingElement { index }));
+        };
+        if offset != other_offset {
+            return Err(Box::new(LayoutIncompatibility::Offset { index, offset: *offset, other_offset: *other_offset }));
+        }
+        path.push(TypePathElem::Index(index));
+        identical &= layout_compat(elem, other_elem, path)?;
+        path.pop();
+    }
+    Ok(identical)
+}
+
+/// Offsets and types of the fields or elements, if the structure is a C struct, C tuple, or array
+fn layout_elems(structure: &TypeStructure) -> Option<Vec<(usize, &RustType)>> {
+    match structure {
+        TypeStructure::CTuple { elements } => Some(tuple_layout_elems(elements.iter())),
+        TypeStructure::CReprStruct { body } => Some(body_layout_elems(body)),
+        TypeStructure::Array { elem, length } => Some(if elem.size == 0 {
+            Vec::new()
+        } else {
+            zip(infer_slice_offsets(elem), std::iter::repeat(elem.as_ref())).take(*length).collect()
+        }),
+        _ => None
+    }
+}
+
+fn body_layout_elems(body: &TypeStructureBody) -> Vec<(usize, &RustType)> {
+    match body {
+        TypeStructureBody::None => Vec::new(),
+        TypeStructureBody::Tuple(elements) => tuple_layout_elems(elements.iter()),
+        TypeStructureBody::Fields(fields) => tuple_layout_elems(fields.iter().map(|field| &field.rust_type))
+    }
+}
+
+fn tuple_layout_elems<'a>(elems: impl Iterator<Item=&'a RustType> + Clone + 'a) -> Vec<(usize, &'a RustType)> {
+    zip(infer_c_tuple_elem_offsets(elems.clone()), elems).collect()
+}
+
+impl RustPointerKind {
+    /// Can a pointer of this kind be reinterpreted as a pointer of the other kind?
+    /// Mutable pointers can become immutable, and references can become raw pointers, but not vice versa.
+    fn can_reinterpret_as(&self, other: &RustPointerKind) -> bool {
+        let is_mut = |kind: &RustPointerKind| matches!(kind, RustPointerKind::MutRef | RustPointerKind::MutRaw);
+        let is_ref = |kind: &RustPointerKind| matches!(kind, RustPointerKind::ImmRef | RustPointerKind::MutRef);
+        (is_mut(self) || !is_mut(other)) && (is_ref(self) || !is_ref(other))
+    }
+}
+
+impl Display for LayoutCompat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LayoutCompat::Identical => write!(f, "identical layout"),
+            LayoutCompat::PrefixCompatible => write!(f, "prefix-compatible layout"),
+            LayoutCompat::Incompatible { path, reason } => write!(f, "incompatible layout at {}: {}", path, reason)
+        }
+    }
+}
+
+impl Display for LayoutIncompatibility {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LayoutIncompatibility::UnknownLayout { type_name } => write!(f, "layout of {} is unknown", type_name.qualified()),
+            LayoutIncompatibility::Size { size, other_size } => write!(f, "size {} is smaller than {}", size, other_size),
+            LayoutIncompatibility::Align { align, other_align } => write!(f, "alignment {} is smaller than {}", align, other_align),
+            LayoutIncompatibility::KindMismatch { kind, other_kind } => write!(f, "{} is not a {}", kind, other_kind),
+            LayoutIncompatibility::PrimitiveMismatch { primitive, other_primitive } => write!(f, "primitive {} is not {}", primitive, other_primitive),
+            LayoutIncompatibility::MissingElement { index } => write!(f, "missing element {}", index),
+            LayoutIncompatibility::Offset { index, offset, other_offset } => write!(f, "element {} is at offset {} but expected {}", index, offset, other_offset),
+            LayoutIncompatibility::ElemNotIdentical => write!(f, "elements don't have identical layouts"),
+            LayoutIncompatibility::PointerKind { ptr_kind, other_ptr_kind } => write!(f, "`{}` pointer can't be reinterpreted as a `{}` pointer", ptr_kind.to_string().trim_end(), other_ptr_kind.to_string().trim_end()),
+            LayoutIncompatibility::PointerSize { ptr_size, other_ptr_size } => write!(f, "pointer has size {} but expected {}", ptr_size, other_ptr_size),
+            LayoutIncompatibility::PointeeMismatch { refd_name, other_refd_name } => write!(f, "points to {} but expected {}", refd_name.qualified(), other_refd_name.qualified()),
+            LayoutIncompatibility::DiscriminantSize { discriminant_size, other_discriminant_size } => write!(f, "discriminant has size {} but expected {}", discriminant_size, other_discriminant_size),
+            LayoutIncompatibility::ExtraVariants { num_variants, other_num_variants } => write!(f, "has {} variants but expected at most {}", num_variants, other_num_variants),
+            LayoutIncompatibility::PayloadOffset { payload_offset, other_payload_offset } => write!(f, "variant data is at offset {} but expected {}", payload_offset, other_payload_offset)
+        }
+    }
+}
@@ -23,10 +23,37 @@ mod structure;
 mod primitive;
 #[cfg(feature = "registry")]
 mod registry;
+#[cfg(feature = "registry")]
+mod template;
+#[cfg(feature = "registry")]
+mod registry_search;
+#[cfg(feature = "registry")]
+mod registry_observer;
+#[cfg(feature = "persist")]
+mod persist;
+#[cfg(feature = "auto-register")]
+mod auto_register;
 mod subtype;
+mod subtype_report;
+mod subtype_cache;
+mod type_path;
+mod unify_conflict;
+mod lattice;
+mod infer;
+mod coerce;
+mod layout;
 mod size_align;
 mod index;
 mod intrinsic;
+mod value;
+mod dyn_value;
+mod value_debug;
+#[cfg(feature = "serde")]
+mod value_serialize;
+#[cfg(feature = "serde")]
+mod value_deserialize;
+#[cfg(feature = "serde")]
+mod type_serde;
 
 /// Miscellaneous helpers which may go into other crates in the future
 pub mod misc;
@@ -37,10 +64,31 @@ pub use structure::*;
 pub use primitive::*;
 #[cfg(feature = "registry")]
 pub use registry::*;
+#[cfg(feature = "registry")]
+pub use template::*;
+#[cfg(feature = "registry")]
+pub use registry_observer::*;
+#[cfg(feature = "persist")]
+pub use persist::*;
+#[cfg(feature = "auto-register")]
+pub use auto_register::*;
 pub use subtype::*;
+pub use subtype_report::*;
+pub use subtype_cache::*;
+pub use type_path::*;
+pub use unify_conflict::*;
+pub use infer::*;
+pub use coerce::*;
+pub use layout::*;
 pub use size_align::*;
 pub use index::*;
 pub use intrinsic::*;
+pub use value::*;
+pub use dyn_value::*;
+#[cfg(feature = "serde")]
+pub use value_serialize::*;
+#[cfg(feature = "serde")]
+pub use value_deserialize::*;
 
 /// Name, structure, type id, and layout info for a rust type.
 /// May not be an actual rust type, but a defined in another language or external dependency.
@@ -48,8 +96,13 @@ pub use intrinsic::*;
 /// Rust types are considered equal if both types have the same type id or name.
 /// To check for subtyping, use [RustType::is_rough_subtype_of] or [RustType::is_structural_subtype_of].
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct RustType {
     /// Corresponds to [TypeId::of]. Types in external libraries may not have ids.
+    ///
+    /// With the `serde` feature, this isn't serialized: on deserialization it's the id of the registered type
+    /// with the same name, if that type has the same layout.
+    #[cfg_attr(feature = "serde", serde(skip_serializing))]
     pub type_id: Option<TypeId>,
     /// Corresponds to [std::any::type_name], but more detailed and specified.
     pub type_name: RustTypeName,
@@ -0,0 +1,390 @@
+use std::any::TypeId;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::sync::Arc;
+use derive_more::{Display, Error};
+use serde::{Deserialize, Serialize};
+use crate::{PrimitiveType, RegistryConflict, RustPointerKind, RustType, RustTypeName, RustTypeNameParseError, TypeEnumVariant, TypeRegistry, TypeStructure, TypeStructureBody, TypeStructureBodyField};
+use crate::registry::log_conflict;
+
+/// Identifies registry files
+const REGISTRY_FORMAT: &str = "structural-reflection-registry";
+/// Version of the registry file format written by [TypeRegistry::export].
+/// Files with a newer version can't be imported.
+pub const REGISTRY_FORMAT_VERSION: u64 = 1;
+
+/// Error exporting or importing a registry file
+#[derive(Debug, Display, Error)]
+pub enum RegistryPersistError {
+    #[display(fmt = "io error: {}", _0)]
+    Io(#[error(source)] io::Error),
+    #[display(fmt = "malformed registry file: {}", _0)]
+    Json(#[error(source)] serde_json::Error),
+    #[display(fmt = "not a registry file")]
+    NotARegistryFile,
+    #[display(fmt = "registry file has version {} but only versions up to {} are supported", version, REGISTRY_FORMAT_VERSION)]
+    UnsupportedVersion { #[error(not(source))] version: u64 },
+    #[display(fmt = "bad type name {:?}: {}", name, cause)]
+    BadTypeName { name: String, #[error(source)] cause: RustTypeNameParseError },
+    #[display(fmt = "{} contains a type variable, which can't be persisted", "type_name.qualified()")]
+    TypeVar { #[error(not(source))] type_name: RustTypeName }
+}
+
+/// Registry file contents. Type names are stored as qualified strings, and type ids aren't stored
+#[derive(Serialize, Deserialize)]
+struct PersistedRegistry {
+    format: String,
+    version: u64,
+    types: Vec<PersistedType>
+}
+
+#[derive(Serialize, Deserialize)]
+struct PersistedType {
+    name: String,
+    size: usize,
+    align: usize,
+    structure: PersistedStructure
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum PersistedStructure {
+    Opaque,
+    OpaqueTuple { elements: Vec<PersistedType> },
+    OpaqueFields { fields: Vec<PersistedField> },
+    Primitive { primitive: PersistedPrimitive },
+    CReprEnum { variants: Vec<PersistedVariant> },
+    CReprStruct { body: PersistedBody },
+    Pointer { ptr_kind: PersistedPointerKind, ptr_size: usize, refd_name: String },
+    CTuple { elements: Vec<PersistedType> },
+    Array { elem: Box<PersistedType>, length: usize },
+    Slice { elem: Box<PersistedType> }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "form", content = "elements", rename_all = "snake_case")]
+enum PersistedBody {
+    None,
+    Tuple(Vec<PersistedType>),
+    Fields(Vec<PersistedField>)
+}
+
+#[derive(Serialize, Deserialize)]
+struct PersistedField {
+    name: String,
+    #[serde(rename = "type")]
+    rust_type: PersistedType
+}
+
+#[derive(Serialize, Deserialize)]
+struct PersistedVariant {
+    name: String,
+    body: PersistedBody
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum PersistedPrimitive {
+    I8, I16, I32, I64, I128, Isize,
+    U8, U16, U32, U64, U128, Usize,
+    F32, F64, Bool, Char
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum PersistedPointerKind {
+    ImmRef,
+    MutRef,
+    ImmRaw,
+    MutRaw
+}
+
+impl TypeRegistry {
+    /// Writes every registered type (name, structure, size, and alignment) in a stable, versioned
+    /// JSON format, so another process can [TypeRegistry::import] it.
+    ///
+    /// Type ids and intrinsics aren't written, because they are only valid in this process.
+    /// Types are sorted by name, so the same registry always produces the same file.
+    pub fn export(&self, writer: impl Write) -> Result<(), RegistryPersistError> {
+        let mut types = self.types().map(Arc::as_ref).collect::<Vec<_>>();
+        types.sort_by_cached_key(|rust_type| rust_type.type_name.qualified().to_string());
+        let registry = PersistedRegistry {
+            format: REGISTRY_FORMAT.to_string(),
+            version: REGISTRY_FORMAT_VERSION,
+            types: types.into_iter().map(PersistedType::from_type).collect::<Result<Vec<_>, _>>()?
+        };
+        serde_json::to_writer_pretty(writer, &registry).map_err(RegistryPersistError::Json)
+    }
+
+    /// [TypeRegistry::export] to a file, replacing it if it exists
+    pub fn export_to_file(&self, path: impl AsRef<Path>) -> Result<(), RegistryPersistError> {
+        let mut writer = BufWriter::new(File::create(path).map_err(RegistryPersistError::Io)?);
+        self.export(&mut writer)?;
+        writer.flush().map_err(RegistryPersistError::Io)
+    }
+
+    /// Registers every type written by [TypeRegistry::export].
+    ///
+    /// Type ids are looked up by name in this registry, so types which are also registered here
+    /// with a type id and the same layout (e.g. builtins) get it back, and so do pointers to them
+    /// if the file contains the pointee. Types registered with a type id and a different layout
+    /// aren't replaced, and the conflict is logged. Other conflicts are handled like [TypeRegistry::register].
+    /// If the file is malformed, nothing is registered.
+    ///
+    /// To import into the global registry (so [RustType::lookup] finds the types), use [TypeRegistry::with_global_mut].
+    pub fn import(&mut self, reader: impl Read) -> Result<(), RegistryPersistError> {
+        let value = serde_json::from_reader::<_, serde_json::Value>(reader).map_err(RegistryPersistError::Json)?;
+        if value.get("format").and_then(serde_json::Value::as_str) != Some(REGISTRY_FORMAT) {
+            return Err(RegistryPersistError::NotARegistryFile);
+        }
+        match value.get("version").and_then(serde_json::Value::as_u64) {
+            None => return Err(RegistryPersistError::NotARegistryFile),
+            Some(version) if version > REGISTRY_FORMAT_VERSION => return Err(RegistryPersistError::UnsupportedVersion { version }),
+            Some(_) => {}
+        }
+        let registry = serde_json::from_value::<PersistedRegistry>(value).map_err(RegistryPersistError::Json)?;
+        let mut types = registry.types.into_iter()
+            .map(|rust_type| rust_type.into_type(self))
+            .collect::<Result<Vec<_>, _>>()?;
+        let refd_ids = types.iter()
+            .filter_map(|rust_type| Some((rust_type.type_name.clone(), rust_type.type_id?)))
+            .collect::<HashMap<_, _>>();
+        for rust_type in &mut types {
+            set_refd_ids(rust_type, &refd_ids);
+        }
+        for rust_type in types {
+            match self.lookup(&rust_type.type_name) {
+                // The registered type is the real type with the name, so it isn't replaced
+                Some(registered) if registered.type_id.is_some() && rust_type.type_id.is_none() => log_conflict(Some(&RegistryConflict::Type {
+                    old: Box::new(RustType::clone(registered)),
+                    new: Box::new(rust_type)
+                })),
+                _ => self.register(Cow::Owned(rust_type), None)
+            }
+        }
+        Ok(())
+    }
+
+    /// [TypeRegistry::import] from a file
+    pub fn import_from_file(&mut self, path: impl AsRef<Path>) -> Result<(), RegistryPersistError> {
+        self.import(BufReader::new(File::open(path).map_err(RegistryPersistError::Io)?))
+    }
+}
+
+impl PersistedType {
+    fn from_type(rust_type: &RustType) -> Result<Self, RegistryPersistError> {
+        Ok(PersistedType {
+            name: rust_type.type_name.qualified().to_string(),
+            size: rust_type.size,
+            align: rust_type.align,
+            structure: PersistedStructure::from_structure(&rust_type.structure, &rust_type.type_name)?
+        })
+    }
+
+    fn into_type(self, registry: &TypeRegistry) -> Result<RustType, RegistryPersistError> {
+        let mut rust_type = RustType {
+            type_id: None,
+            type_name: parse_name(self.name)?,
+            size: self.size,
+            align: self.align,
+            structure: self.structure.into_structure(registry)?
+        };
+        rust_type.type_id = registry.lookup_id_with_same_layout(&rust_type);
+        Ok(rust_type)
+    }
+}
+
+impl PersistedStructure {
+    fn from_structure(structure: &TypeStructure, type_name: &RustTypeName) -> Result<Self, RegistryPersistError> {
+        Ok(match structure {
+            TypeStructure::Opaque => PersistedStructure::Opaque,
+            TypeStructure::OpaqueTuple { elements } => PersistedStructure::OpaqueTuple { elements: from_types(elements)? },
+            TypeStructure::OpaqueFields { fields } => PersistedStructure::OpaqueFields { fields: from_fields(fields)? },
+            TypeStructure::Primitive(primitive) => PersistedStructure::Primitive { primitive: PersistedPrimitive::from(*primitive) },
+            TypeStructure::CReprEnum { variants } => PersistedStructure::CReprEnum {
+                variants: variants.iter().map(|variant| Ok(PersistedVariant {
+                    name: variant.variant_name.clone(),
+                    body: PersistedBody::from_body(&variant.body)?
+                })).collect::<Result<Vec<_>, _>>()?
+            },
+            TypeStructure::CReprStruct { body } => PersistedStructure::CReprStruct { body: PersistedBody::from_body(body)? },
+            TypeStructure::Var(_) => return Err(RegistryPersistError::TypeVar { type_name: type_name.clone() }),
+            TypeStructure::Pointer { ptr_kind, ptr_size, refd_id: _, refd_name } => PersistedStructure::Pointer {
+                ptr_kind: PersistedPointerKind::from(*ptr_kind),
+                ptr_size: *ptr_size,
+                refd_name: refd_name.qualified().to_string()
+            },
+            TypeStructure::CTuple { elements } => PersistedStructure::CTuple { elements: from_types(elements)? },
+            TypeStructure::Array { elem, length } => PersistedStructure::Array { elem: Box::new(PersistedType::from_type(elem)?), length: *length },
+            TypeStructure::Slice { elem } => PersistedStructure::Slice { elem: Box::new(PersistedType::from_type(elem)?) }
+        })
+    }
+
+    fn into_structure(self, registry: &TypeRegistry) -> Result<TypeStructure, RegistryPersistError> {
+        Ok(match self {
+            PersistedStructure::Opaque => TypeStructure::Opaque,
+            PersistedStructure::OpaqueTuple { elements } => TypeStructure::OpaqueTuple { elements: into_types(elements, registry)? },
+            PersistedStructure::OpaqueFields { fields } => TypeStructure::OpaqueFields { fields: into_fields(fields, registry)? },
+            PersistedStructure::Primitive { primitive } => TypeStructure::Primitive(PrimitiveType::from(primitive)),
+            PersistedStructure::CReprEnum { variants } => TypeStructure::CReprEnum {
+                variants: variants.into_iter().map(|variant| Ok(TypeEnumVariant {
+                    variant_name: variant.name,
+                    body: variant.body.into_body(registry)?
+                })).collect::<Result<Vec<_>, _>>()?
+            },
+            PersistedStructure::CReprStruct { body } => TypeStructure::CReprStruct { body: body.into_body(registry)? },
+            PersistedStructure::Pointer { ptr_kind, ptr_size, refd_name } => {
+                let refd_name = parse_name(refd_name)?;
+                TypeStructure::Pointer {
+                    ptr_kind: RustPointerKind::from(ptr_kind),
+                    ptr_size,
+                    // Set by `set_refd_ids`, once we know which types keep their ids
+                    refd_id: None,
+                    refd_name
+                }
+            }
+            PersistedStructure::CTuple { elements } => TypeStructure::CTuple { elements: into_types(elements, registry)? },
+            PersistedStructure::Array { elem, length } => TypeStructure::Array { elem: Box::new(elem.into_type(registry)?), length },
+            PersistedStructure::Slice { elem } => TypeStructure::Slice { elem: Box::new(elem.into_type(registry)?) }
+        })
+    }
+}
+
+impl PersistedBody {
+    fn from_body(body: &TypeStructureBody) -> Result<Self, RegistryPersistError> {
+        Ok(match body {
+            TypeStructureBody::None => PersistedBody::None,
+            TypeStructureBody::Tuple(elements) => PersistedBody::Tuple(from_types(elements)?),
+            TypeStructureBody::Fields(fields) => PersistedBody::Fields(from_fields(fields)?)
+        })
+    }
+
+    fn into_body(self, registry: &TypeRegistry) -> Result<TypeStructureBody, RegistryPersistError> {
+        Ok(match self {
+            PersistedBody::None => TypeStructureBody::None,
+            PersistedBody::Tuple(elements) => TypeStructureBody::Tuple(into_types(elements, registry)?),
+            PersistedBody::Fields(fields) => TypeStructureBody::Fields(into_fields(fields, registry)?)
+        })
+    }
+}
+
+/// Sets the pointee ids of pointers in the type, to the ids of the imported types they point to
+fn set_refd_ids(rust_type: &mut RustType, refd_ids: &HashMap<RustTypeName, TypeId>) {
+    match &mut rust_type.structure {
+        TypeStructure::Opaque | TypeStructure::Primitive(_) | TypeStructure::Var(_) => {}
+        TypeStructure::OpaqueTuple { elements } | TypeStructure::CTuple { elements } => {
+            elements.iter_mut().for_each(|element| set_refd_ids(element, refd_ids))
+        }
+        TypeStructure::OpaqueFields { fields } => fields.iter_mut().for_each(|field| set_refd_ids(&mut field.rust_type, refd_ids)),
+        TypeStructure::CReprEnum { variants } => variants.iter_mut().for_each(|variant| set_body_refd_ids(&mut variant.body, refd_ids)),
+        TypeStructure::CReprStruct { body } => set_body_refd_ids(body, refd_ids),
+        TypeStructure::Pointer { refd_id, refd_name, .. } => *refd_id = refd_ids.get(refd_name).copied(),
+        TypeStructure::Array { elem, length: _ } | TypeStructure::Slice { elem } => set_refd_ids(elem, refd_ids)
+    }
+}
+
+fn set_body_refd_ids(body: &mut TypeStructureBody, refd_ids: &HashMap<RustTypeName, TypeId>) {
+    match body {
+        TypeStructureBody::None => {}
+        TypeStructureBody::Tuple(elements) => elements.iter_mut().for_each(|element| set_refd_ids(element, refd_ids)),
+        TypeStructureBody::Fields(fields) => fields.iter_mut().for_each(|field| set_refd_ids(&mut field.rust_type, refd_ids))
+    }
+}
+
+fn from_types(rust_types: &[RustType]) -> Result<Vec<PersistedType>, RegistryPersistError> {
+    rust_types.iter().map(PersistedType::from_type).collect()
+}
+
+fn into_types(rust_types: Vec<PersistedType>, registry: &TypeRegistry) -> Result<Vec<RustType>, RegistryPersistError> {
+    rust_types.into_iter().map(|rust_type| rust_type.into_type(registry)).collect()
+}
+
+fn from_fields(fields: &[TypeStructureBodyField]) -> Result<Vec<PersistedField>, RegistryPersistError> {
+    fields.iter().map(|field| Ok(PersistedField {
+        name: field.name.clone(),
+        rust_type: PersistedType::from_type(&field.rust_type)?
+    })).collect()
+}
+
+fn into_fields(fields: Vec<PersistedField>, registry: &TypeRegistry) -> Result<Vec<TypeStructureBodyField>, RegistryPersistError> {
+    fields.into_iter().map(|field| Ok(TypeStructureBodyField {
+        name: field.name,
+        rust_type: field.rust_type.into_type(registry)?
+    })).collect()
+}
+
+fn parse_name(name: String) -> Result<RustTypeName, RegistryPersistError> {
+    RustTypeName::try_from(name.as_str()).map_err(|cause| RegistryPersistError::BadTypeName { name, cause })
+}
+
+impl From<PrimitiveType> for PersistedPrimitive {
+    fn from(primitive: PrimitiveType) -> Self {
+        match primitive {
+            PrimitiveType::I8 => PersistedPrimitive::I8,
+            PrimitiveType::I16 => PersistedPrimitive::I16,
+            PrimitiveType::I32 => PersistedPrimitive::I32,
+            PrimitiveType::I64 => PersistedPrimitive::I64,
+            PrimitiveType::I128 => PersistedPrimitive::I128,
+            PrimitiveType::Isize => PersistedPrimitive::Isize,
+            PrimitiveType::U8 => PersistedPrimitive::U8,
+            PrimitiveType::U16 => PersistedPrimitive::U16,
+            PrimitiveType::U32 => PersistedPrimitive::U32,
+            PrimitiveType::U64 => PersistedPrimitive::U64,
+            PrimitiveType::U128 => PersistedPrimitive::U128,
+            PrimitiveType::Usize => PersistedPrimitive::Usize,
+            PrimitiveType::F32 => PersistedPrimitive::F32,
+            PrimitiveType::F64 => PersistedPrimitive::F64,
+            PrimitiveType::Bool => PersistedPrimitive::Bool,
+            PrimitiveType::Char => PersistedPrimitive::Char
+        }
+    }
+}
+
+impl From<PersistedPrimitive> for PrimitiveType {
+    fn from(primitive: PersistedPrimitive) -> Self {
+        match primitive {
+            PersistedPrimitive::I8 => PrimitiveType::I8,
+            PersistedPrimitive::I16 => PrimitiveType::I16,
+            PersistedPrimitive::I32 => PrimitiveType::I32,
+            PersistedPrimitive::I64 => PrimitiveType::I64,
+            PersistedPrimitive::I128 => PrimitiveType::I128,
+            PersistedPrimitive::Isize => PrimitiveType::Isize,
+            PersistedPrimitive::U8 => PrimitiveType::U8,
+            PersistedPrimitive::U16 => PrimitiveType::U16,
+            PersistedPrimitive::U32 => PrimitiveType::U32,
+            PersistedPrimitive::U64 => PrimitiveType::U64,
+            PersistedPrimitive::U128 => PrimitiveType::U128,
+            PersistedPrimitive::Usize => PrimitiveType::Usize,
+            PersistedPrimitive::F32 => PrimitiveType::F32,
+            PersistedPrimitive::F64 => PrimitiveType::F64,
+            PersistedPrimitive::Bool => PrimitiveType::Bool,
+            PersistedPrimitive::Char => PrimitiveType::Char
+        }
+    }
+}
+
+impl From<RustPointerKind> for PersistedPointerKind {
+    fn from(ptr_kind: RustPointerKind) -> Self {
+        match ptr_kind {
+            RustPointerKind::ImmRef => PersistedPointerKind::ImmRef,
+            RustPointerKind::MutRef => PersistedPointerKind::MutRef,
+            RustPointerKind::ImmRaw => PersistedPointerKind::ImmRaw,
+            RustPointerKind::MutRaw => PersistedPointerKind::MutRaw
+        }
+    }
+}
+
+impl From<PersistedPointerKind> for RustPointerKind {
+    fn from(ptr_kind: PersistedPointerKind) -> Self {
+        match ptr_kind {
+            PersistedPointerKind::ImmRef => RustPointerKind::ImmRef,
+            PersistedPointerKind::MutRef => RustPointerKind::MutRef,
+            PersistedPointerKind::ImmRaw => RustPointerKind::ImmRaw,
+            PersistedPointerKind::MutRaw => RustPointerKind::MutRaw
+        }
+    }
+}
@@ -6,6 +6,7 @@ use crate::structure::TypeStructure;
 use crate::type_name::RustTypeName;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum PrimitiveType {
     I8,
     I16,
@@ -82,6 +83,29 @@ impl PrimitiveType {
         }
     }
 
+    /// The primitive with the given name (e.g. `"u32"`), if any
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "i8" => Some(PrimitiveType::I8),
+            "i16" => Some(PrimitiveType::I16),
+            "i32" => Some(PrimitiveType::I32),
+            "i64" => Some(PrimitiveType::I64),
+            "i128" => Some(PrimitiveType::I128),
+            "isize" => Some(PrimitiveType::Isize),
+            "u8" => Some(PrimitiveType::U8),
+            "u16" => Some(PrimitiveType::U16),
+            "u32" => Some(PrimitiveType::U32),
+            "u64" => Some(PrimitiveType::U64),
+            "u128" => Some(PrimitiveType::U128),
+            "usize" => Some(PrimitiveType::Usize),
+            "f32" => Some(PrimitiveType::F32),
+            "f64" => Some(PrimitiveType::F64),
+            "bool" => Some(PrimitiveType::Bool),
+            "char" => Some(PrimitiveType::Char),
+            _ => None
+        }
+    }
+
     pub fn size(&self) -> usize {
         match self {
             PrimitiveType::I8 => size_of::<i8>(),
@@ -123,6 +147,38 @@ impl PrimitiveType {
             PrimitiveType::Char => align_of::<char>(),
         }
     }
+
+    /// Can every value of this primitive be converted to `other` without losing information?
+    /// True if they're equal, or this is a narrower integer of compatible signedness,
+    /// or this is an integer or float which fits in the float's mantissa.
+    pub fn widens_to(&self, other: &PrimitiveType) -> bool {
+        if self == other {
+            return true;
+        }
+        match (self.is_signed_int(), other.is_signed_int(), self.is_unsigned_int(), other.is_unsigned_int()) {
+            // signed -> signed or unsigned -> unsigned
+            (true, true, _, _) | (_, _, true, true) => self.size() <= other.size(),
+            // unsigned -> signed needs an extra bit
+            (_, true, true, _) => self.size() < other.size(),
+            _ => match other {
+                PrimitiveType::F32 => self.is_int() && self.size() <= 2,
+                PrimitiveType::F64 => (self.is_int() && self.size() <= 4) || *self == PrimitiveType::F32,
+                _ => false
+            }
+        }
+    }
+
+    fn is_signed_int(&self) -> bool {
+        matches!(self, PrimitiveType::I8 | PrimitiveType::I16 | PrimitiveType::I32 | PrimitiveType::I64 | PrimitiveType::I128 | PrimitiveType::Isize)
+    }
+
+    fn is_unsigned_int(&self) -> bool {
+        matches!(self, PrimitiveType::U8 | PrimitiveType::U16 | PrimitiveType::U32 | PrimitiveType::U64 | PrimitiveType::U128 | PrimitiveType::Usize)
+    }
+
+    fn is_int(&self) -> bool {
+        self.is_signed_int() || self.is_unsigned_int()
+    }
 }
 
 impl Display for PrimitiveType {
@@ -1,155 +1,804 @@
 use std::any::TypeId;
 use std::borrow::Cow;
-use std::sync::RwLock;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::sync::atomic::{AtomicU64, Ordering as AtomicOrdering};
 use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::cmp::Ordering;
+use std::ffi::OsString;
+use std::ops::RangeFull;
+use std::ptr::NonNull;
+use std::rc::Rc;
+use std::time::Duration;
+use arc_swap::ArcSwap;
 use bimap::BiHashMap;
+use derive_more::{Display, Error};
 use lazy_static::lazy_static;
 use log::error;
-use crate::{IntrinsicRustType, RustType};
+use crate::{HasStructure, IntrinsicRustType, RustType, TypeTemplate};
 use crate::intrinsic::UnknownIntrinsicType;
+use crate::template::{cow_layout, mutex_layout, option_layout, range_bound_layout, range_inclusive_layout, range_layout, ref_cell_layout, result_layout, TypeTemplateKey};
+use crate::registry_observer::RegistryObservers;
+use crate::RegistryEvent;
+#[cfg(feature = "serde")]
+use crate::value_serialize::CustomSerializers;
+#[cfg(feature = "serde")]
+use crate::value_deserialize::CustomDeserializers;
 
 use crate::structure::TypeStructure;
 use crate::type_name::RustTypeName;
 
 lazy_static! {
-    static ref KNOWN_TYPES: RwLock<HashMap<RustTypeName, RustType>> = RwLock::new({
-        let mut known_types = HashMap::new();
-        RustType::register_builtins(&mut known_types);
-        known_types
-    });
-    static ref KNOWN_NAMES: RwLock<BiHashMap<TypeId, RustTypeName>> = RwLock::new(BiHashMap::new());
-    static ref KNOWN_INTRINSICS: RwLock<HashMap<TypeId, IntrinsicRustType>> = RwLock::new(HashMap::new());
+    static ref GLOBAL_REGISTRY: ArcSwap<TypeRegistry> = ArcSwap::from_pointee(TypeRegistry::new());
+    /// Held while modifying the global registry, so concurrent modifications aren't lost
+    static ref GLOBAL_REGISTRY_WRITE: Mutex<()> = Mutex::new(());
 }
 
-impl RustType {
+/// Registered types, so they can be looked up by name or type id.
+///
+/// There is a global singleton used by [RustType::of], [RustType::register], [RustType::lookup] etc.
+/// (see [TypeRegistry::with_global]), but you can also create separate registries, e.g. so that
+/// tests don't interfere with each other or plugins have their own type namespaces.
+///
+/// The global registry is optimized for reading: readers don't lock, they access the current version,
+/// and writers copy it, modify the copy, and swap it in. Registered types are shared (`Arc<RustType>`),
+/// so lookups and copies don't clone them.
+///
+/// With the `auto-register` feature, types deriving `HasStructure` with `#[has_structure(register)]`
+/// are in the global registry before it's first accessed.
+#[derive(Debug)]
+pub struct TypeRegistry {
+    types: HashMap<RustTypeName, Arc<RustType>>,
+    names: BiHashMap<TypeId, RustTypeName>,
+    intrinsics: HashMap<TypeId, IntrinsicRustType>,
+    pub(crate) templates: HashMap<TypeTemplateKey, TypeTemplate>,
+    conflict_policy: ConflictPolicy,
+    pub(crate) observers: RegistryObservers,
+    #[cfg(feature = "serde")]
+    pub(crate) serializers: CustomSerializers,
+    #[cfg(feature = "serde")]
+    pub(crate) deserializers: CustomDeserializers,
+    /// Changed whenever a registered type, type name, or template changes, so caches (e.g. [SubtypeCache](crate::SubtypeCache))
+    /// know when they are stale. Generations are unique across registries, so a cache also knows when it's used with a different registry
+    generation: u64
+}
+
+impl TypeRegistry {
+    /// Creates a registry containing only the builtin types (e.g. `std::string::String`, `std::vec::Vec<{unknown}>`, and the template `std::vec::Vec<T>`)
+    pub fn new() -> Self {
+        let mut registry = TypeRegistry {
+            types: HashMap::new(),
+            names: BiHashMap::new(),
+            intrinsics: HashMap::new(),
+            templates: HashMap::new(),
+            conflict_policy: ConflictPolicy::default(),
+            observers: RegistryObservers::default(),
+            #[cfg(feature = "serde")]
+            serializers: CustomSerializers::default(),
+            #[cfg(feature = "serde")]
+            deserializers: CustomDeserializers::default(),
+            generation: next_generation()
+        };
+        registry.register_builtins();
+        registry
+    }
+
+    /// Run `f` with the current version of the global registry, without locking.
+    ///
+    /// Always returns `Some`; the `Option` is for compatibility with [TypeRegistry::with_global_mut].
+    pub fn with_global<R>(f: impl FnOnce(&TypeRegistry) -> R) -> Option<R> {
+        #[cfg(feature = "auto-register")]
+        crate::auto_register::ensure_auto_registered();
+        Some(f(&GLOBAL_REGISTRY.load()))
+    }
+
+    /// The current version of the global registry, which isn't affected by later modifications
+    pub fn global() -> Arc<TypeRegistry> {
+        #[cfg(feature = "auto-register")]
+        crate::auto_register::ensure_auto_registered();
+        GLOBAL_REGISTRY.load_full()
+    }
+
+    /// Run `f` with a copy of the global registry, mutably, then replace the global registry with the copy.
+    /// Concurrent modifications wait, but readers see the previous version until `f` returns.
+    ///
+    /// Observers (see [TypeRegistry::subscribe]) are called after the registry is replaced,
+    /// so they can access and modify it.
+    ///
+    /// Always returns `Some`. If `f` panics, the global registry isn't replaced, so later calls continue
+    /// with the registry from before.
+    pub fn with_global_mut<R>(f: impl FnOnce(&mut TypeRegistry) -> R) -> Option<R> {
+        #[cfg(feature = "auto-register")]
+        crate::auto_register::ensure_auto_registered();
+        // A panic while the lock was held didn't replace the registry, so it isn't corrupt
+        let write_guard = GLOBAL_REGISTRY_WRITE.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut registry = GLOBAL_REGISTRY.load().copy();
+        registry.observers.begin_deferring();
+        let result = f(&mut registry);
+        let deferred_events = registry.observers.end_deferring();
+        GLOBAL_REGISTRY.store(Arc::new(registry));
+        drop(write_guard);
+        deferred_events.dispatch();
+        Some(result)
+    }
+
+    /// What happens when registering something which conflicts with an existing entry
+    pub fn conflict_policy(&self) -> ConflictPolicy {
+        self.conflict_policy
+    }
+
+    /// Set what happens when registering something which conflicts with an existing entry
+    pub fn set_conflict_policy(&mut self, conflict_policy: ConflictPolicy) {
+        self.conflict_policy = conflict_policy;
+    }
+
     /// Registers the rust type so it can be looked up by name,
     /// registers the type name so it can be looked up by id (if the rust type has an id),
     /// and registers the intrinsic type so it can be looked up by id (if `Some`).
     ///
     /// The provided type is `Cow` so that it doesn't need to be cloned if already registered.
-    /// If another type is registered with the same name and the type ids aren't equal, this will log an error.
-    pub fn register(rust_type: Cow<'_, RustType>, intrinsic_rust_type: Option<IntrinsicRustType>) {
+    /// If this conflicts with an existing entry, this will log an error, and the conflict is resolved by
+    /// the [ConflictPolicy] ([ConflictPolicy::Error] keeps the existing entry).
+    pub fn register(&mut self, rust_type: Cow<'_, RustType>, intrinsic_rust_type: Option<IntrinsicRustType>) {
         if let Some(intrinsic_type) = intrinsic_rust_type {
-            IntrinsicRustType::register(intrinsic_type);
+            self.register_intrinsic(intrinsic_type);
         }
         if let Some(type_id) = rust_type.type_id {
-            RustTypeName::register(type_id, Cow::Borrowed(&rust_type.type_name))
+            self.register_type_name(type_id, Cow::Borrowed(&rust_type.type_name))
+        }
+        let conflict = self.check_type(&rust_type).err();
+        log_conflict(conflict.as_ref());
+        if let Ok(Some(rust_type)) = self.resolve_type(rust_type, conflict) {
+            self.insert_type(rust_type);
+        }
+    }
+
+    /// Like [TypeRegistry::register], but instead of logging conflicts, returns the first one which
+    /// the [ConflictPolicy] doesn't resolve. Conflicts which it resolves aren't errors.
+    ///
+    /// If this returns an error, the registry isn't modified (not even the type name or intrinsic).
+    pub fn try_register(&mut self, rust_type: Cow<'_, RustType>, intrinsic_rust_type: Option<IntrinsicRustType>) -> Result<(), RegistryConflict> {
+        let insert_intrinsic = match &intrinsic_rust_type {
+            None => false,
+            Some(intrinsic_type) => self.resolve_intrinsic(self.check_intrinsic(intrinsic_type).err())?
+        };
+        let insert_type_name = match rust_type.type_id {
+            None => None,
+            Some(type_id) => self.resolve_type_name(type_id, self.check_type_name(type_id, &rust_type.type_name).err())?
+                .then(|| (type_id, rust_type.type_name.clone()))
+        };
+        let conflict = self.check_type(&rust_type).err();
+        let insert_type = self.resolve_type(rust_type, conflict)?;
+        // Every conflict is resolved, so we can modify the registry
+        if let Some(intrinsic_type) = intrinsic_rust_type.filter(|_| insert_intrinsic) {
+            self.insert_intrinsic(intrinsic_type);
+        }
+        if let Some((type_id, type_name)) = insert_type_name {
+            self.insert_type_name(type_id, type_name);
+        }
+        if let Some(rust_type) = insert_type {
+            self.insert_type(rust_type);
+        }
+        Ok(())
+    }
+
+    /// Registers the type id to the type name.
+    ///
+    /// The provided type name is `Cow` so that it doesn't need to be cloned if already registered.
+    /// If another type id is registered with the same name or vice versa and they aren't equal, this will log an error,
+    /// and the conflict is resolved by the [ConflictPolicy] (names can't be unified, so [ConflictPolicy::Unify] keeps the existing entry).
+    pub fn register_type_name(&mut self, type_id: TypeId, type_name: Cow<'_, RustTypeName>) {
+        let conflict = self.check_type_name(type_id, &type_name).err();
+        log_conflict(conflict.as_ref());
+        if let Ok(true) = self.resolve_type_name(type_id, conflict) {
+            self.insert_type_name(type_id, type_name.into_owned());
+        }
+    }
+
+    /// Like [TypeRegistry::register_type_name], but instead of logging conflicts, returns them if
+    /// the [ConflictPolicy] doesn't resolve them.
+    pub fn try_register_type_name(&mut self, type_id: TypeId, type_name: Cow<'_, RustTypeName>) -> Result<(), RegistryConflict> {
+        let conflict = self.check_type_name(type_id, &type_name).err();
+        if self.resolve_type_name(type_id, conflict)? {
+            self.insert_type_name(type_id, type_name.into_owned());
+        }
+        Ok(())
+    }
+
+    /// Registers the intrinsic type so it can be looked up by id.
+    ///
+    /// If another intrinsic type is registered with the same id but a different name, this will log an error,
+    /// and the conflict is resolved by the [ConflictPolicy] (intrinsics can't be unified, so [ConflictPolicy::Unify] keeps the existing entry).
+    pub fn register_intrinsic(&mut self, intrinsic_type: IntrinsicRustType) {
+        let conflict = self.check_intrinsic(&intrinsic_type).err();
+        log_conflict(conflict.as_ref());
+        if let Ok(true) = self.resolve_intrinsic(conflict) {
+            self.insert_intrinsic(intrinsic_type);
+        }
+    }
+
+    /// Like [TypeRegistry::register_intrinsic], but instead of logging conflicts, returns them if
+    /// the [ConflictPolicy] doesn't resolve them.
+    pub fn try_register_intrinsic(&mut self, intrinsic_type: IntrinsicRustType) -> Result<(), RegistryConflict> {
+        let conflict = self.check_intrinsic(&intrinsic_type).err();
+        if self.resolve_intrinsic(conflict)? {
+            self.insert_intrinsic(intrinsic_type);
+        }
+        Ok(())
+    }
+
+    /// Whether registering would do nothing, because the same type, type name, and intrinsic are already registered
+    fn is_registered(&self, rust_type: &RustType, intrinsic_rust_type: Option<&IntrinsicRustType>) -> bool {
+        self.types.get(&rust_type.type_name).is_some_and(|existing_type| is_same_registered_type(existing_type, rust_type)) &&
+            rust_type.type_id.is_none_or(|type_id| self.is_type_name_registered(type_id, &rust_type.type_name)) &&
+            intrinsic_rust_type.is_none_or(|intrinsic_type| self.is_intrinsic_registered(intrinsic_type))
+    }
+
+    fn is_type_name_registered(&self, type_id: TypeId, type_name: &RustTypeName) -> bool {
+        self.names.get_by_left(&type_id) == Some(type_name)
+    }
+
+    fn is_intrinsic_registered(&self, intrinsic_type: &IntrinsicRustType) -> bool {
+        self.intrinsics.get(&intrinsic_type.type_id).is_some_and(|existing_intrinsic| existing_intrinsic.type_name == intrinsic_type.type_name)
+    }
+
+    /// Returns `Err` if registering the type would replace a different one
+    fn check_type(&self, rust_type: &RustType) -> Result<(), RegistryConflict> {
+        match self.types.get(&rust_type.type_name) {
+            Some(existing_type) if !is_same_registered_type(existing_type, rust_type) => Err(RegistryConflict::Type {
+                old: Box::new(RustType::clone(existing_type)),
+                new: Box::new(rust_type.clone())
+            }),
+            _ => Ok(())
         }
-        Self::register_just_type(rust_type);
     }
 
-    /// Registers the rust type so it can be looked up by name.
-    fn register_just_type(rust_type: Cow<'_, RustType>) {
-        let type_name = &rust_type.type_name;
-        if let Some(mut known_types) = catch_and_log!(KNOWN_TYPES.write(), "known rust types poisoned") {
-            if let Some(existing_type) = known_types.get(type_name) {
-                if existing_type != &*rust_type || &existing_type.type_id != &rust_type.type_id {
-                    error!("rust type with name {} already registered with a different structure", type_name.qualified());
+    /// The type to insert (`None` if the same type is registered or the [ConflictPolicy] keeps the existing one),
+    /// or the conflict if the policy doesn't resolve it
+    fn resolve_type(&self, rust_type: Cow<'_, RustType>, conflict: Option<RegistryConflict>) -> Result<Option<RustType>, RegistryConflict> {
+        match conflict {
+            // Same type is already registered
+            None if self.types.contains_key(&rust_type.type_name) => Ok(None),
+            None => Ok(Some(rust_type.into_owned())),
+            Some(conflict) => match self.conflict_policy {
+                ConflictPolicy::KeepOld => Ok(None),
+                ConflictPolicy::Replace => Ok(Some(rust_type.into_owned())),
+                ConflictPolicy::Unify => {
+                    let mut unified = RustType::clone(&self.types[&rust_type.type_name]);
+                    match unified.try_unify(rust_type.into_owned()) {
+                        Ok(()) => Ok(Some(unified)),
+                        Err(_) => Err(conflict)
+                    }
                 }
+                ConflictPolicy::Error => Err(conflict)
             }
-            known_types.insert(type_name.clone(), rust_type.into_owned());
         }
     }
 
-    /// Index into the type registry, which is a global singleton.
-    /// Returns the registered type with the given type name.
-    pub fn lookup(type_name: &RustTypeName) -> Option<RustType> {
-        match catch_and_log!(KNOWN_TYPES.read(), "known rust types poisoned") {
-            None => None,
-            Some(known_types) => known_types.get(type_name).cloned()
+    fn insert_type(&mut self, rust_type: RustType) {
+        let rust_type = Arc::new(rust_type);
+        let old = self.types.insert(rust_type.type_name.clone(), rust_type.clone());
+        self.bump_generation();
+        self.emit(|| match old {
+            None => RegistryEvent::Registered { new: rust_type },
+            Some(old) => RegistryEvent::Replaced { old, new: rust_type }
+        });
+    }
+
+    /// Returns `Err` if registering the type name would replace a different name or id
+    fn check_type_name(&self, type_id: TypeId, type_name: &RustTypeName) -> Result<(), RegistryConflict> {
+        if let Some(existing_name) = self.names.get_by_left(&type_id) {
+            if existing_name != type_name {
+                return Err(RegistryConflict::TypeName {
+                    type_id,
+                    old: Box::new(existing_name.clone()),
+                    new: Box::new(type_name.clone())
+                });
+            }
         }
+        if let Some(existing_id) = self.names.get_by_right(type_name) {
+            if existing_id != &type_id {
+                return Err(RegistryConflict::TypeId {
+                    type_name: Box::new(type_name.clone()),
+                    old: *existing_id,
+                    new: type_id
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether to insert the type name (`false` if it's registered or the [ConflictPolicy] keeps the existing one),
+    /// or the conflict if the policy doesn't resolve it
+    fn resolve_type_name(&self, type_id: TypeId, conflict: Option<RegistryConflict>) -> Result<bool, RegistryConflict> {
+        match conflict {
+            None => Ok(!self.names.contains_left(&type_id)),
+            Some(conflict) => self.resolve_ununifiable(conflict)
+        }
+    }
+
+    fn insert_type_name(&mut self, type_id: TypeId, type_name: RustTypeName) {
+        self.names.insert(type_id, type_name);
+        self.bump_generation();
+    }
+
+    /// Returns `Err` if registering the intrinsic would replace a different one
+    fn check_intrinsic(&self, intrinsic_type: &IntrinsicRustType) -> Result<(), RegistryConflict> {
+        match self.intrinsics.get(&intrinsic_type.type_id) {
+            Some(existing_intrinsic) if existing_intrinsic.type_name != intrinsic_type.type_name => Err(RegistryConflict::Intrinsic {
+                old: Box::new(existing_intrinsic.clone()),
+                new: Box::new(intrinsic_type.clone())
+            }),
+            _ => Ok(())
+        }
+    }
+
+    /// Whether to insert the intrinsic (`false` if the [ConflictPolicy] keeps the existing one),
+    /// or the conflict if the policy doesn't resolve it
+    fn resolve_intrinsic(&self, conflict: Option<RegistryConflict>) -> Result<bool, RegistryConflict> {
+        match conflict {
+            None => Ok(true),
+            Some(conflict) => self.resolve_ununifiable(conflict)
+        }
+    }
+
+    fn insert_intrinsic(&mut self, intrinsic_type: IntrinsicRustType) {
+        self.intrinsics.insert(intrinsic_type.type_id, intrinsic_type);
+    }
+
+    /// Whether to replace the existing entry, for entries which can't be unified
+    fn resolve_ununifiable(&self, conflict: RegistryConflict) -> Result<bool, RegistryConflict> {
+        match self.conflict_policy {
+            ConflictPolicy::KeepOld => Ok(false),
+            ConflictPolicy::Replace => Ok(true),
+            ConflictPolicy::Unify | ConflictPolicy::Error => Err(conflict)
+        }
+    }
+
+    /// Returns the registered type with the given type name.
+    pub fn lookup(&self, type_name: &RustTypeName) -> Option<&Arc<RustType>> {
+        self.types.get(type_name)
     }
 
-    /// Index into the type registry, which is a global singleton.
     /// Returns the registered type with the given type id.
-    pub fn lookup_from_id(type_id: TypeId) -> Option<RustType> {
-        match (catch_and_log!(KNOWN_NAMES.read(), "known rust type names poisoned"), catch_and_log!(KNOWN_TYPES.read(), "known rust types poisoned")) {
-            (Some(known_names), Some(known_types)) => {
-                known_names.get_by_left(&type_id)
-                    .and_then(|type_name| known_types.get(&*type_name))
-                    .cloned()
-            },
-            _ => None
+    pub fn lookup_from_id(&self, type_id: TypeId) -> Option<&Arc<RustType>> {
+        self.names.get_by_left(&type_id).and_then(|type_name| self.types.get(type_name))
+    }
+
+    /// Returns the registered type name with the given type id.
+    pub fn lookup_name(&self, type_id: TypeId) -> Option<&RustTypeName> {
+        self.names.get_by_left(&type_id)
+    }
+
+    /// Returns the registered type id with the given type name.
+    pub fn lookup_id(&self, type_name: &RustTypeName) -> Option<TypeId> {
+        self.names.get_by_right(type_name).copied()
+    }
+
+    /// Returns the registered type id with the type's name, if the registered type has the same layout.
+    /// Otherwise the type id would let values of the type be reinterpreted as the registered type
+    /// (e.g. by [DynValue::downcast](crate::DynValue::downcast)).
+    #[cfg(any(feature = "persist", feature = "serde"))]
+    pub(crate) fn lookup_id_with_same_layout(&self, rust_type: &RustType) -> Option<TypeId> {
+        self.lookup(&rust_type.type_name)
+            .filter(|registered| has_same_layout(rust_type, registered))
+            .and_then(|registered| registered.type_id)
+    }
+
+    /// Returns the registered intrinsic type with the given type id.
+    pub fn lookup_intrinsic(&self, type_id: TypeId) -> Option<&IntrinsicRustType> {
+        self.intrinsics.get(&type_id)
+    }
+
+    /// Returns the registered intrinsic type with the given type name.
+    pub fn lookup_intrinsic_from_name(&self, type_name: &RustTypeName) -> Option<&IntrinsicRustType> {
+        self.lookup_id(type_name).and_then(|type_id| self.lookup_intrinsic(type_id))
+    }
+
+    /// Iterate all registered types
+    pub fn types(&self) -> impl Iterator<Item=&Arc<RustType>> + '_ {
+        self.types.values()
+    }
+
+    /// Iterate all registered type ids and their names
+    pub fn type_names(&self) -> impl Iterator<Item=(TypeId, &RustTypeName)> + '_ {
+        self.names.iter().map(|(type_id, type_name)| (*type_id, type_name))
+    }
+
+    /// Iterate all registered intrinsic types
+    pub fn intrinsics(&self) -> impl Iterator<Item=&IntrinsicRustType> + '_ {
+        self.intrinsics.values()
+    }
+
+    /// Unregisters the type with the given name, along with its type id and intrinsic type.
+    /// Returns the type if it was registered.
+    ///
+    /// If only the name is registered to a type id (via [TypeRegistry::register_type_name]), that is still removed.
+    pub fn unregister(&mut self, type_name: &RustTypeName) -> Option<Arc<RustType>> {
+        let type_id = self.names.remove_by_right(type_name).map(|(type_id, _)| type_id);
+        self.unregister_rest(type_id, type_name)
+    }
+
+    /// Unregisters the type with the given type id, along with its name and intrinsic type.
+    /// Returns the type if it was registered.
+    pub fn unregister_from_id(&mut self, type_id: TypeId) -> Option<Arc<RustType>> {
+        match self.names.remove_by_left(&type_id) {
+            None => {
+                self.intrinsics.remove(&type_id);
+                None
+            }
+            Some((_, type_name)) => self.unregister_rest(Some(type_id), &type_name)
+        }
+    }
+
+    /// Removes the type and intrinsic after the type name was removed
+    fn unregister_rest(&mut self, type_id: Option<TypeId>, type_name: &RustTypeName) -> Option<Arc<RustType>> {
+        if let Some(type_id) = type_id {
+            self.intrinsics.remove(&type_id);
+        }
+        let rust_type = self.types.remove(type_name);
+        if type_id.is_some() || rust_type.is_some() {
+            self.bump_generation();
+        }
+        if let Some(old) = &rust_type {
+            self.emit(|| RegistryEvent::Unregistered { old: old.clone() });
+        }
+        rust_type
+    }
+
+    /// Copy the full state of the registry, so it can be restored later with [TypeRegistry::restore]
+    pub fn snapshot(&self) -> TypeRegistrySnapshot {
+        TypeRegistrySnapshot(self.clone())
+    }
+
+    /// Restore the full state of the registry (all entries and the [ConflictPolicy]) from a snapshot.
+    /// Observers aren't restored, and are sent events for every type which changed.
+    pub fn restore(&mut self, snapshot: TypeRegistrySnapshot) {
+        let mut restored = snapshot.0;
+        // Caches from after the snapshot must see a different generation
+        restored.bump_generation();
+        restored.observers = std::mem::take(&mut self.observers);
+        let old = std::mem::replace(self, restored);
+        if !self.observers.is_empty() {
+            for event in restore_events(old.types, &self.types) {
+                self.emit(|| event);
+            }
+        }
+    }
+
+    /// [TypeRegistry::snapshot] the global registry
+    pub fn snapshot_global() -> Option<TypeRegistrySnapshot> {
+        TypeRegistry::with_global(TypeRegistry::snapshot)
+    }
+
+    /// [TypeRegistry::restore] the global registry
+    pub fn restore_global(snapshot: TypeRegistrySnapshot) {
+        TypeRegistry::with_global_mut(|registry| registry.restore(snapshot));
+    }
+
+    /// If this changed, registered types or type names changed (or this is a different registry)
+    pub(crate) fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    pub(crate) fn bump_generation(&mut self) {
+        self.generation = next_generation();
+    }
+
+    /// Copy including the observers and generation, so it can replace this registry
+    fn copy(&self) -> Self {
+        TypeRegistry {
+            types: self.types.clone(),
+            names: self.names.clone(),
+            intrinsics: self.intrinsics.clone(),
+            templates: self.templates.clone(),
+            conflict_policy: self.conflict_policy,
+            observers: self.observers.clone(),
+            #[cfg(feature = "serde")]
+            serializers: self.serializers.clone(),
+            #[cfg(feature = "serde")]
+            deserializers: self.deserializers.clone(),
+            generation: self.generation
+        }
+    }
+}
+
+/// Copies the entries and [ConflictPolicy], but not the observers.
+/// The clone is a different registry, so it has a new generation
+impl Clone for TypeRegistry {
+    fn clone(&self) -> Self {
+        let mut registry = self.copy();
+        registry.observers = RegistryObservers::default();
+        registry.bump_generation();
+        registry
+    }
+}
+
+/// Full state of a [TypeRegistry], see [TypeRegistry::snapshot]
+#[derive(Debug, Clone)]
+pub struct TypeRegistrySnapshot(TypeRegistry);
+
+/// What happens when registering a type, type name, or intrinsic which conflicts with an existing entry
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum ConflictPolicy {
+    /// Keep the existing entry
+    KeepOld,
+    /// Replace the existing entry
+    #[default]
+    Replace,
+    /// Unify the existing type with the new one (see [RustType::try_unify]).
+    /// If they have conflicts, or the entry is a type name or intrinsic, this is [ConflictPolicy::Error]
+    Unify,
+    /// Keep the existing entry and fail (`try_register` returns the conflict)
+    Error
+}
+
+/// Registering something conflicts with an existing entry
+#[derive(Debug, Clone, Display, Error)]
+pub enum RegistryConflict {
+    /// Another type is registered with the same name and a different type id, layout, or structure
+    #[display(fmt = "rust type with name {} already registered with a different structure", "old.type_name.qualified()")]
+    Type { old: Box<RustType>, new: Box<RustType> },
+    /// Another name is registered for the type id
+    #[display(fmt = "rust type with id {:?} already registered with a different name: old={} new={}", type_id, "old.qualified()", "new.qualified()")]
+    TypeName { type_id: TypeId, old: Box<RustTypeName>, new: Box<RustTypeName> },
+    /// Another type id is registered for the name
+    #[display(fmt = "rust type with name {} already registered with a different id: old={:?} new={:?}", "type_name.qualified()", old, new)]
+    TypeId { type_name: Box<RustTypeName>, old: TypeId, new: TypeId },
+    /// Another intrinsic is registered for the type id
+    #[display(fmt = "intrinsic type with id {:?} already registered with a different name: old={} new={}", "old.type_id", "old.type_name", "new.type_name")]
+    Intrinsic { old: Box<IntrinsicRustType>, new: Box<IntrinsicRustType> }
+}
+
+fn is_same_registered_type(existing_type: &RustType, rust_type: &RustType) -> bool {
+    existing_type.type_id == rust_type.type_id &&
+        existing_type.size == rust_type.size &&
+        existing_type.align == rust_type.align &&
+        existing_type.structure == rust_type.structure
+}
+
+/// Events sent when the registered types change from `old_types` to `new_types`, sorted by name
+fn restore_events(mut old_types: HashMap<RustTypeName, Arc<RustType>>, new_types: &HashMap<RustTypeName, Arc<RustType>>) -> Vec<RegistryEvent> {
+    let mut events = new_types.iter().filter_map(|(type_name, new)| match old_types.remove(type_name) {
+        None => Some((type_name.clone(), RegistryEvent::Registered { new: new.clone() })),
+        Some(old) if !is_same_registered_type(&old, new) => Some((type_name.clone(), RegistryEvent::Replaced { old, new: new.clone() })),
+        Some(_) => None
+    }).collect::<Vec<_>>();
+    events.extend(old_types.into_iter().map(|(type_name, old)| (type_name, RegistryEvent::Unregistered { old })));
+    events.sort_by_cached_key(|(type_name, _)| type_name.qualified().to_string());
+    events.into_iter().map(|(_, event)| event).collect()
+}
+
+#[cfg(any(feature = "persist", feature = "serde"))]
+fn has_same_layout(rust_type: &RustType, registered: &RustType) -> bool {
+    match (&rust_type.structure, &registered.structure) {
+        // We can't compare the contents, but opaque values can only be created by the registered type's code
+        (TypeStructure::Opaque, TypeStructure::Opaque) => rust_type.size == registered.size && rust_type.align == registered.align,
+        _ => rust_type.is_layout_compatible_with(registered) == crate::LayoutCompat::Identical
+    }
+}
+
+pub(crate) fn log_conflict(conflict: Option<&RegistryConflict>) {
+    if let Some(conflict) = conflict {
+        error!("{}", conflict);
+    }
+}
+
+impl Default for TypeRegistry {
+    fn default() -> Self {
+        TypeRegistry::new()
+    }
+}
+
+/// Whether registering in the global registry would do nothing (so we can avoid copying it)
+fn is_registered_globally(rust_type: &RustType, intrinsic_rust_type: Option<&IntrinsicRustType>) -> bool {
+    TypeRegistry::with_global(|registry| registry.is_registered(rust_type, intrinsic_rust_type)).unwrap_or(false)
+}
+
+/// A generation no registry has had yet
+fn next_generation() -> u64 {
+    static NEXT_GENERATION: AtomicU64 = AtomicU64::new(0);
+    NEXT_GENERATION.fetch_add(1, AtomicOrdering::Relaxed)
+}
+
+/// Current generation of the global registry (see [TypeRegistry::generation])
+pub(crate) fn registry_generation() -> u64 {
+    TypeRegistry::with_global(TypeRegistry::generation).unwrap_or(0)
+}
+
+impl RustType {
+    /// Returns the type containing metadata of `T` and adds it to the given registry
+    /// (unlike [RustType::of], which adds it to the global registry)
+    pub fn of_in<T: HasStructure>(registry: &mut TypeRegistry) -> Self where T::StaticId: Sized {
+        let rust_type = RustType::of_dont_register::<T>();
+        registry.register(Cow::Borrowed(&rust_type), Some(IntrinsicRustType::of::<T>()));
+        rust_type
+    }
+
+    /// Returns the type of an array of `T` and the given length and adds it to the given registry
+    /// (unlike [RustType::of_array], which adds it to the global registry)
+    pub fn of_array_in<T: HasStructure>(len: usize, registry: &mut TypeRegistry) -> Self where T::StaticId: Sized {
+        let rust_type = RustType::of_array_dont_register::<T>(len);
+        let elem_type = rust_type.structure.array_elem_type_and_length().unwrap().0;
+        registry.register(Cow::Borrowed(elem_type), Some(IntrinsicRustType::of::<T>()));
+        registry.register(Cow::Borrowed(&rust_type), None);
+        rust_type
+    }
+
+    /// Returns the type of a slice of `T` and adds it to the given registry
+    /// (unlike [RustType::of_slice], which adds it to the global registry)
+    pub fn of_slice_in<T: HasStructure>(registry: &mut TypeRegistry) -> Self where T::StaticId: Sized {
+        let rust_type = RustType::of_slice_dont_register::<T>();
+        let elem_type = rust_type.structure.slice_elem_type().unwrap();
+        registry.register(Cow::Borrowed(elem_type), Some(IntrinsicRustType::of::<T>()));
+        registry.register(Cow::Borrowed(&rust_type), None);
+        rust_type
+    }
+
+    /// Registers the rust type in the global registry. See [TypeRegistry::register].
+    /// If it's already registered, the registry isn't modified.
+    pub fn register(rust_type: Cow<'_, RustType>, intrinsic_rust_type: Option<IntrinsicRustType>) {
+        if is_registered_globally(&rust_type, intrinsic_rust_type.as_ref()) {
+            return;
+        }
+        TypeRegistry::with_global_mut(|registry| registry.register(rust_type, intrinsic_rust_type));
+    }
+
+    /// Registers the rust type in the global registry, returning conflicts. See [TypeRegistry::try_register].
+    pub fn try_register(rust_type: Cow<'_, RustType>, intrinsic_rust_type: Option<IntrinsicRustType>) -> Result<(), RegistryConflict> {
+        if is_registered_globally(&rust_type, intrinsic_rust_type.as_ref()) {
+            return Ok(());
         }
+        TypeRegistry::with_global_mut(|registry| registry.try_register(rust_type, intrinsic_rust_type)).expect("with_global_mut always returns Some")
+    }
+
+    /// Index into the global type registry.
+    /// Returns the registered type with the given type name, or instantiates the matching template.
+    /// See [TypeRegistry::lookup_or_instantiate]
+    pub fn lookup(type_name: &RustTypeName) -> Option<Arc<RustType>> {
+        TypeRegistry::with_global(|registry| registry.lookup_or_instantiate(type_name)).flatten()
+    }
+
+    /// Index into the global type registry.
+    /// Returns the registered type with the given type id.
+    pub fn lookup_from_id(type_id: TypeId) -> Option<Arc<RustType>> {
+        TypeRegistry::with_global(|registry| registry.lookup_from_id(type_id).cloned()).flatten()
+    }
+
+    /// Unregisters the type with the given name from the global registry. See [TypeRegistry::unregister]
+    pub fn unregister(type_name: &RustTypeName) -> Option<Arc<RustType>> {
+        TypeRegistry::with_global_mut(|registry| registry.unregister(type_name)).flatten()
+    }
+
+    /// Unregisters the type with the given type id from the global registry. See [TypeRegistry::unregister_from_id]
+    pub fn unregister_from_id(type_id: TypeId) -> Option<Arc<RustType>> {
+        TypeRegistry::with_global_mut(|registry| registry.unregister_from_id(type_id)).flatten()
     }
 }
 
 impl RustTypeName {
-    /// Registers the type id to the type name.
-    ///
-    /// The provided type name is `Cow` so that it doesn't need to be cloned if already registered.
-    /// If another type id is registered with the same name or vice versa and they aren't equal, this will log an error.
+    /// Registers the type id to the type name in the global registry. See [TypeRegistry::register_type_name]
     pub fn register(type_id: TypeId, type_name: Cow<'_, RustTypeName>) {
-        if let Some(mut known_names) = catch_and_log!(KNOWN_NAMES.write(), "known rust type names poisoned") {
-            if let Some(existing_name) = known_names.get_by_left(&type_id) {
-                if existing_name != &*type_name {
-                    error!("rust type with id {:?} already registered with a different name: old={} new={}", type_id, existing_name.qualified(), type_name.qualified());
-                }
-            }
-            known_names.insert(type_id, type_name.into_owned());
+        if TypeRegistry::with_global(|registry| registry.is_type_name_registered(type_id, &type_name)).unwrap_or(false) {
+            return;
         }
+        TypeRegistry::with_global_mut(|registry| registry.register_type_name(type_id, type_name));
+    }
+
+    /// Registers the type id to the type name in the global registry, returning conflicts. See [TypeRegistry::try_register_type_name]
+    pub fn try_register(type_id: TypeId, type_name: Cow<'_, RustTypeName>) -> Result<(), RegistryConflict> {
+        TypeRegistry::with_global_mut(|registry| registry.try_register_type_name(type_id, type_name)).expect("with_global_mut always returns Some")
     }
 
-    /// Index into the type registry, which is a global singleton.
+    /// Index into the global type registry.
     /// Returns the registered type name with the given type id.
     pub fn lookup(type_id: TypeId) -> Option<RustTypeName> {
-        match catch_and_log!(KNOWN_NAMES.read(), "known rust type names poisoned") {
-            None => None,
-            Some(known_names) => known_names.get_by_left(&type_id).cloned()
-        }
+        TypeRegistry::with_global(|registry| registry.lookup_name(type_id).cloned()).flatten()
     }
 
-    /// Index into the type registry, which is a global singleton.
+    /// Index into the global type registry.
     /// Returns the registered type id with the given type name.
     pub fn lookup_back(&self) -> Option<TypeId> {
-        match catch_and_log!(KNOWN_NAMES.read(), "known rust type names poisoned") {
-            None => None,
-            Some(known_names) => known_names.get_by_right(self).copied()
-        }
+        TypeRegistry::with_global(|registry| registry.lookup_id(self)).flatten()
     }
 
-    /// Index into the type registry, which is a global singleton.
+    /// Index into the global type registry.
     /// Returns the registered intrinsic type with the given type name.
     pub fn lookup_back_intrinsic(&self) -> Option<IntrinsicRustType> {
-        self.lookup_back().and_then(IntrinsicRustType::lookup)
+        TypeRegistry::with_global(|registry| registry.lookup_intrinsic_from_name(self).cloned()).flatten()
     }
 }
 
 impl IntrinsicRustType {
-    /// Registers the intrinsic type so it can be looked up by id.
+    /// Registers the intrinsic type in the global registry so it can be looked up by id.
     pub fn register(intrinsic_type: IntrinsicRustType) {
-        if let Some(mut known_intrinsics) = catch_and_log!(KNOWN_INTRINSICS.write(), "known intrinsic types poisoned") {
-            if let Some(existing_intrinsic) = known_intrinsics.get(&intrinsic_type.type_id) {
-                if existing_intrinsic != &intrinsic_type {
-                    error!("intrinsic type with id {:?} already registered with a different name: old={} new={}", intrinsic_type.type_id, existing_intrinsic.type_name, intrinsic_type.type_name);
-                }
-            }
-            known_intrinsics.insert(intrinsic_type.type_id, intrinsic_type);
+        if TypeRegistry::with_global(|registry| registry.is_intrinsic_registered(&intrinsic_type)).unwrap_or(false) {
+            return;
         }
+        TypeRegistry::with_global_mut(|registry| registry.register_intrinsic(intrinsic_type));
+    }
+
+    /// Registers the intrinsic type in the global registry, returning conflicts. See [TypeRegistry::try_register_intrinsic]
+    pub fn try_register(intrinsic_type: IntrinsicRustType) -> Result<(), RegistryConflict> {
+        TypeRegistry::with_global_mut(|registry| registry.try_register_intrinsic(intrinsic_type)).expect("with_global_mut always returns Some")
     }
 
+    /// Index into the global type registry.
+    /// Returns the registered intrinsic type with the given type id.
     pub fn lookup(type_id: TypeId) -> Option<IntrinsicRustType> {
-        match catch_and_log!(KNOWN_INTRINSICS.read(), "known intrinsic types poisoned") {
-            None => None,
-            Some(known_intrinsics) => known_intrinsics.get(&type_id).cloned()
-        }
+        TypeRegistry::with_global(|registry| registry.lookup_intrinsic(type_id).cloned()).flatten()
     }
 }
 
 // region builtins
-impl RustType {
-    fn register_builtins(builtins: &mut HashMap<RustTypeName, RustType>) {
-        RustType::register_builtin::<String>(builtins, "String");
-        RustType::register_builtin::<Box<UnknownIntrinsicType>>(builtins, "Box<{unknown}>");
-        RustType::register_builtin::<Vec<UnknownIntrinsicType>>(builtins, "Vec<{unknown}>");
-        RustType::register_builtin::<VecDeque<UnknownIntrinsicType>>(builtins, "VecDeque<{unknown}>");
-        RustType::register_builtin::<BTreeSet<UnknownIntrinsicType>>(builtins, "BTreeSet<{unknown}>");
-        RustType::register_builtin::<HashSet<UnknownIntrinsicType>>(builtins, "HashSet<{unknown}>");
-        RustType::register_builtin::<BTreeMap<UnknownIntrinsicType, UnknownIntrinsicType>>(builtins, "BTreeSet<{unknown}, {unknown}>");
-        RustType::register_builtin::<HashMap<UnknownIntrinsicType, UnknownIntrinsicType>>(builtins, "HashSet<{unknown}, {unknown}>");
-    }
-
-    fn register_builtin<T: 'static>(builtins: &mut HashMap<RustTypeName, RustType>, name: &str) {
+impl TypeRegistry {
+    fn register_builtins(&mut self) {
+        self.register_builtin::<String>("std::string::String");
+        self.register_builtin::<OsString>("std::ffi::OsString");
+        self.register_builtin::<Duration>("std::time::Duration");
+        self.register_builtin::<Ordering>("std::cmp::Ordering");
+        self.register_builtin::<RangeFull>("std::ops::RangeFull");
+        // Generic types whose layout doesn't depend on their (sized) generic arguments
+        self.register_builtin::<Box<UnknownIntrinsicType>>("std::boxed::Box<{unknown}>");
+        self.register_builtin::<Rc<UnknownIntrinsicType>>("std::rc::Rc<{unknown}>");
+        self.register_builtin::<Arc<UnknownIntrinsicType>>("std::sync::Arc<{unknown}>");
+        self.register_builtin::<NonNull<UnknownIntrinsicType>>("std::ptr::NonNull<{unknown}>");
+        self.register_builtin::<Vec<UnknownIntrinsicType>>("std::vec::Vec<{unknown}>");
+        self.register_builtin::<VecDeque<UnknownIntrinsicType>>("std::collections::VecDeque<{unknown}>");
+        self.register_builtin::<BTreeSet<UnknownIntrinsicType>>("std::collections::BTreeSet<{unknown}>");
+        self.register_builtin::<HashSet<UnknownIntrinsicType>>("std::collections::HashSet<{unknown}>");
+        self.register_builtin::<BTreeMap<UnknownIntrinsicType, UnknownIntrinsicType>>("std::collections::BTreeMap<{unknown}, {unknown}>");
+        self.register_builtin::<HashMap<UnknownIntrinsicType, UnknownIntrinsicType>>("std::collections::HashMap<{unknown}, {unknown}>");
+        for erased_name in [
+            "std::boxed::Box<{unknown}>",
+            "std::rc::Rc<{unknown}>",
+            "std::sync::Arc<{unknown}>",
+            "std::ptr::NonNull<{unknown}>",
+            "std::vec::Vec<{unknown}>",
+            "std::collections::VecDeque<{unknown}>",
+            "std::collections::BTreeSet<{unknown}>",
+            "std::collections::HashSet<{unknown}>",
+            "std::collections::BTreeMap<{unknown}, {unknown}>",
+            "std::collections::HashMap<{unknown}, {unknown}>"
+        ] {
+            self.register_builtin_template(erased_name);
+        }
+        // Generic types whose layout depends on their generic arguments. The erased types have an unknown layout
+        for erased_name in [
+            "std::cell::RefCell<{unknown}>",
+            "std::sync::Mutex<{unknown}>",
+            "std::option::Option<{unknown}>",
+            "std::result::Result<{unknown}, {unknown}>",
+            "std::borrow::Cow<{unknown}>",
+            "std::ops::Range<{unknown}>",
+            "std::ops::RangeInclusive<{unknown}>",
+            "std::ops::RangeFrom<{unknown}>",
+            "std::ops::RangeTo<{unknown}>",
+            "std::ops::RangeToInclusive<{unknown}>"
+        ] {
+            self.register_builtin_erased(erased_name);
+        }
+        self.register_builtin_layout_template("std::cell::RefCell<T>", ref_cell_layout);
+        self.register_builtin_layout_template("std::sync::Mutex<T>", mutex_layout);
+        self.register_builtin_layout_template("std::option::Option<T>", option_layout);
+        self.register_builtin_layout_template("std::result::Result<T, E>", result_layout);
+        self.register_builtin_layout_template("std::borrow::Cow<B>", cow_layout);
+        self.register_builtin_layout_template("std::ops::Range<Idx>", range_layout);
+        self.register_builtin_layout_template("std::ops::RangeInclusive<Idx>", range_inclusive_layout);
+        self.register_builtin_layout_template("std::ops::RangeFrom<Idx>", range_bound_layout);
+        self.register_builtin_layout_template("std::ops::RangeTo<Idx>", range_bound_layout);
+        self.register_builtin_layout_template("std::ops::RangeToInclusive<Idx>", range_bound_layout);
+        // Generic types with the same layout as their generic argument
+        self.register_builtin_wrapper_template("std::cell::Cell<T>", true);
+        self.register_builtin_wrapper_template("std::mem::ManuallyDrop<T>", true);
+        self.register_builtin_wrapper_template("std::mem::MaybeUninit<T>", false);
+        #[cfg(feature = "serde")]
+        {
+            self.register_serializer::<String>();
+            self.register_deserializer::<String>();
+        }
+    }
+
+    fn register_builtin<T: 'static>(&mut self, name: &str) {
         let type_name = RustTypeName::try_from(name).expect("bad builtin name");
         let intrinsic = IntrinsicRustType::of_static::<T>();
         let rust_type = RustType {
@@ -159,28 +808,30 @@ impl RustType {
             align: intrinsic.align,
             structure: TypeStructure::Opaque
         };
-        let old = builtins.insert(type_name.clone(), rust_type);
+        let old = self.types.insert(type_name.clone(), Arc::new(rust_type));
         if old.is_some() {
             panic!("builtin type {} already registered", type_name.qualified());
         }
 
-        // We can access and load the other lazy members here
-        RustTypeName::register(intrinsic.type_id, Cow::Owned(type_name));
-        IntrinsicRustType::register(intrinsic);
+        self.register_type_name(intrinsic.type_id, Cow::Owned(type_name));
+        self.register_intrinsic(intrinsic);
     }
-}
-// endregion
 
-// region misc
-/// Instead of propagating this error, just log it as an error.
-/// Useful for non-essential failures like the file watcher
-macro catch_and_log($e:expr, $msg:literal $(, $args:expr)*) {
-match $e {
-    Ok(v) => Some(v),
-    Err(e) => {
-        log::error!(concat!($msg, ": {}") $(, $args)*, e);
-        None
+    /// Registers an erased generic type (e.g. `Option<{unknown}>`) whose layout depends on its generic arguments,
+    /// so it's unknown
+    fn register_builtin_erased(&mut self, name: &str) {
+        let type_name = RustTypeName::try_from(name).expect("bad builtin name");
+        let rust_type = RustType {
+            type_id: None,
+            type_name: type_name.clone(),
+            size: usize::MAX,
+            align: usize::MAX,
+            structure: TypeStructure::Opaque
+        };
+        let old = self.types.insert(type_name.clone(), Arc::new(rust_type));
+        if old.is_some() {
+            panic!("builtin type {} already registered", type_name.qualified());
+        }
     }
 }
-}
-// endregion
\ No newline at end of file
+// endregion
@@ -0,0 +1,120 @@
+use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
+use crate::{RustType, TypeRegistry};
+
+/// A registered type changed, see [TypeRegistry::subscribe]
+#[derive(Debug, Clone)]
+pub enum RegistryEvent {
+    /// A type was registered with a new name
+    Registered { new: Arc<RustType> },
+    /// A registered type was replaced (or unified, see [ConflictPolicy](crate::ConflictPolicy))
+    Replaced { old: Arc<RustType>, new: Arc<RustType> },
+    /// A type was unregistered
+    Unregistered { old: Arc<RustType> }
+}
+
+/// Identifies a subscribed observer so it can be unsubscribed, see [TypeRegistry::subscribe]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RegistryObserverId(u64);
+
+type ObserverFn = dyn Fn(&RegistryEvent) + Send + Sync;
+
+/// Subscribed observers and, while the global registry is locked, the events they haven't been sent yet
+#[derive(Clone, Default)]
+pub(crate) struct RegistryObservers {
+    observers: Vec<(RegistryObserverId, Arc<ObserverFn>)>,
+    next_id: u64,
+    deferred: Option<Vec<RegistryEvent>>
+}
+
+/// Events which were deferred until the global registry was unlocked, and the observers to send them to
+#[must_use = "the events aren't sent until dispatched"]
+pub(crate) struct DeferredRegistryEvents {
+    events: Vec<RegistryEvent>,
+    observers: Vec<Arc<ObserverFn>>
+}
+
+impl TypeRegistry {
+    /// Calls `observer` whenever a type is registered, replaced, or unregistered (including by
+    /// [TypeRegistry::restore]). Type names and intrinsics alone don't send events.
+    ///
+    /// For the global registry, observers are called after the registry is unlocked, so they can access it.
+    /// Other registries call observers immediately, while they are still mutably borrowed.
+    pub fn subscribe(&mut self, observer: impl Fn(&RegistryEvent) + Send + Sync + 'static) -> RegistryObserverId {
+        let id = RegistryObserverId(self.observers.next_id);
+        self.observers.next_id += 1;
+        self.observers.observers.push((id, Arc::new(observer)));
+        id
+    }
+
+    /// Stop calling the observer. Returns `false` if it wasn't subscribed
+    pub fn unsubscribe(&mut self, id: RegistryObserverId) -> bool {
+        let num_observers = self.observers.observers.len();
+        self.observers.observers.retain(|(observer_id, _)| *observer_id != id);
+        self.observers.observers.len() != num_observers
+    }
+
+    /// [TypeRegistry::subscribe] to the global registry
+    pub fn subscribe_global(observer: impl Fn(&RegistryEvent) + Send + Sync + 'static) -> Option<RegistryObserverId> {
+        TypeRegistry::with_global_mut(|registry| registry.subscribe(observer))
+    }
+
+    /// [TypeRegistry::unsubscribe] from the global registry
+    pub fn unsubscribe_global(id: RegistryObserverId) -> bool {
+        TypeRegistry::with_global_mut(|registry| registry.unsubscribe(id)).unwrap_or(false)
+    }
+
+    /// Send the event to observers (or defer it). `event` is only called if there are observers
+    pub(crate) fn emit(&mut self, event: impl FnOnce() -> RegistryEvent) {
+        if self.observers.is_empty() {
+            return;
+        }
+        let event = event();
+        match &mut self.observers.deferred {
+            None => for (_, observer) in &self.observers.observers {
+                observer(&event);
+            },
+            Some(deferred) => deferred.push(event)
+        }
+    }
+}
+
+impl RegistryObservers {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.observers.is_empty()
+    }
+
+    /// Defer events until [RegistryObservers::end_deferring]
+    pub(crate) fn begin_deferring(&mut self) {
+        self.deferred = Some(Vec::new());
+    }
+
+    /// Stop deferring events and return the deferred ones, which must be dispatched
+    pub(crate) fn end_deferring(&mut self) -> DeferredRegistryEvents {
+        let events = self.deferred.take().unwrap_or_default();
+        let observers = match events.is_empty() {
+            true => Vec::new(),
+            false => self.observers.iter().map(|(_, observer)| observer.clone()).collect()
+        };
+        DeferredRegistryEvents { events, observers }
+    }
+}
+
+impl DeferredRegistryEvents {
+    pub(crate) fn dispatch(self) {
+        for event in &self.events {
+            for observer in &self.observers {
+                observer(event);
+            }
+        }
+    }
+}
+
+impl Debug for RegistryObservers {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RegistryObservers")
+            .field("observers", &self.observers.iter().map(|(id, _)| id).collect::<Vec<_>>())
+            .field("deferred", &self.deferred)
+            .finish()
+    }
+}
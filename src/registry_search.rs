@@ -0,0 +1,138 @@
+use std::sync::Arc;
+use crate::{PrimitiveType, RustType, RustTypeName, TypeRegistry};
+
+impl TypeRegistry {
+    /// Returns all registered types whose name is an identifier with the given simple name,
+    /// with any qualifier or generic arguments, sorted by qualified name.
+    pub fn lookup_simple(&self, simple_name: &str) -> Vec<&Arc<RustType>> {
+        let mut candidates = self.types()
+            .filter(|rust_type| matches!(
+                &rust_type.type_name,
+                RustTypeName::Ident { simple_name: candidate_simple_name, .. } if candidate_simple_name == simple_name
+            ))
+            .collect::<Vec<_>>();
+        candidates.sort_by_cached_key(|rust_type| rust_type.type_name.qualified().to_string());
+        candidates
+    }
+
+    /// Returns all registered types whose names match the given name, where identifiers in the given
+    /// name without qualifiers match any qualifier (see [RustTypeName::matches_partial]),
+    /// sorted by qualified name.
+    ///
+    /// If none are registered, but the name has a unique full name using templates (e.g. `Vec<Point>`,
+    /// where only `geo::Point` is registered), this returns its instantiation
+    /// (see [TypeRegistry::lookup_or_instantiate]).
+    pub fn lookup_partial(&self, type_name: &RustTypeName) -> Vec<Arc<RustType>> {
+        let mut candidates = self.types()
+            .filter(|rust_type| type_name.matches_partial(&rust_type.type_name))
+            .cloned()
+            .collect::<Vec<_>>();
+        if candidates.is_empty() {
+            candidates.extend(self.resolve_partial_name(type_name).and_then(|full_name| {
+                self.lookup_or_instantiate(&full_name)
+            }));
+        }
+        candidates.sort_by_cached_key(|rust_type| rust_type.type_name.qualified().to_string());
+        candidates
+    }
+
+    /// The unique full name of a registered type, primitive, or template instantiation which the
+    /// given name partially matches, if there is exactly one
+    fn resolve_partial_name(&self, type_name: &RustTypeName) -> Option<RustTypeName> {
+        let mut registered = self.types().filter(|rust_type| type_name.matches_partial(&rust_type.type_name));
+        if let Some(rust_type) = registered.next() {
+            return match registered.next() {
+                None => Some(rust_type.type_name.clone()),
+                Some(_) => None
+            };
+        }
+        let RustTypeName::Ident { qualifier, simple_name, generic_args } = type_name else {
+            return None;
+        };
+        if generic_args.is_empty() {
+            return (qualifier.is_local() && PrimitiveType::from_name(simple_name).is_some()).then(|| type_name.clone());
+        }
+        let mut templates = self.templates()
+            .map(|template| template.key())
+            .filter(|(template_qualifier, template_simple_name, num_params)| {
+                (qualifier.is_local() || qualifier == template_qualifier) &&
+                    simple_name == template_simple_name &&
+                    generic_args.len() == *num_params
+            });
+        let (template_qualifier, _, _) = templates.next()?;
+        if templates.next().is_some() {
+            return None;
+        }
+        Some(RustTypeName::Ident {
+            qualifier: template_qualifier,
+            simple_name: simple_name.clone(),
+            generic_args: generic_args.iter()
+                .map(|generic_arg| self.resolve_partial_name(generic_arg))
+                .collect::<Option<Vec<_>>>()?
+        })
+    }
+
+    /// Registered type names similar to `name` (which may not parse), for "did you mean" messages.
+    ///
+    /// Names are compared qualified and unqualified by edit distance, and returned closest first,
+    /// at most `limit`.
+    pub fn suggest_names(&self, name: &str, limit: usize) -> Vec<&RustTypeName> {
+        let name = name.trim();
+        let max_distance = (name.chars().count() / 3).max(1);
+        let mut suggestions = self.types()
+            .map(|rust_type| &rust_type.type_name)
+            .filter(|type_name| !type_name.is_anonymous())
+            .filter_map(|type_name| {
+                let qualified = type_name.qualified().to_string();
+                let distance = edit_distance(name, &qualified).min(edit_distance(name, &type_name.unqualified().to_string()));
+                (distance <= max_distance).then_some((distance, qualified, type_name))
+            })
+            .collect::<Vec<_>>();
+        suggestions.sort_by(|(distance, qualified, _), (other_distance, other_qualified, _)| {
+            distance.cmp(other_distance).then_with(|| qualified.cmp(other_qualified))
+        });
+        suggestions.into_iter().take(limit).map(|(_, _, type_name)| type_name).collect()
+    }
+}
+
+impl RustType {
+    /// Index into the global type registry.
+    /// Returns all registered types with the given simple name. See [TypeRegistry::lookup_simple]
+    pub fn lookup_simple(simple_name: &str) -> Vec<Arc<RustType>> {
+        TypeRegistry::with_global(|registry| {
+            registry.lookup_simple(simple_name).into_iter().cloned().collect()
+        }).unwrap_or_default()
+    }
+
+    /// Index into the global type registry.
+    /// Returns all registered types which partially match the given name. See [TypeRegistry::lookup_partial]
+    pub fn lookup_partial(type_name: &RustTypeName) -> Vec<Arc<RustType>> {
+        TypeRegistry::with_global(|registry| registry.lookup_partial(type_name)).unwrap_or_default()
+    }
+}
+
+impl RustTypeName {
+    /// Index into the global type registry.
+    /// Returns registered type names similar to `name`. See [TypeRegistry::suggest_names]
+    pub fn suggest(name: &str, limit: usize) -> Vec<RustTypeName> {
+        TypeRegistry::with_global(|registry| {
+            registry.suggest_names(name, limit).into_iter().cloned().collect()
+        }).unwrap_or_default()
+    }
+}
+
+/// Levenshtein distance between the strings' characters
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut prev_row = (0..=b.len()).collect::<Vec<_>>();
+    let mut row = vec![0; b.len() + 1];
+    for (i, a_char) in a.chars().enumerate() {
+        row[0] = i + 1;
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution_cost = if a_char == *b_char { 0 } else { 1 };
+            row[j + 1] = (prev_row[j] + substitution_cost).min(prev_row[j + 1] + 1).min(row[j] + 1);
+        }
+        std::mem::swap(&mut prev_row, &mut row);
+    }
+    prev_row[b.len()]
+}
@@ -10,15 +10,17 @@ impl TypeStructure {
             TypeStructure::OpaqueFields { fields } => Some(infer_c_tuple_size(fields.iter().map(|field| &field.rust_type))),
             TypeStructure::Primitive(primitive) => Some(primitive.size()),
             TypeStructure::CReprEnum { variants } => {
-                let discriminant_size = discriminant_size(variants.len());
+                // Discriminant followed by a union of the variants
+                let payload_offset = infer_enum_payload_offset(variants);
                 let data_size = variants.iter().map(|variant| variant.infer_size()).max().unwrap_or(0);
-                Some(discriminant_size + data_size)
+                Some(align_up(payload_offset + data_size, self.infer_align().unwrap()))
             }
             TypeStructure::CReprStruct { body } => Some(body.infer_size()),
             TypeStructure::Pointer { ptr_size, .. } => Some(*ptr_size),
             TypeStructure::CTuple { elements } => Some(infer_c_tuple_size(elements)),
             TypeStructure::Array { elem, length } => Some(infer_array_size(elem, *length)),
-            TypeStructure::Slice { .. } => None
+            TypeStructure::Slice { .. } => None,
+            TypeStructure::Var(_) => None
         }
     }
 
@@ -37,7 +39,8 @@ impl TypeStructure {
             TypeStructure::Pointer { .. } => Some(align_of::<*const ()>()),
             TypeStructure::CTuple { elements } => Some(infer_c_tuple_align(elements)),
             TypeStructure::Array { elem, length: _ } => Some(infer_slice_align(elem)),
-            TypeStructure::Slice { elem } => Some(infer_slice_align(elem))
+            TypeStructure::Slice { elem } => Some(infer_slice_align(elem)),
+            TypeStructure::Var(_) => None
         }
     }
 }
@@ -53,7 +56,7 @@ impl TypeEnumVariant {
 }
 
 impl TypeStructureBody {
-    fn infer_size(&self) -> usize {
+    pub(crate) fn infer_size(&self) -> usize {
         match self {
             TypeStructureBody::None => 0,
             TypeStructureBody::Tuple(elems) => infer_c_tuple_size(elems),
@@ -149,7 +152,19 @@ pub fn infer_slice_offsets(elem: &RustType) -> impl Iterator<Item=usize> {
     (0..).map(move |i| i * aligned_size)
 }
 
-fn discriminant_size(_num_discriminants: usize) -> usize {
+/// Offset of the variant data in a `#[repr(C)]` enum with the given variants:
+/// after the discriminant, aligned to the largest variant alignment
+pub fn infer_enum_payload_offset(variants: &[TypeEnumVariant]) -> usize {
+    let discriminant_size = discriminant_size(variants.len());
+    let data_align = variants.iter().map(|variant| variant.infer_align()).max().unwrap_or(0);
+    if data_align == 0 {
+        discriminant_size
+    } else {
+        align_up(discriminant_size, data_align)
+    }
+}
+
+pub(crate) fn discriminant_size(_num_discriminants: usize) -> usize {
     // "but it selects the same size as the C compiler would use for the given target for an equivalent C-enum declaration"
     // I have no idea if this is correct. C is defined to represent enums as ints. I know this is wrong on systems where int != 4 bytes,
     // but don't know how to detect that.
@@ -1,10 +1,11 @@
 use std::any::TypeId;
 use std::fmt::{Display, Formatter};
 use std::iter::{empty, repeat, repeat_n};
-use crate::{PrimitiveType, RustPointerKind, RustType, RustTypeName};
+use crate::{PrimitiveType, RustPointerKind, RustType, RustTypeName, TypeVarId};
 use auto_enums::auto_enum;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum TypeStructure {
     /// Structure is unknown or cannot be interpreted structurally
     Opaque,
@@ -26,12 +27,20 @@ pub enum TypeStructure {
     Array { elem: Box<RustType>, length: usize },
     /// Array with unknown length
     Slice { elem: Box<RustType> },
+    /// Type variable: stands for a type which isn't known yet, but is the same type everywhere
+    /// the variable occurs. See [InferenceContext](crate::InferenceContext).
+    /// With the `serde` feature, serializing fails: variables only mean something within their context
+    #[cfg_attr(feature = "serde", serde(skip_deserializing, serialize_with = "crate::type_serde::serialize_var"))]
+    Var(TypeVarId),
     /// Pointer
     Pointer {
         /// Pointer kind, as mutable pointers are not subtypes of immutable pointers and raw pointers are not subtypes of references
         ptr_kind: RustPointerKind,
         /// Size of pointer including metadata. For thin pointers this is `size_of::<*const ()>()`
         ptr_size: usize,
+        /// With the `serde` feature, this isn't serialized: when deserializing the pointer's [RustType],
+        /// it's the id of the registered type named `refd_name`
+        #[cfg_attr(feature = "serde", serde(skip))]
         refd_id: Option<TypeId>,
         /// Remember: we don't need refd structure because it doesn't affect the pointer size.
         refd_name: RustTypeName
@@ -40,6 +49,7 @@ pub enum TypeStructure {
 
 /// Enum variant
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TypeEnumVariant {
     pub variant_name: String,
     pub body: TypeStructureBody
@@ -47,6 +57,7 @@ pub struct TypeEnumVariant {
 
 /// Struct or enum variant body
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum TypeStructureBody {
     /// This is a unit struct or variant without associated values
     None,
@@ -66,6 +77,7 @@ pub enum TypeStructureBodyForm {
 
 /// Field in a field struct or variant
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TypeStructureBodyField {
     pub name: String,
     pub rust_type: RustType
@@ -73,6 +85,7 @@ pub struct TypeStructureBodyField {
 
 /// Is lhs a subtype of rhs?
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum IsSubtypeOf {
     /// No
     No,
@@ -82,6 +95,69 @@ pub enum IsSubtypeOf {
     Yes,
 }
 
+/// How pointer subtyping treats the pointees
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum PointerVariance {
+    /// Pointees must be the same type (same type id if both have one, otherwise same name)
+    #[default]
+    Invariant,
+    /// Shared references and `*const` pointers are covariant in the pointee:
+    /// `&Derived` is a subtype of `&Base` if `Derived` is a structural subtype of `Base` whose layout starts with `Base`'s.
+    /// `&mut` and `*mut` pointers stay invariant.
+    Covariant
+}
+
+/// Which rules structural subtyping and unification follow.
+/// The default is the behavior described in [TypeStructure::is_structural_subtype_of].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SubtypePolicy {
+    /// Field structures may have extra fields (`self` has fields which `other` doesn't)
+    pub allow_extra_fields: bool,
+    /// Enums may be missing variants (`other` has variants which `self` doesn't)
+    pub allow_missing_variants: bool,
+    /// Arrays are subtypes of slices with the same element
+    pub array_to_slice: bool,
+    /// Primitives are subtypes of primitives they losslessly convert to, e.g. `u8 <: u32` and `i32 <: f64`
+    /// (see [PrimitiveType::widens_to](crate::PrimitiveType::widens_to))
+    pub numeric_widening: bool,
+    /// Fields are matched by position instead of by name (names are ignored)
+    pub fields_by_position: bool,
+    /// How pointers treat their pointees
+    pub pointer_variance: PointerVariance
+}
+
+impl SubtypePolicy {
+    /// The strictest policy: no extra fields or missing variants, no array-to-slice coercion
+    /// or numeric widening, fields are matched by position, and pointers are invariant.
+    ///
+    /// This still isn't exact type equality: pointer kinds still have subtypes (e.g. `&mut T` is a subtype of `&T`),
+    /// and opaque tuples and fields still match any structure with compatible elements.
+    /// To check whether a value can be reinterpreted (e.g. across FFI), use [RustType::is_layout_compatible_with].
+    pub fn exact() -> Self {
+        SubtypePolicy {
+            allow_extra_fields: false,
+            allow_missing_variants: false,
+            array_to_slice: false,
+            numeric_widening: false,
+            fields_by_position: true,
+            pointer_variance: PointerVariance::Invariant
+        }
+    }
+}
+
+impl Default for SubtypePolicy {
+    fn default() -> Self {
+        SubtypePolicy {
+            allow_extra_fields: true,
+            allow_missing_variants: true,
+            array_to_slice: true,
+            numeric_widening: false,
+            fields_by_position: false,
+            pointer_variance: PointerVariance::default()
+        }
+    }
+}
+
 impl TypeStructure {
     /// - If `TypeStructureBody::Unit`, returns `TypeStructure::Opaque`
     /// - If `TypeStructureBody::Tuple`, returns `TypeStructure::OpaqueTuple`
@@ -248,6 +324,23 @@ impl TypeStructure {
         };
         Some(result)
     }
+
+    /// Short description of what kind of structure this is, e.g. "struct" or "pointer"
+    pub fn kind_name(&self) -> &'static str {
+        match self {
+            TypeStructure::Opaque => "opaque type",
+            TypeStructure::OpaqueTuple { .. } => "tuple-like",
+            TypeStructure::OpaqueFields { .. } => "struct-like",
+            TypeStructure::Primitive(_) => "primitive",
+            TypeStructure::CReprEnum { .. } => "enum",
+            TypeStructure::CReprStruct { .. } => "struct",
+            TypeStructure::CTuple { .. } => "tuple",
+            TypeStructure::Array { .. } => "array",
+            TypeStructure::Slice { .. } => "slice",
+            TypeStructure::Var(_) => "type variable",
+            TypeStructure::Pointer { .. } => "pointer"
+        }
+    }
 }
 
 impl TypeStructureBody {
@@ -268,7 +361,7 @@ impl TypeStructureBody {
     }
 
     #[auto_enum(Iterator)]
-    fn general_compound_elem_types(&self) -> impl Iterator<Item=&RustType> {
+    pub(crate) fn general_compound_elem_types(&self) -> impl Iterator<Item=&RustType> {
         match self {
             TypeStructureBody::Tuple(tuple_items) => tuple_items.iter(),
             TypeStructureBody::Fields(fields) => fields.iter().map(|field| &field.rust_type),
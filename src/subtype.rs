@@ -1,6 +1,161 @@
+use std::any::TypeId;
 use std::iter::zip;
-use crate::{RustPointerKind, RustType, TypeEnumVariant, TypeStructureBody, TypeStructureBodyField};
-use crate::structure::{IsSubtypeOf, TypeStructure};
+#[cfg(not(feature = "registry"))]
+use std::marker::PhantomData;
+use std::sync::Arc;
+use crate::{RustPointerKind, SubtypeCache, RustType, RustTypeName, SubtypeReport, SubtypeRule, TypeEnumVariant, TypePath, TypePathElem, TypeStructureBody, TypeStructureBodyField, TypeStructureBodyForm, UnifyConflict, UnifyConflictKind, UnifyConflicts};
+use crate::subtype_cache::SubtypeCacheLookup;
+#[cfg(feature = "registry")]
+use crate::TypeRegistry;
+use crate::structure::{IsSubtypeOf, PointerVariance, SubtypePolicy, TypeStructure};
+
+/// State threaded through a single subtype check
+pub(crate) struct SubtypeCx<'a> {
+    policy: SubtypePolicy,
+    /// Pointee pairs currently being compared, and the cache depth when they started.
+    /// Comparing one of them again means the pointees are recursive, in which case we assume they
+    /// are subtypes (the other parts decide the result)
+    visiting_pointees: Vec<(RustTypeName, RustTypeName, usize)>,
+    cache: Option<&'a mut SubtypeCache>,
+    /// Whether failed checks report the path and rule, or only the result
+    explain: bool,
+    /// Where pointees are looked up, or `None` for the global registry
+    #[cfg(feature = "registry")]
+    registry: Option<&'a TypeRegistry>
+}
+
+impl<'a> SubtypeCx<'a> {
+    fn new(policy: SubtypePolicy) -> Self {
+        SubtypeCx {
+            policy,
+            visiting_pointees: Vec::new(),
+            cache: None,
+            explain: true,
+            #[cfg(feature = "registry")]
+            registry: None
+        }
+    }
+
+    /// Check with the cache's policy, consulting and filling the cache.
+    /// If `explain` is false, only compute the result (see [SubtypeCx::result_only])
+    pub(crate) fn with_cache(cache: &'a mut SubtypeCache, explain: bool) -> Self {
+        SubtypeCx {
+            policy: cache.policy(),
+            visiting_pointees: Vec::new(),
+            cache: Some(cache),
+            explain,
+            #[cfg(feature = "registry")]
+            registry: None
+        }
+    }
+
+    /// Look up pointees in `registry` instead of the global registry
+    #[cfg(feature = "registry")]
+    pub(crate) fn in_registry(self, registry: &'a TypeRegistry) -> Self {
+        SubtypeCx { registry: Some(registry), ..self }
+    }
+
+    /// Only compute the result, not where and why the check failed
+    fn result_only(self) -> Self {
+        SubtypeCx { explain: false, ..self }
+    }
+
+    /// `self` isn't a subtype because of `rule` (which is only created if we explain failures)
+    fn no(&self, rule: impl FnOnce() -> SubtypeRule) -> SubtypeReport {
+        SubtypeReport::failed(IsSubtypeOf::No, self.explain, rule)
+    }
+
+    /// We don't know if `self` is a subtype because of `rule` (which is only created if we explain failures)
+    fn unknown(&self, rule: impl FnOnce() -> SubtypeRule) -> SubtypeReport {
+        SubtypeReport::failed(IsSubtypeOf::Unknown, self.explain, rule)
+    }
+}
+
+/// State threaded through a single unification
+struct UnifyCx<'a> {
+    policy: SubtypePolicy,
+    /// Path to the types currently being unified
+    path: TypePath,
+    /// `None` if we don't record conflicts (plain `unify`)
+    conflicts: Option<Vec<UnifyConflict>>,
+    /// Where pointees are looked up, or `None` for the global registry
+    #[cfg(feature = "registry")]
+    registry: Option<&'a TypeRegistry>,
+    #[cfg(not(feature = "registry"))]
+    registry: PhantomData<&'a ()>
+}
+
+impl<'a> UnifyCx<'a> {
+    fn new(policy: SubtypePolicy, record_conflicts: bool) -> Self {
+        UnifyCx {
+            policy,
+            path: TypePath::empty(),
+            conflicts: if record_conflicts { Some(Vec::new()) } else { None },
+            #[cfg(feature = "registry")]
+            registry: None,
+            #[cfg(not(feature = "registry"))]
+            registry: PhantomData
+        }
+    }
+
+    /// Look up pointees in `registry` instead of the global registry
+    #[cfg(feature = "registry")]
+    fn in_registry(self, registry: &'a TypeRegistry) -> Self {
+        UnifyCx { registry: Some(registry), ..self }
+    }
+
+    /// Context for subtype checks during the unification, which only need the result
+    fn subtype_cx(&self) -> SubtypeCx<'a> {
+        let subtype_cx = SubtypeCx::new(self.policy).result_only();
+        #[cfg(feature = "registry")]
+        if let Some(registry) = self.registry {
+            return subtype_cx.in_registry(registry);
+        }
+        subtype_cx
+    }
+
+    fn records_conflicts(&self) -> bool {
+        self.conflicts.is_some()
+    }
+
+    fn conflict(&mut self, kind: UnifyConflictKind) {
+        if let Some(conflicts) = &mut self.conflicts {
+            conflicts.push(UnifyConflict {
+                path: self.path.clone(),
+                kind
+            });
+        }
+    }
+
+    fn kind_conflict(&mut self, kind: &'static str, other: &TypeStructure) {
+        self.conflict(UnifyConflictKind::KindMismatch {
+            kind,
+            other_kind: other.kind_name()
+        });
+    }
+
+    fn body_form_conflict(&mut self, form: TypeStructureBodyForm, other: &TypeStructureBody) {
+        self.conflict(UnifyConflictKind::BodyFormMismatch {
+            form,
+            other_form: other.form()
+        });
+    }
+
+    /// Run `f` on the nested types at `elem`
+    fn nested<R>(&mut self, elem: TypePathElem, f: impl FnOnce(&mut Self) -> R) -> R {
+        self.path.push(elem);
+        let result = f(self);
+        self.path.pop();
+        result
+    }
+
+    fn into_result(self) -> Result<(), UnifyConflicts> {
+        match self.conflicts {
+            Some(conflicts) if !conflicts.is_empty() => Err(UnifyConflicts(conflicts)),
+            _ => Ok(())
+        }
+    }
+}
 
 impl RustType {
     /// Returns true if a value of this type can be casted to the other type,
@@ -10,15 +165,75 @@ impl RustType {
     /// - Otherwise, check that one type is a structural subtype of another (see [TypeStructure::is_structural_subtype_of])
     ///
     /// If you want to compare rust types for actual structural subtyping, use [RustType::is_structural_subtype_of].
+    /// If you want to know why this isn't a subtype, use [RustType::explain_subtype].
     pub fn is_rough_subtype_of(&self, other: &RustType) -> IsSubtypeOf {
+        self.is_rough_subtype_of_with_policy(other, SubtypePolicy::default())
+    }
+
+    /// [RustType::is_rough_subtype_of] with the given policy (see [SubtypePolicy])
+    pub fn is_rough_subtype_of_with_policy(&self, other: &RustType, policy: SubtypePolicy) -> IsSubtypeOf {
+        self.is_rough_subtype_of_in(other, &mut SubtypeCx::new(policy).result_only()).result
+    }
+
+    /// [RustType::is_rough_subtype_of] with the given pointer variance and otherwise the default policy
+    #[deprecated(note = "use `is_rough_subtype_of_with_policy(other, SubtypePolicy { pointer_variance, ..Default::default() })`")]
+    pub fn is_rough_subtype_of_with(&self, other: &RustType, pointer_variance: PointerVariance) -> IsSubtypeOf {
+        self.is_rough_subtype_of_with_policy(other, SubtypePolicy { pointer_variance, ..SubtypePolicy::default() })
+    }
+
+    /// Checks [RustType::is_rough_subtype_of], and if the result isn't [IsSubtypeOf::Yes],
+    /// also returns the path to the part of the type which isn't a subtype and the rule which failed.
+    pub fn explain_subtype(&self, other: &RustType) -> SubtypeReport {
+        self.explain_subtype_with_policy(other, SubtypePolicy::default())
+    }
+
+    /// [RustType::explain_subtype] with the given policy (see [SubtypePolicy])
+    pub fn explain_subtype_with_policy(&self, other: &RustType, policy: SubtypePolicy) -> SubtypeReport {
+        self.is_rough_subtype_of_in(other, &mut SubtypeCx::new(policy)).outermost_first()
+    }
+
+    /// [RustType::explain_subtype] with the given pointer variance and otherwise the default policy
+    #[deprecated(note = "use `explain_subtype_with_policy(other, SubtypePolicy { pointer_variance, ..Default::default() })`")]
+    pub fn explain_subtype_with(&self, other: &RustType, pointer_variance: PointerVariance) -> SubtypeReport {
+        self.explain_subtype_with_policy(other, SubtypePolicy { pointer_variance, ..SubtypePolicy::default() })
+    }
+
+    /// [RustType::explain_subtype_with_policy], but covariant pointer checks look up the pointees
+    /// in `registry` instead of the global registry
+    #[cfg(feature = "registry")]
+    pub fn explain_subtype_in_registry(&self, other: &RustType, policy: SubtypePolicy, registry: &TypeRegistry) -> SubtypeReport {
+        self.is_rough_subtype_of_in(other, &mut SubtypeCx::new(policy).in_registry(registry)).outermost_first()
+    }
+
+    pub(crate) fn is_rough_subtype_of_in(&self, other: &RustType, cx: &mut SubtypeCx) -> SubtypeReport {
+        let explain = cx.explain;
+        match cx.cache.as_mut().map(|cache| cache.begin(self, other, explain)) {
+            None | Some(SubtypeCacheLookup::Uncacheable) => self.is_rough_subtype_of_uncached(other, cx),
+            Some(SubtypeCacheLookup::Hit(report)) => report,
+            Some(SubtypeCacheLookup::Started) => {
+                let report = self.is_rough_subtype_of_uncached(other, cx);
+                cx.cache.as_mut().unwrap().finish(&report);
+                report
+            }
+        }
+    }
+
+    fn is_rough_subtype_of_uncached(&self, other: &RustType, cx: &mut SubtypeCx) -> SubtypeReport {
         if self.type_name.is_bottom() {
-            IsSubtypeOf::Yes
+            SubtypeReport::yes()
         } else if other.type_name.is_bottom() {
-            IsSubtypeOf::No
-        } else if self.type_id.is_some() && other.type_id.is_some() {
-            IsSubtypeOf::known(self.type_id == other.type_id)
+            cx.no(|| SubtypeRule::NotBottom)
+        } else if self.type_id.is_some() && other.type_id.is_some() && !cx.policy.widens(&self.structure, &other.structure) {
+            if self.type_id == other.type_id {
+                SubtypeReport::yes()
+            } else {
+                cx.no(|| SubtypeRule::TypeIdMismatch {
+                    type_name: self.type_name.clone(),
+                    other_type_name: other.type_name.clone()
+                })
+            }
         } else {
-            self.is_structural_subtype_of(other)
+            self.structure.is_structural_subtype_of_in(&other.structure, cx)
         }
     }
 
@@ -27,6 +242,17 @@ impl RustType {
         self.structure.is_structural_subtype_of(&other.structure)
     }
 
+    /// [RustType::is_structural_subtype_of] with the given policy (see [SubtypePolicy])
+    pub fn is_structural_subtype_of_with_policy(&self, other: &RustType, policy: SubtypePolicy) -> IsSubtypeOf {
+        self.structure.is_structural_subtype_of_with_policy(&other.structure, policy)
+    }
+
+    /// [RustType::is_structural_subtype_of] with the given pointer variance and otherwise the default policy
+    #[deprecated(note = "use `is_structural_subtype_of_with_policy(other, SubtypePolicy { pointer_variance, ..Default::default() })`")]
+    pub fn is_structural_subtype_of_with(&self, other: &RustType, pointer_variance: PointerVariance) -> IsSubtypeOf {
+        self.is_structural_subtype_of_with_policy(other, SubtypePolicy { pointer_variance, ..SubtypePolicy::default() })
+    }
+
     /// **Unifies** `self`, the "explicitly-provided" type, with `other`, the "inferred-by-value" type:
     /// attempts to *create the lowest common subtype of both `self` and `other`*,
     /// consuming `other` in the process and *guaranteeing that the result type is a subtype of `self` (assuming unknown = bottom)*.
@@ -36,16 +262,63 @@ impl RustType {
     /// it will not change, and if this is unknown, it will become the other type, including its name, type_id,
     /// and layout (unifying the structure wouldn't change those things).
     pub fn unify(&mut self, other: RustType) {
+        self.unify_with_policy(other, SubtypePolicy::default());
+    }
+
+    /// [RustType::unify] with the given policy (see [SubtypePolicy])
+    pub fn unify_with_policy(&mut self, other: RustType, policy: SubtypePolicy) {
+        self.unify_in(other, &mut UnifyCx::new(policy, false));
+    }
+
+    /// Like [RustType::unify], but returns every place where the types disagree (those places are
+    /// left unchanged). The compatible parts are still unified, even if this returns an error.
+    ///
+    /// If this type has a type_id and known size/align it will still not change,
+    /// but conflicts with `other` are reported.
+    pub fn try_unify(&mut self, other: RustType) -> Result<(), UnifyConflicts> {
+        self.try_unify_with_policy(other, SubtypePolicy::default())
+    }
+
+    /// [RustType::try_unify] with the given policy (see [SubtypePolicy])
+    pub fn try_unify_with_policy(&mut self, other: RustType, policy: SubtypePolicy) -> Result<(), UnifyConflicts> {
+        let mut cx = UnifyCx::new(policy, true);
+        self.unify_in(other, &mut cx);
+        cx.into_result()
+    }
+
+    /// [RustType::try_unify_with_policy], but covariant pointer checks look up the pointees
+    /// in `registry` instead of the global registry
+    #[cfg(feature = "registry")]
+    pub fn try_unify_in_registry(&mut self, other: RustType, policy: SubtypePolicy, registry: &TypeRegistry) -> Result<(), UnifyConflicts> {
+        let mut cx = UnifyCx::new(policy, true).in_registry(registry);
+        self.unify_in(other, &mut cx);
+        cx.into_result()
+    }
+
+    fn unify_in(&mut self, other: RustType, cx: &mut UnifyCx) {
         if self.type_name.is_unknown() {
             *self = other;
-        } else if self.type_id.is_none() || self.size == usize::MAX || self.align == usize::MAX {
-            self.structure.unify(other.structure);
+            return;
+        }
+        if let (Some(type_id), Some(other_type_id)) = (self.type_id, other.type_id) {
+            if type_id != other_type_id && !cx.policy.widens(&other.structure, &self.structure) {
+                cx.conflict(UnifyConflictKind::TypeIdMismatch {
+                    type_name: self.type_name.clone(),
+                    other_type_name: other.type_name.clone()
+                });
+            }
+        }
+        if self.type_id.is_none() || self.size == usize::MAX || self.align == usize::MAX {
+            self.structure.unify_in(other.structure, cx);
             if self.size == usize::MAX {
                 self.size = self.structure.infer_size().unwrap_or(other.size);
             }
             if self.align == usize::MAX {
                 self.align = self.structure.infer_align().unwrap_or(other.align);
             }
+        } else if cx.records_conflicts() && other.type_id.is_none() {
+            // `self` doesn't change, but we still want to know if `other`'s structure is compatible
+            self.structure.clone().unify_in(other.structure, cx);
         }
     }
 }
@@ -57,84 +330,175 @@ impl TypeStructure {
     /// - If either type is an opaque tuple: if the other type is a tuple and elements may be subtypes, returns [IsSubtypeOf::Unknown], otherwise [IsSubtypeOf::No].
     /// - If either type is an opaque field compound: if the other type is a field compound and for each of `self`'s fields one of `other`'s fields may be a subtype, returns [IsSubtypeOf::Unknown], otherwise [IsSubtypeOf::No].
     /// - If both types are tuples or tuple structures: the lengths must be equal and each corresponding element must be a subtype.
-    /// - If both types are field structures: `self` may have extra fields and will still be a subtype. Shared fields must be subtypes
+    /// - If both types are primitives: they must be equal.
+    /// - If both types are field structures: `self` may have extra fields and will still be a subtype. Shared fields (by name) must be subtypes
     /// - If both types are enums: `self` may *be missing variants* will still be a subtype. Shared variants must be subtypes
     /// - If both types are pointers: the inner type ids must be equal if both are provided, else the names must be equal; *and* the pointer kind must be a subtype:
     ///   - Mutable references are subtypes of immutable references.
     ///   - Mutable raw pointers are subtypes of immutable raw pointers.
     ///   - References are subtypes of raw pointers, except mutable reference is not a subtype of immutable raw pointer.
+    ///
+    ///   (with [PointerVariance::Covariant], shared pointers may instead point to a subtype, see [TypeStructure::is_structural_subtype_of_with_policy])
     /// - If both types are arrays or slices: the element must be a subtype. If both types are arrays the length must be equal. If `self` is an array `other` can be a slice, but not vice versa.
+    ///
+    /// Many of these rules can be changed with a [SubtypePolicy], see [TypeStructure::is_structural_subtype_of_with_policy].
     pub fn is_structural_subtype_of(&self, other: &TypeStructure) -> IsSubtypeOf {
+        self.is_structural_subtype_of_with_policy(other, SubtypePolicy::default())
+    }
+
+    /// [TypeStructure::is_structural_subtype_of] with the given policy (see [SubtypePolicy]).
+    ///
+    /// If the policy's pointer variance is [PointerVariance::Covariant] and `other` is a shared reference or `*const` pointer whose
+    /// pointee isn't the same as `self`'s, then `self` is a subtype if its pointee is a structural
+    /// subtype of `other`'s pointee *and* its layout starts with the layout of `other`'s pointee.
+    /// The pointee structures are looked up in the registry by id or name: if either isn't registered
+    /// (or the `registry` feature is disabled), returns [IsSubtypeOf::Unknown].
+    pub fn is_structural_subtype_of_with_policy(&self, other: &TypeStructure, policy: SubtypePolicy) -> IsSubtypeOf {
+        self.is_structural_subtype_of_in(other, &mut SubtypeCx::new(policy).result_only()).result
+    }
+
+    /// [TypeStructure::is_structural_subtype_of] with the given pointer variance and otherwise the default policy
+    #[deprecated(note = "use `is_structural_subtype_of_with_policy(other, SubtypePolicy { pointer_variance, ..Default::default() })`")]
+    pub fn is_structural_subtype_of_with(&self, other: &TypeStructure, pointer_variance: PointerVariance) -> IsSubtypeOf {
+        self.is_structural_subtype_of_with_policy(other, SubtypePolicy { pointer_variance, ..SubtypePolicy::default() })
+    }
+
+    /// Checks [TypeStructure::is_structural_subtype_of], and if the result isn't [IsSubtypeOf::Yes],
+    /// also returns the path to the part of the type which isn't a subtype and the rule which failed.
+    pub fn explain_subtype(&self, other: &TypeStructure) -> SubtypeReport {
+        self.explain_subtype_with_policy(other, SubtypePolicy::default())
+    }
+
+    /// [TypeStructure::explain_subtype] with the given policy (see [SubtypePolicy])
+    pub fn explain_subtype_with_policy(&self, other: &TypeStructure, policy: SubtypePolicy) -> SubtypeReport {
+        self.is_structural_subtype_of_in(other, &mut SubtypeCx::new(policy)).outermost_first()
+    }
+
+    /// [TypeStructure::explain_subtype] with the given pointer variance and otherwise the default policy
+    #[deprecated(note = "use `explain_subtype_with_policy(other, SubtypePolicy { pointer_variance, ..Default::default() })`")]
+    pub fn explain_subtype_with(&self, other: &TypeStructure, pointer_variance: PointerVariance) -> SubtypeReport {
+        self.explain_subtype_with_policy(other, SubtypePolicy { pointer_variance, ..SubtypePolicy::default() })
+    }
+
+    /// [TypeStructure::explain_subtype_with_policy], but covariant pointer checks look up the pointees
+    /// in `registry` instead of the global registry
+    #[cfg(feature = "registry")]
+    pub fn explain_subtype_in_registry(&self, other: &TypeStructure, policy: SubtypePolicy, registry: &TypeRegistry) -> SubtypeReport {
+        self.is_structural_subtype_of_in(other, &mut SubtypeCx::new(policy).in_registry(registry)).outermost_first()
+    }
+
+    pub(crate) fn is_structural_subtype_of_in(&self, other: &TypeStructure, cx: &mut SubtypeCx) -> SubtypeReport {
         match (self, other) {
-            (TypeStructure::Opaque, _) | (_, TypeStructure::Opaque) => IsSubtypeOf::Unknown,
+            (TypeStructure::Opaque, _) | (_, TypeStructure::Opaque) => cx.unknown(|| SubtypeRule::Opaque),
+            (TypeStructure::Var(var), TypeStructure::Var(other_var)) if var == other_var => SubtypeReport::yes(),
+            (TypeStructure::Var(var), _) | (_, TypeStructure::Var(var)) => cx.unknown(|| SubtypeRule::UnresolvedVar { var: *var }),
             (TypeStructure::OpaqueTuple { elements }, other) => {
                 match other.general_tuple_item_types2(elements.len()) {
-                    None => IsSubtypeOf::No,
+                    None => kind_mismatch(self, other, cx),
                     Some(other_elements) => {
-                        tuple_is_subtype_of2(elements.iter(), other_elements).downgrade_to_unknown()
+                        tuple_is_subtype_of2(elements.iter(), other_elements, cx).downgrade_to_unknown(cx.explain)
                     }
                 }
             }
             (this, TypeStructure::OpaqueTuple { elements: other_elements }) => {
                 match this.general_tuple_item_types2(other_elements.len()) {
-                    None => IsSubtypeOf::No,
+                    None => kind_mismatch(self, other, cx),
                     Some(elements) => {
-                        tuple_is_subtype_of2(elements, other_elements.iter()).downgrade_to_unknown()
+                        tuple_is_subtype_of2(elements, other_elements.iter(), cx).downgrade_to_unknown(cx.explain)
                     }
                 }
             }
             (TypeStructure::OpaqueFields { fields }, other) => {
                 match other.general_field_compound_field_types() {
-                    None => IsSubtypeOf::No,
+                    None => kind_mismatch(self, other, cx),
                     Some(other_fields) => {
-                        fields_is_subtype_of(fields, other_fields).downgrade_to_unknown()
+                        fields_is_subtype_of(fields, other_fields, cx).downgrade_to_unknown(cx.explain)
                     }
                 }
             }
             (this, TypeStructure::OpaqueFields { fields: other_fields }) => {
                 match this.general_field_compound_field_types() {
-                    None => IsSubtypeOf::No,
+                    None => kind_mismatch(self, other, cx),
                     Some(fields) => {
-                        fields_is_subtype_of(fields, other_fields).downgrade_to_unknown()
+                        fields_is_subtype_of(fields, other_fields, cx).downgrade_to_unknown(cx.explain)
                     }
                 }
             }
             (TypeStructure::Primitive(primitive), TypeStructure::Primitive(other_primitive)) => {
-                IsSubtypeOf::known(primitive == other_primitive)
+                if primitive == other_primitive || (cx.policy.numeric_widening && primitive.widens_to(other_primitive)) {
+                    SubtypeReport::yes()
+                } else {
+                    cx.no(|| SubtypeRule::PrimitiveMismatch {
+                        primitive: *primitive,
+                        other_primitive: *other_primitive
+                    })
+                }
             },
             (TypeStructure::CReprEnum { variants }, TypeStructure::CReprEnum { variants: other_variants }) => {
-                variants.iter().map(|other_variant| {
-                    match other_variants.iter().find(|variant| variant.variant_name == other_variant.variant_name) {
-                        None => IsSubtypeOf::No,
-                        Some(variant) => variant.body.is_structural_subtype_of(&other_variant.body)
+                let (allow_missing_variants, explain) = (cx.policy.allow_missing_variants, cx.explain);
+                let missing_variants = other_variants.iter()
+                    .filter(move |other_variant| !allow_missing_variants && !variants.iter().any(|variant| variant.variant_name == other_variant.variant_name))
+                    .map(move |other_variant| SubtypeReport::failed(IsSubtypeOf::No, explain, || SubtypeRule::MissingVariant { name: other_variant.variant_name.clone() }));
+                SubtypeReport::min(variants.iter().map(|variant| {
+                    match other_variants.iter().find(|other_variant| variant.variant_name == other_variant.variant_name) {
+                        None => cx.no(|| SubtypeRule::ExtraVariant { name: variant.variant_name.clone() }),
+                        Some(other_variant) => variant.body.is_structural_subtype_of(&other_variant.body, cx)
+                            .nested_in(|| TypePathElem::Variant(variant.variant_name.clone()))
                     }
-                }).min().unwrap_or(IsSubtypeOf::Yes)
+                }).chain(missing_variants))
             }
             (TypeStructure::CReprStruct { body }, TypeStructure::CReprStruct { body: other_body }) => {
-                body.is_structural_subtype_of(other_body)
+                body.is_structural_subtype_of(other_body, cx)
             }
             (TypeStructure::Pointer { ptr_kind, ptr_size, refd_id, refd_name }, TypeStructure::Pointer { ptr_kind: other_ptr_kind, ptr_size: other_ptr_size, refd_id: other_refd_id, refd_name: other_refd_name }) => {
+                if !ptr_kind.is_subtype_of(other_ptr_kind) {
+                    return cx.no(|| SubtypeRule::PointerKind {
+                        ptr_kind: *ptr_kind,
+                        other_ptr_kind: *other_ptr_kind
+                    });
+                }
+                if ptr_size != other_ptr_size {
+                    return cx.no(|| SubtypeRule::PointerSize {
+                        ptr_size: *ptr_size,
+                        other_ptr_size: *other_ptr_size
+                    });
+                }
                 let refd_equal = match (refd_id, other_refd_id) {
                     (Some(refd_id), Some(other_refd_id)) => refd_id == other_refd_id,
                     _ => refd_name == other_refd_name
                 };
-                IsSubtypeOf::known(ptr_kind.is_subtype_of(other_ptr_kind) && ptr_size == other_ptr_size && refd_equal)
+                if refd_equal {
+                    SubtypeReport::yes()
+                } else if cx.policy.pointer_variance == PointerVariance::Covariant && other_ptr_kind.is_shared() {
+                    refd_is_covariant_subtype_of((*refd_id, refd_name), (*other_refd_id, other_refd_name), cx)
+                } else {
+                    cx.no(|| SubtypeRule::PointeeMismatch {
+                        refd_name: refd_name.clone(),
+                        other_refd_name: other_refd_name.clone()
+                    })
+                }
             }
             (TypeStructure::CTuple { elements }, TypeStructure::CTuple { elements: other_elements }) => {
-                tuple_is_subtype_of(elements, other_elements)
+                tuple_is_subtype_of(elements, other_elements, cx)
             }
             (TypeStructure::Array { elem, length }, TypeStructure::Array { elem: other_elem, length: other_length }) => {
                 if length != other_length {
-                    IsSubtypeOf::No
+                    cx.no(|| SubtypeRule::LengthMismatch {
+                        length: *length,
+                        other_length: *other_length
+                    })
                 } else {
-                    elem.is_rough_subtype_of(other_elem)
+                    elem.is_rough_subtype_of_in(other_elem, cx).nested_in(|| TypePathElem::Elem)
                 }
             }
-            (TypeStructure::Array { elem, length: _ }, TypeStructure::Slice { elem: other_elem }) |
+            (TypeStructure::Array { elem, length: _ }, TypeStructure::Slice { elem: other_elem }) if cx.policy.array_to_slice => {
+                elem.is_rough_subtype_of_in(other_elem, cx).nested_in(|| TypePathElem::Elem)
+            }
             (TypeStructure::Slice { elem }, TypeStructure::Slice { elem: other_elem }) => {
-                elem.is_rough_subtype_of(other_elem)
+                elem.is_rough_subtype_of_in(other_elem, cx).nested_in(|| TypePathElem::Elem)
             }
-            _ => IsSubtypeOf::No
+            (TypeStructure::Slice { .. }, TypeStructure::Array { .. }) => cx.no(|| SubtypeRule::SliceNotArray),
+            _ => kind_mismatch(self, other, cx)
         }
     }
 
@@ -150,30 +514,74 @@ impl TypeStructure {
     /// - Add length to a slice, converting it into an array
     ///
     /// Note: if these types are definitely different, than [TypeStructure::unify] will succeed but
-    /// not cause any changes. If you want different behavior (e.g. an error or bottom type), check
-    /// [TypeStructure::is_structural_subtype_of].
+    /// not cause any changes. If you want different behavior (e.g. an error or bottom type), use
+    /// [TypeStructure::try_unify] or check [TypeStructure::is_structural_subtype_of].
     pub fn unify(&mut self, other: TypeStructure) {
+        self.unify_with_policy(other, SubtypePolicy::default());
+    }
+
+    /// [TypeStructure::unify] with the given policy (see [SubtypePolicy]).
+    /// The result is still a subtype of `self` under the policy, e.g. if missing variants aren't
+    /// allowed, enum variants are never subtracted.
+    pub fn unify_with_policy(&mut self, other: TypeStructure, policy: SubtypePolicy) {
+        self.unify_in(other, &mut UnifyCx::new(policy, false));
+    }
+
+    /// Like [TypeStructure::unify], but returns every place where the types disagree (those places
+    /// are left unchanged). The compatible parts are still unified, even if this returns an error.
+    pub fn try_unify(&mut self, other: TypeStructure) -> Result<(), UnifyConflicts> {
+        self.try_unify_with_policy(other, SubtypePolicy::default())
+    }
+
+    /// [TypeStructure::try_unify] with the given policy (see [SubtypePolicy])
+    pub fn try_unify_with_policy(&mut self, other: TypeStructure, policy: SubtypePolicy) -> Result<(), UnifyConflicts> {
+        let mut cx = UnifyCx::new(policy, true);
+        self.unify_in(other, &mut cx);
+        cx.into_result()
+    }
+
+    /// [TypeStructure::try_unify_with_policy], but covariant pointer checks look up the pointees
+    /// in `registry` instead of the global registry
+    #[cfg(feature = "registry")]
+    pub fn try_unify_in_registry(&mut self, other: TypeStructure, policy: SubtypePolicy, registry: &TypeRegistry) -> Result<(), UnifyConflicts> {
+        let mut cx = UnifyCx::new(policy, true).in_registry(registry);
+        self.unify_in(other, &mut cx);
+        cx.into_result()
+    }
+
+    fn unify_in(&mut self, other: TypeStructure, cx: &mut UnifyCx) {
         // Can't put in match expr because of borrowing rules
         if matches!(self, TypeStructure::Opaque) {
             *self = other;
             return;
         }
+        if matches!(other, TypeStructure::Opaque) {
+            return;
+        }
+        // Type variables are only assigned by an `InferenceContext`
+        if matches!(self, TypeStructure::Var(_)) || matches!(other, TypeStructure::Var(_)) {
+            return;
+        }
+        let kind = self.kind_name();
         if let TypeStructure::OpaqueTuple { elements } = self {
             match other {
                 TypeStructure::OpaqueTuple { elements: other_elements } => {
-                    unify_tuple(elements, other_elements)
+                    unify_tuple(elements, other_elements, cx)
                 }
                 TypeStructure::CTuple { elements: other_elements } => {
-                    unify_tuple(elements, other_elements);
+                    unify_tuple(elements, other_elements, cx);
                     *self = TypeStructure::CTuple { elements: elements.clone() };
                 }
                 TypeStructure::CReprStruct { body: TypeStructureBody::Tuple(other_elements) } => {
-                    unify_tuple(elements, other_elements);
+                    unify_tuple(elements, other_elements, cx);
                     *self = TypeStructure::CReprStruct { body: TypeStructureBody::Tuple(elements.clone()) };
                 }
+                TypeStructure::CReprStruct { body: other_body } => {
+                    cx.body_form_conflict(TypeStructureBodyForm::Tuple, &other_body);
+                }
                 TypeStructure::CReprEnum { variants: other_variants } if other_variants.len() == 1 => match other_variants.into_iter().next().unwrap() {
                     TypeEnumVariant { variant_name, body: TypeStructureBody::Tuple(other_elements) } => {
-                        unify_tuple(elements, other_elements);
+                        cx.nested(TypePathElem::Variant(variant_name.clone()), |cx| unify_tuple(elements, other_elements, cx));
                         *self = TypeStructure::CReprEnum {
                             variants: vec![TypeEnumVariant {
                                 variant_name,
@@ -181,15 +589,23 @@ impl TypeStructure {
                             }]
                         };
                     }
-                    _ => {}
+                    TypeEnumVariant { variant_name, body: other_body } => {
+                        cx.nested(TypePathElem::Variant(variant_name), |cx| cx.body_form_conflict(TypeStructureBodyForm::Tuple, &other_body));
+                    }
                 },
                 TypeStructure::Array { elem: other_elem, length } => {
+                    if elements.len() != length {
+                        cx.conflict(UnifyConflictKind::LengthMismatch {
+                            length: elements.len(),
+                            other_length: length
+                        });
+                    }
                     if elements.iter().all(|elem| elem.is_structural_subtype_of(other_elem.as_ref()) != IsSubtypeOf::No) {
                         *self = TypeStructure::Array { elem: other_elem, length };
                     } else {
                         elements.truncate(length);
-                        for element in elements {
-                            element.unify(other_elem.as_ref().clone());
+                        for (index, element) in elements.iter_mut().enumerate() {
+                            cx.nested(TypePathElem::Index(index), |cx| element.unify_in(other_elem.as_ref().clone(), cx));
                         }
                     }
                 }
@@ -197,27 +613,30 @@ impl TypeStructure {
                     if elements.iter().all(|elem| elem.is_structural_subtype_of(other_elem.as_ref()) != IsSubtypeOf::No) {
                         *self = TypeStructure::Array { elem: other_elem, length: elements.len() };
                     } else {
-                        for element in elements {
-                            element.unify(other_elem.as_ref().clone());
+                        for (index, element) in elements.iter_mut().enumerate() {
+                            cx.nested(TypePathElem::Index(index), |cx| element.unify_in(other_elem.as_ref().clone(), cx));
                         }
                     }
                 }
-                _ => {}
+                other => cx.kind_conflict(kind, &other)
             }
             return;
         }
         if let TypeStructure::OpaqueFields { fields } = self {
             match other {
                 TypeStructure::OpaqueFields { fields: other_fields } => {
-                    unify_fields(fields, other_fields);
+                    unify_fields(fields, other_fields, cx);
                 }
                 TypeStructure::CReprStruct { body: TypeStructureBody::Fields(other_fields) } => {
-                    unify_fields(fields, other_fields);
+                    unify_fields(fields, other_fields, cx);
                     *self = TypeStructure::CReprStruct { body: TypeStructureBody::Fields(fields.clone()) };
                 }
+                TypeStructure::CReprStruct { body: other_body } => {
+                    cx.body_form_conflict(TypeStructureBodyForm::Fields, &other_body);
+                }
                 TypeStructure::CReprEnum { variants: other_variants } if other_variants.len() == 1 => match other_variants.into_iter().next().unwrap() {
                     TypeEnumVariant { variant_name, body: TypeStructureBody::Fields(other_fields) } => {
-                        unify_fields(fields, other_fields);
+                        cx.nested(TypePathElem::Variant(variant_name.clone()), |cx| unify_fields(fields, other_fields, cx));
                         *self = TypeStructure::CReprEnum {
                             variants: vec![TypeEnumVariant {
                                 variant_name,
@@ -225,21 +644,26 @@ impl TypeStructure {
                             }]
                         }
                     }
-                    _ => {}
+                    TypeEnumVariant { variant_name, body: other_body } => {
+                        cx.nested(TypePathElem::Variant(variant_name), |cx| cx.body_form_conflict(TypeStructureBodyForm::Fields, &other_body));
+                    }
                 }
-                _ => {}
+                other => cx.kind_conflict(kind, &other)
             }
             return;
         }
         if let TypeStructure::Slice { elem } = self {
-            if let TypeStructure::Array { elem: other_elem, length: other_length } = other {
-                elem.unify(*other_elem);
+            if !cx.policy.array_to_slice && matches!(other, TypeStructure::Array { .. } | TypeStructure::OpaqueTuple { .. }) {
+                cx.kind_conflict(kind, &other);
+                return;
+            } else if let TypeStructure::Array { elem: other_elem, length: other_length } = other {
+                cx.nested(TypePathElem::Elem, |cx| elem.unify_in(*other_elem, cx));
                 *self = TypeStructure::Array { elem: elem.clone(), length: other_length };
                 return;
             } else if let TypeStructure::OpaqueTuple { elements: other_elements } = other {
                 let length = other_elements.len();
                 for other_elem in other_elements {
-                    elem.unify(other_elem);
+                    cx.nested(TypePathElem::Elem, |cx| elem.unify_in(other_elem, cx));
                 }
                 *self = TypeStructure::Array { elem: elem.clone(), length };
                 return;
@@ -250,43 +674,73 @@ impl TypeStructure {
         match (self, other) {
             (TypeStructure::Opaque, _) |
             (TypeStructure::OpaqueTuple { .. }, _) |
-            (TypeStructure::OpaqueFields { .. }, _) => unreachable!(),
+            (TypeStructure::OpaqueFields { .. }, _) |
+            (TypeStructure::Var(_), _) |
+            (_, TypeStructure::Opaque) |
+            (_, TypeStructure::Var(_)) => unreachable!(),
             (TypeStructure::CReprEnum { variants }, TypeStructure::CReprEnum { variants: mut other_variants }) => {
+                let had_variants = !variants.is_empty() && !other_variants.is_empty();
+                let allow_missing_variants = cx.policy.allow_missing_variants;
                 let _ = variants.drain_filter(|variant| {
                     if let Some(other_variant_idx) = other_variants.iter().position(|other_variant| variant.variant_name == other_variant.variant_name) {
                         let other_variant = other_variants.remove(other_variant_idx);
-                        variant.body.unify(other_variant.body);
+                        cx.nested(TypePathElem::Variant(variant.variant_name.clone()), |cx| variant.body.unify_in(other_variant.body, cx));
+                        false
+                    } else if !allow_missing_variants {
+                        // Removing the variant would make the result not a subtype of `self`
+                        cx.conflict(UnifyConflictKind::VariantMismatch { name: variant.variant_name.clone() });
                         false
                     } else {
                         true
                     }
                 });
+                if had_variants && variants.is_empty() {
+                    cx.conflict(UnifyConflictKind::NoCommonVariants);
+                }
             },
             (TypeStructure::CReprEnum { variants }, TypeStructure::OpaqueTuple { elements: other_elements }) if variants.len() == 1 => {
-                if let TypeStructureBody::Tuple(elements) = &mut variants[0].body {
-                    unify_tuple(elements, other_elements);
-                }
+                let variant = &mut variants[0];
+                cx.nested(TypePathElem::Variant(variant.variant_name.clone()), |cx| match &mut variant.body {
+                    TypeStructureBody::Tuple(elements) => unify_tuple(elements, other_elements, cx),
+                    body => cx.body_form_conflict(body.form(), &TypeStructureBody::Tuple(other_elements))
+                });
             }
             (TypeStructure::CReprEnum { variants }, TypeStructure::OpaqueFields { fields: other_fields }) if variants.len() == 1 => {
-                if let TypeStructureBody::Fields(fields) = &mut variants[0].body {
-                    unify_fields(fields, other_fields);
-                }
+                let variant = &mut variants[0];
+                cx.nested(TypePathElem::Variant(variant.variant_name.clone()), |cx| match &mut variant.body {
+                    TypeStructureBody::Fields(fields) => unify_fields(fields, other_fields, cx),
+                    body => cx.body_form_conflict(body.form(), &TypeStructureBody::Fields(other_fields))
+                });
             }
             (TypeStructure::CReprStruct { body }, TypeStructure::CReprStruct { body: other_body }) => {
-                body.unify(other_body);
+                body.unify_in(other_body, cx);
             }
             (TypeStructure::CReprStruct { body }, TypeStructure::OpaqueTuple { elements: other_elements }) => {
-                if let TypeStructureBody::Tuple(elements) = body {
-                    unify_tuple(elements, other_elements);
-                }
+                body.unify_in(TypeStructureBody::Tuple(other_elements), cx);
             }
             (TypeStructure::CReprStruct { body }, TypeStructure::OpaqueFields { fields: other_fields }) => {
-                if let TypeStructureBody::Fields(fields) = body {
-                    unify_fields(fields, other_fields);
-                }
+                body.unify_in(TypeStructureBody::Fields(other_fields), cx);
             }
-            (TypeStructure::Pointer { ptr_kind, ptr_size: _, refd_id, refd_name: _ }, TypeStructure::Pointer { ptr_kind: other_ptr_kind, ptr_size: _, refd_id: other_refd_id, refd_name: _ }) => {
+            (TypeStructure::Pointer { ptr_kind, ptr_size, refd_id, refd_name }, TypeStructure::Pointer { ptr_kind: other_ptr_kind, ptr_size: other_ptr_size, refd_id: other_refd_id, refd_name: other_refd_name }) => {
                 ptr_kind.unify(other_ptr_kind);
+                if *ptr_size != other_ptr_size {
+                    cx.conflict(UnifyConflictKind::PointerSize {
+                        ptr_size: *ptr_size,
+                        other_ptr_size
+                    });
+                }
+                let refd_equal = match (&refd_id, &other_refd_id) {
+                    (Some(refd_id), Some(other_refd_id)) => refd_id == other_refd_id,
+                    _ => refd_name == &other_refd_name
+                };
+                if !refd_equal && !(cx.policy.pointer_variance == PointerVariance::Covariant && ptr_kind.is_shared() &&
+                    refd_is_covariant_subtype_of((other_refd_id, &other_refd_name), (*refd_id, refd_name), &mut cx.subtype_cx()).result == IsSubtypeOf::Yes) {
+                    // Pointing to a subtype of `self`'s pointee is fine, the pointer stays the same
+                    cx.conflict(UnifyConflictKind::PointeeMismatch {
+                        refd_name: refd_name.clone(),
+                        other_refd_name
+                    });
+                }
                 if refd_id.is_none() {
                     *refd_id = other_refd_id;
                 }
@@ -294,54 +748,80 @@ impl TypeStructure {
             }
             (TypeStructure::CTuple { elements }, TypeStructure::CTuple { elements: other_elements }) |
             (TypeStructure::CTuple { elements }, TypeStructure::OpaqueTuple { elements: other_elements }) => {
-                unify_tuple(elements, other_elements);
+                unify_tuple(elements, other_elements, cx);
+            }
+            (TypeStructure::Array { elem, length }, TypeStructure::Array { elem: other_elem, length: other_length }) => {
+                if *length != other_length {
+                    cx.conflict(UnifyConflictKind::LengthMismatch {
+                        length: *length,
+                        other_length
+                    });
+                }
+                cx.nested(TypePathElem::Elem, |cx| elem.unify_in(*other_elem, cx));
+            }
+            (TypeStructure::Array { elem, length: _ }, TypeStructure::Slice { elem: other_elem }) if cx.policy.array_to_slice => {
+                cx.nested(TypePathElem::Elem, |cx| elem.unify_in(*other_elem, cx));
             }
-            (TypeStructure::Array { elem, length: _ }, TypeStructure::Array { elem: other_elem, length: _ }) |
-            (TypeStructure::Array { elem, length: _ }, TypeStructure::Slice { elem: other_elem }) |
             (TypeStructure::Slice { elem }, TypeStructure::Slice { elem: other_elem }) => {
-                elem.unify(*other_elem);
+                cx.nested(TypePathElem::Elem, |cx| elem.unify_in(*other_elem, cx));
             }
-            (TypeStructure::Array { elem, length: _ }, TypeStructure::OpaqueTuple { elements: other_elements }) => {
+            (TypeStructure::Array { elem, length }, TypeStructure::OpaqueTuple { elements: other_elements }) => {
+                if *length != other_elements.len() {
+                    cx.conflict(UnifyConflictKind::LengthMismatch {
+                        length: *length,
+                        other_length: other_elements.len()
+                    });
+                }
                 for other_elem in other_elements {
-                    elem.unify(other_elem);
+                    cx.nested(TypePathElem::Elem, |cx| elem.unify_in(other_elem, cx));
+                }
+            }
+            (TypeStructure::Primitive(primitive), TypeStructure::Primitive(other_primitive)) => {
+                if *primitive != other_primitive && !(cx.policy.numeric_widening && other_primitive.widens_to(primitive)) {
+                    cx.conflict(UnifyConflictKind::PrimitiveMismatch {
+                        primitive: *primitive,
+                        other_primitive
+                    });
                 }
             }
-            _ => {}
+            (_, other) => cx.kind_conflict(kind, &other)
         }
     }
 }
 
 impl TypeStructureBody {
-    fn is_structural_subtype_of(&self, other: &TypeStructureBody) -> IsSubtypeOf {
+    fn is_structural_subtype_of(&self, other: &TypeStructureBody, cx: &mut SubtypeCx) -> SubtypeReport {
         match (self, other) {
-            (TypeStructureBody::None, TypeStructureBody::None) => IsSubtypeOf::Yes,
+            (TypeStructureBody::None, TypeStructureBody::None) => SubtypeReport::yes(),
             (TypeStructureBody::Tuple(elements), TypeStructureBody::Tuple(other_elements)) => {
-                tuple_is_subtype_of(elements, other_elements)
+                tuple_is_subtype_of(elements, other_elements, cx)
             }
             (TypeStructureBody::Fields(fields), TypeStructureBody::Fields(other_fields)) => {
-                fields_is_subtype_of(fields, other_fields)
+                fields_is_subtype_of(fields, other_fields, cx)
             }
-            _ => IsSubtypeOf::No
+            _ => cx.no(|| SubtypeRule::BodyFormMismatch {
+                form: self.form(),
+                other_form: other.form()
+            })
         }
     }
 
-    fn unify(&mut self, other: TypeStructureBody) {
+    fn unify_in(&mut self, other: TypeStructureBody, cx: &mut UnifyCx) {
         match (self, other) {
+            (TypeStructureBody::None, TypeStructureBody::None) => {}
             (TypeStructureBody::Tuple(elements), TypeStructureBody::Tuple(other_elements)) => {
-                for (element, other_element) in elements.iter_mut().zip(other_elements.into_iter()) {
-                    element.unify(other_element);
-                }
+                unify_tuple(elements, other_elements, cx);
             }
             (TypeStructureBody::Fields(fields), TypeStructureBody::Fields(other_fields)) => {
-                unify_fields(fields, other_fields);
+                unify_fields(fields, other_fields, cx);
             }
-            _ => {}
+            (this, other) => cx.body_form_conflict(this.form(), &other)
         }
     }
 }
 
 impl RustPointerKind {
-    fn is_subtype_of(&self, other: &RustPointerKind) -> bool {
+    pub(crate) fn is_subtype_of(&self, other: &RustPointerKind) -> bool {
         match (self, other) {
             (RustPointerKind::ImmRaw, RustPointerKind::ImmRaw) => true,
             (RustPointerKind::ImmRaw, RustPointerKind::MutRaw) => true,
@@ -362,6 +842,11 @@ impl RustPointerKind {
         }
     }
 
+    /// Is an immutable reference or raw pointer?
+    fn is_shared(&self) -> bool {
+        matches!(self, RustPointerKind::ImmRef | RustPointerKind::ImmRaw)
+    }
+
     fn unify(&mut self, other: RustPointerKind) {
         *self = match (*self, other) {
             (RustPointerKind::ImmRaw, RustPointerKind::ImmRaw) => RustPointerKind::ImmRaw,
@@ -384,44 +869,203 @@ impl RustPointerKind {
     }
 }
 
-fn tuple_is_subtype_of(elements: &[RustType], other_elements: &[RustType]) -> IsSubtypeOf {
-    tuple_is_subtype_of2(elements.iter(), other_elements.iter())
+fn tuple_is_subtype_of(elements: &[RustType], other_elements: &[RustType], cx: &mut SubtypeCx) -> SubtypeReport {
+    tuple_is_subtype_of2(elements.iter(), other_elements.iter(), cx)
 }
 
-fn tuple_is_subtype_of2<'a>(elements: impl ExactSizeIterator<Item=&'a RustType>, other_elements: impl ExactSizeIterator<Item=&'a RustType>) -> IsSubtypeOf {
+fn tuple_is_subtype_of2<'a>(elements: impl ExactSizeIterator<Item=&'a RustType>, other_elements: impl ExactSizeIterator<Item=&'a RustType>, cx: &mut SubtypeCx) -> SubtypeReport {
     if elements.len() != other_elements.len() {
-        IsSubtypeOf::No
+        cx.no(|| SubtypeRule::LengthMismatch {
+            length: elements.len(),
+            other_length: other_elements.len()
+        })
     } else {
-        zip(elements, other_elements).map(|(element, other_element)| {
-            element.is_rough_subtype_of(other_element)
-        }).min().unwrap_or(IsSubtypeOf::Yes)
+        SubtypeReport::min(zip(elements, other_elements).enumerate().map(|(index, (element, other_element))| {
+            element.is_rough_subtype_of_in(other_element, cx).nested_in(|| TypePathElem::Index(index))
+        }))
     }
 }
 
-fn fields_is_subtype_of(fields: &[TypeStructureBodyField], other_fields: &[TypeStructureBodyField]) -> IsSubtypeOf {
-    if fields.len() < other_fields.len() {
-        IsSubtypeOf::No
+fn fields_is_subtype_of(fields: &[TypeStructureBodyField], other_fields: &[TypeStructureBodyField], cx: &mut SubtypeCx) -> SubtypeReport {
+    if cx.policy.fields_by_position {
+        return fields_is_subtype_of_by_position(fields, other_fields, cx);
+    }
+    let (allow_extra_fields, explain) = (cx.policy.allow_extra_fields, cx.explain);
+    let extra_fields = fields.iter()
+        .filter(move |field| !allow_extra_fields && !other_fields.iter().any(|other_field| field.name == other_field.name))
+        .map(move |field| SubtypeReport::failed(IsSubtypeOf::No, explain, || SubtypeRule::ExtraField { name: field.name.clone() }));
+    let report = SubtypeReport::min(other_fields.iter().map(|other_field| {
+        match fields.iter().find(|field| field.name == other_field.name) {
+            None => cx.no(|| SubtypeRule::MissingField { name: other_field.name.clone() }),
+            Some(field) => field.rust_type.is_rough_subtype_of_in(&other_field.rust_type, cx)
+                .nested_in(|| TypePathElem::Field(field.name.clone()))
+        }
+    }).chain(extra_fields));
+    if report.result != IsSubtypeOf::No && fields.len() < other_fields.len() {
+        // Only happens with duplicate field names
+        cx.no(|| SubtypeRule::LengthMismatch {
+            length: fields.len(),
+            other_length: other_fields.len()
+        })
     } else {
-        other_fields.iter().map(|field| {
-            match fields.iter().find(|other_field| field.name == other_field.name) {
-                None => IsSubtypeOf::No,
-                Some(other_field) => field.rust_type.is_rough_subtype_of(&other_field.rust_type)
-            }
-        }).min().unwrap_or(IsSubtypeOf::Yes)
+        report
     }
 }
 
-fn unify_tuple(elements: &mut Vec<RustType>, other_elements: Vec<RustType>) {
-    for (element, other_element) in zip(elements, other_elements) {
-        element.unify(other_element);
+/// [fields_is_subtype_of] when fields are matched by position: names are ignored, and `self` may
+/// only have extra fields at the end
+fn fields_is_subtype_of_by_position(fields: &[TypeStructureBodyField], other_fields: &[TypeStructureBodyField], cx: &mut SubtypeCx) -> SubtypeReport {
+    if fields.len() < other_fields.len() || (fields.len() > other_fields.len() && !cx.policy.allow_extra_fields) {
+        return cx.no(|| SubtypeRule::LengthMismatch {
+            length: fields.len(),
+            other_length: other_fields.len()
+        });
+    }
+    SubtypeReport::min(zip(fields, other_fields).enumerate().map(|(index, (field, other_field))| {
+        field.rust_type.is_rough_subtype_of_in(&other_field.rust_type, cx).nested_in(|| TypePathElem::Index(index))
+    }))
+}
+
+/// Covariant pointer subtyping: is the pointee `refd` a structural subtype of `other_refd`
+/// with a layout that starts with `other_refd`'s layout?
+fn refd_is_covariant_subtype_of(refd: (Option<TypeId>, &RustTypeName), other_refd: (Option<TypeId>, &RustTypeName), cx: &mut SubtypeCx) -> SubtypeReport {
+    let visiting = (refd.1, other_refd.1);
+    if let Some((_, _, depth)) = cx.visiting_pointees.iter().find(|(refd_name, other_refd_name, _)| (refd_name, other_refd_name) == visiting) {
+        // Cached checks which started inside the visited pointee check depend on this assumption
+        let depth = *depth;
+        if let Some(cache) = cx.cache.as_mut() {
+            cache.assume(depth);
+        }
+        return SubtypeReport::yes();
+    }
+    let (refd, other_refd) = match (lookup_refd(refd, cx), lookup_refd(other_refd, cx)) {
+        (Some(refd), Some(other_refd)) => (refd, other_refd),
+        (None, _) => return cx.unknown(|| SubtypeRule::UnregisteredPointee { refd_name: visiting.0.clone() }),
+        (_, None) => return cx.unknown(|| SubtypeRule::UnregisteredPointee { refd_name: visiting.1.clone() })
+    };
+    let depth = cx.cache.as_ref().map_or(0, |cache| cache.depth());
+    cx.visiting_pointees.push((visiting.0.clone(), visiting.1.clone(), depth));
+    let report = refd.structure.is_structural_subtype_of_in(&other_refd.structure, cx).nested_in(|| TypePathElem::Pointee);
+    cx.visiting_pointees.pop();
+    if report.result == IsSubtypeOf::No || (refd.is_layout_compatible_with(&other_refd).is_compatible() && has_names_in_order(&refd, &other_refd)) {
+        report
+    } else {
+        cx.no(|| SubtypeRule::PointeeLayout {
+            refd_name: refd.type_name.clone(),
+            other_refd_name: other_refd.type_name.clone()
+        })
     }
 }
 
-fn unify_fields(fields: &mut Vec<TypeStructureBodyField>, mut other_fields: Vec<TypeStructureBodyField>) {
+/// Do `this`'s fields and variants have the same names as `prefix`'s at the same positions?
+/// Layout compatibility compares them by position and subtyping by name,
+/// so they only agree on which field is which if the names are in the same order
+fn has_names_in_order(this: &RustType, prefix: &RustType) -> bool {
+    if this.type_id.is_some() && this.type_id == prefix.type_id {
+        return true;
+    }
+    if let (TypeStructure::CReprEnum { variants }, TypeStructure::CReprEnum { variants: prefix_variants }) = (&this.structure, &prefix.structure) {
+        return zip(variants, prefix_variants).all(|(variant, prefix_variant)| {
+            variant.variant_name == prefix_variant.variant_name && bodies_have_names_in_order(&variant.body, &prefix_variant.body)
+        });
+    }
+    if let (Some(fields), Some(prefix_fields)) = (this.structure.general_field_compound_field_types(), prefix.structure.general_field_compound_field_types()) {
+        return fields_have_names_in_order(fields, prefix_fields);
+    }
+    if let (Some(elem), Some(prefix_elem)) = (this.structure.array_or_slice_elem_type(), prefix.structure.array_or_slice_elem_type()) {
+        return has_names_in_order(elem, prefix_elem);
+    }
+    if let (Some(elems), Some(prefix_elems)) = (this.structure.general_tuple_item_types(), prefix.structure.general_tuple_item_types()) {
+        return zip(elems, prefix_elems).all(|(elem, prefix_elem)| has_names_in_order(elem, prefix_elem));
+    }
+    true
+}
+
+fn bodies_have_names_in_order(body: &TypeStructureBody, prefix_body: &TypeStructureBody) -> bool {
+    match (body, prefix_body) {
+        (TypeStructureBody::Fields(fields), TypeStructureBody::Fields(prefix_fields)) => fields_have_names_in_order(fields, prefix_fields),
+        (TypeStructureBody::Tuple(elems), TypeStructureBody::Tuple(prefix_elems)) => {
+            zip(elems, prefix_elems).all(|(elem, prefix_elem)| has_names_in_order(elem, prefix_elem))
+        }
+        _ => true
+    }
+}
+
+fn fields_have_names_in_order(fields: &[TypeStructureBodyField], prefix_fields: &[TypeStructureBodyField]) -> bool {
+    zip(fields, prefix_fields).all(|(field, prefix_field)| {
+        field.name == prefix_field.name && has_names_in_order(&field.rust_type, &prefix_field.rust_type)
+    })
+}
+
+#[cfg(feature = "registry")]
+fn lookup_refd((refd_id, refd_name): (Option<TypeId>, &RustTypeName), cx: &SubtypeCx) -> Option<Arc<RustType>> {
+    match cx.registry {
+        None => refd_id.and_then(RustType::lookup_from_id).or_else(|| RustType::lookup(refd_name)),
+        Some(registry) => refd_id.and_then(|refd_id| registry.lookup_from_id(refd_id).cloned())
+            .or_else(|| registry.lookup_or_instantiate(refd_name))
+    }
+}
+
+#[cfg(not(feature = "registry"))]
+fn lookup_refd(_refd: (Option<TypeId>, &RustTypeName), _cx: &SubtypeCx) -> Option<Arc<RustType>> {
+    None
+}
+
+fn unify_tuple(elements: &mut Vec<RustType>, other_elements: Vec<RustType>, cx: &mut UnifyCx) {
+    if elements.len() != other_elements.len() {
+        cx.conflict(UnifyConflictKind::LengthMismatch {
+            length: elements.len(),
+            other_length: other_elements.len()
+        });
+    }
+    for (index, (element, other_element)) in zip(elements, other_elements).enumerate() {
+        cx.nested(TypePathElem::Index(index), |cx| element.unify_in(other_element, cx));
+    }
+}
+
+fn unify_fields(fields: &mut Vec<TypeStructureBodyField>, mut other_fields: Vec<TypeStructureBodyField>, cx: &mut UnifyCx) {
+    if cx.policy.fields_by_position {
+        if fields.len() != other_fields.len() && !cx.policy.allow_extra_fields {
+            cx.conflict(UnifyConflictKind::LengthMismatch {
+                length: fields.len(),
+                other_length: other_fields.len()
+            });
+        }
+        for (index, (field, other_field)) in zip(fields, other_fields).enumerate() {
+            cx.nested(TypePathElem::Index(index), |cx| field.rust_type.unify_in(other_field.rust_type, cx));
+        }
+        return;
+    }
     for field in fields {
         if let Some(other_field_idx) = other_fields.iter().position(|other_field| field.name == other_field.name) {
             let other_field = other_fields.remove(other_field_idx);
-            field.rust_type.unify(other_field.rust_type);
+            cx.nested(TypePathElem::Field(field.name.clone()), |cx| field.rust_type.unify_in(other_field.rust_type, cx));
+        } else if !cx.policy.allow_extra_fields {
+            cx.conflict(UnifyConflictKind::FieldMismatch { name: field.name.clone() });
+        }
+    }
+    if !cx.policy.allow_extra_fields {
+        for other_field in other_fields {
+            cx.conflict(UnifyConflictKind::FieldMismatch { name: other_field.name });
+        }
+    }
+}
+
+fn kind_mismatch(this: &TypeStructure, other: &TypeStructure, cx: &SubtypeCx) -> SubtypeReport {
+    cx.no(|| SubtypeRule::KindMismatch {
+        kind: this.kind_name(),
+        other_kind: other.kind_name()
+    })
+}
+
+impl SubtypePolicy {
+    /// Are these primitives, and can `this` widen to `other` under this policy?
+    fn widens(&self, this: &TypeStructure, other: &TypeStructure) -> bool {
+        match (this, other) {
+            (TypeStructure::Primitive(primitive), TypeStructure::Primitive(other_primitive)) => {
+                self.numeric_widening && primitive.widens_to(other_primitive)
+            }
+            _ => false
         }
     }
 }
@@ -0,0 +1,230 @@
+use std::any::TypeId;
+use std::collections::HashMap;
+use crate::{IsSubtypeOf, RustType, RustTypeName, SubtypePolicy, SubtypeReport, TypeStructure};
+use crate::subtype::SubtypeCx;
+#[cfg(feature = "registry")]
+use crate::TypeRegistry;
+
+/// Memoizes subtype checks between types, so checking many values against the same types
+/// doesn't recompute nested checks from scratch.
+///
+/// Types are identified by their type id, or if they don't have one, by their name
+/// (anonymous types aren't cached). So types with the same name but different structures
+/// (which the registry doesn't allow) will have incorrect results.
+///
+/// Every check uses the cache's [SubtypePolicy]. If the `registry` feature is enabled, the cache
+/// is cleared whenever a registered type or type name changes, since covariant pointer checks
+/// look up the pointees. It's also cleared when it's used with a different registry
+/// (see [RustType::explain_subtype_cached_in_registry]).
+#[derive(Debug, Clone, Default)]
+pub struct SubtypeCache {
+    policy: SubtypePolicy,
+    entries: HashMap<(SubtypeKey, SubtypeKey), SubtypeCacheEntry>,
+    /// Checks which are currently in progress, innermost last
+    in_progress: Vec<InProgress>,
+    registry_generation: u64
+}
+
+/// How a type is identified in a [SubtypeCache]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum SubtypeKey {
+    Id(TypeId),
+    Name(RustTypeName)
+}
+
+#[derive(Debug, Clone)]
+enum SubtypeCacheEntry {
+    /// Index into `in_progress`
+    InProgress(usize),
+    Done {
+        report: SubtypeReport,
+        /// Whether the report has the failure, or was only checked for the result
+        explained: bool
+    }
+}
+
+/// Result of [SubtypeCache::begin]
+pub(crate) enum SubtypeCacheLookup {
+    /// The types can't be cached (e.g. they're anonymous)
+    Uncacheable,
+    /// The result is cached (or assumed, if the check is already in progress)
+    Hit(SubtypeReport),
+    /// The check was started
+    Started
+}
+
+#[derive(Debug, Clone)]
+struct InProgress {
+    key: (SubtypeKey, SubtypeKey),
+    explained: bool,
+    /// The result is only final if this is greater than the check's index in `in_progress`.
+    /// Otherwise it assumed that an outer check (which hasn't finished) is a subtype, so it isn't cached.
+    depends_on: usize
+}
+
+impl SubtypeCache {
+    /// Creates an empty cache which checks with the given policy
+    pub fn new(policy: SubtypePolicy) -> Self {
+        SubtypeCache {
+            policy,
+            entries: HashMap::new(),
+            in_progress: Vec::new(),
+            registry_generation: current_registry_generation()
+        }
+    }
+
+    /// The policy all of this cache's checks use
+    pub fn policy(&self) -> SubtypePolicy {
+        self.policy
+    }
+
+    /// Number of cached results
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Remove all cached results
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.in_progress.clear();
+    }
+
+    /// Clear if the registry changed (or is a different registry) since the last check
+    fn sync_with_registry(&mut self, registry_generation: u64) {
+        if self.registry_generation != registry_generation {
+            self.clear();
+            self.registry_generation = registry_generation;
+        }
+    }
+
+    /// Returns the cached result of the check if there is one, otherwise starts the check.
+    /// If `explain` is true and the cached result is a failure which wasn't explained, the check is restarted.
+    ///
+    /// If the check is already in progress (the types are recursive), we assume it's a subtype
+    /// (the other parts decide the result). If the check is started, you must call
+    /// [SubtypeCache::finish] with the result.
+    pub(crate) fn begin(&mut self, this: &RustType, other: &RustType, explain: bool) -> SubtypeCacheLookup {
+        let key = match (SubtypeKey::of(this), SubtypeKey::of(other)) {
+            (Some(key), Some(other_key)) => (key, other_key),
+            _ => return SubtypeCacheLookup::Uncacheable
+        };
+        match self.entries.get(&key) {
+            Some(SubtypeCacheEntry::Done { report, explained }) if *explained || !explain || report.result == IsSubtypeOf::Yes => {
+                SubtypeCacheLookup::Hit(match explain {
+                    true => report.clone(),
+                    false => SubtypeReport { result: report.result, failure: None }
+                })
+            }
+            Some(SubtypeCacheEntry::InProgress(index)) => {
+                let index = *index;
+                self.assume(index + 1);
+                SubtypeCacheLookup::Hit(SubtypeReport::yes())
+            }
+            Some(SubtypeCacheEntry::Done { .. }) | None => {
+                self.entries.insert(key.clone(), SubtypeCacheEntry::InProgress(self.in_progress.len()));
+                self.in_progress.push(InProgress { key, explained: explain, depends_on: usize::MAX });
+                SubtypeCacheLookup::Started
+            }
+        }
+    }
+
+    /// Finish the innermost check started by [SubtypeCache::begin], caching the result if it's final
+    pub(crate) fn finish(&mut self, report: &SubtypeReport) {
+        let InProgress { key, explained, depends_on } = self.in_progress.pop().expect("no subtype check in progress");
+        if depends_on > self.in_progress.len() {
+            self.entries.insert(key, SubtypeCacheEntry::Done { report: report.clone(), explained });
+        } else {
+            self.entries.remove(&key);
+            self.assume(depends_on);
+        }
+    }
+
+    /// Number of checks in progress
+    pub(crate) fn depth(&self) -> usize {
+        self.in_progress.len()
+    }
+
+    /// Record that the innermost check assumed something which is only resolved once the checks
+    /// at `depth` and above finish (see [InProgress::depends_on])
+    pub(crate) fn assume(&mut self, depth: usize) {
+        if let Some(innermost) = self.in_progress.last_mut() {
+            innermost.depends_on = innermost.depends_on.min(depth);
+        }
+    }
+}
+
+impl SubtypeKey {
+    fn of(rust_type: &RustType) -> Option<Self> {
+        match (rust_type.type_id, &rust_type.type_name) {
+            (Some(type_id), _) => Some(SubtypeKey::Id(type_id)),
+            (None, type_name) if !type_name.is_anonymous() => Some(SubtypeKey::Name(type_name.clone())),
+            (None, _) => None
+        }
+    }
+}
+
+impl RustType {
+    /// [RustType::is_rough_subtype_of] with the cache's policy, consulting and filling the cache
+    pub fn is_rough_subtype_of_cached(&self, other: &RustType, cache: &mut SubtypeCache) -> IsSubtypeOf {
+        cache.sync_with_registry(current_registry_generation());
+        self.is_rough_subtype_of_in(other, &mut SubtypeCx::with_cache(cache, false)).result
+    }
+
+    /// [RustType::explain_subtype] with the cache's policy, consulting and filling the cache
+    pub fn explain_subtype_cached(&self, other: &RustType, cache: &mut SubtypeCache) -> SubtypeReport {
+        cache.sync_with_registry(current_registry_generation());
+        self.is_rough_subtype_of_in(other, &mut SubtypeCx::with_cache(cache, true)).outermost_first()
+    }
+
+    /// [RustType::explain_subtype_cached], but covariant pointer checks look up the pointees
+    /// in `registry` instead of the global registry
+    #[cfg(feature = "registry")]
+    pub fn explain_subtype_cached_in_registry(&self, other: &RustType, cache: &mut SubtypeCache, registry: &TypeRegistry) -> SubtypeReport {
+        cache.sync_with_registry(registry.generation());
+        self.is_rough_subtype_of_in(other, &mut SubtypeCx::with_cache(cache, true).in_registry(registry)).outermost_first()
+    }
+
+    /// [RustType::is_structural_subtype_of] with the cache's policy, consulting and filling the cache
+    /// for the nested types
+    pub fn is_structural_subtype_of_cached(&self, other: &RustType, cache: &mut SubtypeCache) -> IsSubtypeOf {
+        self.structure.is_structural_subtype_of_cached(&other.structure, cache)
+    }
+}
+
+impl TypeStructure {
+    /// [TypeStructure::is_structural_subtype_of] with the cache's policy, consulting and filling
+    /// the cache for the nested types
+    pub fn is_structural_subtype_of_cached(&self, other: &TypeStructure, cache: &mut SubtypeCache) -> IsSubtypeOf {
+        cache.sync_with_registry(current_registry_generation());
+        self.is_structural_subtype_of_in(other, &mut SubtypeCx::with_cache(cache, false)).result
+    }
+
+    /// [TypeStructure::explain_subtype] with the cache's policy, consulting and filling the cache
+    /// for the nested types
+    pub fn explain_subtype_cached(&self, other: &TypeStructure, cache: &mut SubtypeCache) -> SubtypeReport {
+        cache.sync_with_registry(current_registry_generation());
+        self.is_structural_subtype_of_in(other, &mut SubtypeCx::with_cache(cache, true)).outermost_first()
+    }
+
+    /// [TypeStructure::explain_subtype_cached], but covariant pointer checks look up the pointees
+    /// in `registry` instead of the global registry
+    #[cfg(feature = "registry")]
+    pub fn explain_subtype_cached_in_registry(&self, other: &TypeStructure, cache: &mut SubtypeCache, registry: &TypeRegistry) -> SubtypeReport {
+        cache.sync_with_registry(registry.generation());
+        self.is_structural_subtype_of_in(other, &mut SubtypeCx::with_cache(cache, true).in_registry(registry)).outermost_first()
+    }
+}
+
+#[cfg(feature = "registry")]
+fn current_registry_generation() -> u64 {
+    crate::registry::registry_generation()
+}
+
+#[cfg(not(feature = "registry"))]
+fn current_registry_generation() -> u64 {
+    0
+}
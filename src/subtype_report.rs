@@ -0,0 +1,203 @@
+use std::fmt::{Display, Formatter};
+use crate::{IsSubtypeOf, PrimitiveType, RustPointerKind, RustTypeName, TypeStructureBodyForm, TypePath, TypePathElem, TypeVarId};
+
+/// Result of a subtype check along with why it isn't [IsSubtypeOf::Yes].
+/// Returned by [RustType::explain_subtype](crate::RustType::explain_subtype) and [TypeStructure::explain_subtype](crate::TypeStructure::explain_subtype).
+///
+/// Display renders a multi-line diagnostic.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubtypeReport {
+    pub result: IsSubtypeOf,
+    /// `None` iff `result` is [IsSubtypeOf::Yes]. Otherwise, the first part of the types which is
+    /// not a subtype (or if `result` is [IsSubtypeOf::Unknown], which we don't know is a subtype)
+    pub failure: Option<SubtypeFailure>
+}
+
+/// Where and why a subtype check failed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubtypeFailure {
+    /// Path to the failing part, which is the same in both types
+    pub path: TypePath,
+    /// The rule which failed
+    pub rule: SubtypeRule
+}
+
+/// Subtyping rule which failed.
+/// `self` refers to the type being checked, `other` to the supposed supertype.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SubtypeRule {
+    /// `other` is bottom and `self` isn't
+    NotBottom,
+    /// Both types have type ids and they are different
+    TypeIdMismatch { type_name: RustTypeName, other_type_name: RustTypeName },
+    /// One of the types is opaque, so we don't know
+    Opaque,
+    /// One of the types is a type variable which isn't the same as the other, so we don't know
+    UnresolvedVar { var: TypeVarId },
+    /// The types are different kinds of structures (e.g. a struct and an enum)
+    KindMismatch { kind: &'static str, other_kind: &'static str },
+    /// Both types are structs or variants but one is a unit/tuple/field body and the other isn't
+    BodyFormMismatch { form: TypeStructureBodyForm, other_form: TypeStructureBodyForm },
+    /// Both types are primitives and they are different
+    PrimitiveMismatch { primitive: PrimitiveType, other_primitive: PrimitiveType },
+    /// `other` has a field which `self` doesn't
+    MissingField { name: String },
+    /// `self` has an enum variant which `other` doesn't
+    ExtraVariant { name: String },
+    /// `self` has a field which `other` doesn't, and the policy doesn't allow extra fields
+    ExtraField { name: String },
+    /// `other` has an enum variant which `self` doesn't, and the policy doesn't allow missing variants
+    MissingVariant { name: String },
+    /// Tuples, tuple structures, or arrays have different lengths
+    LengthMismatch { length: usize, other_length: usize },
+    /// `self`'s pointer kind isn't a subtype of `other`'s
+    PointerKind { ptr_kind: RustPointerKind, other_ptr_kind: RustPointerKind },
+    /// Pointers have different sizes (e.g. one is thin and the other is fat)
+    PointerSize { ptr_size: usize, other_ptr_size: usize },
+    /// Pointers point to different types (and aren't covariant)
+    PointeeMismatch { refd_name: RustTypeName, other_refd_name: RustTypeName },
+    /// Pointers are covariant but the pointee isn't registered, so we don't know its structure
+    UnregisteredPointee { refd_name: RustTypeName },
+    /// Pointers are covariant and the pointee is a structural subtype, but its layout doesn't start with `other`'s pointee,
+    /// or it does but its fields or variants are in a different order
+    PointeeLayout { refd_name: RustTypeName, other_refd_name: RustTypeName },
+    /// `self` is a slice and `other` is an array
+    SliceNotArray
+}
+
+impl SubtypeReport {
+    pub fn yes() -> Self {
+        SubtypeReport {
+            result: IsSubtypeOf::Yes,
+            failure: None
+        }
+    }
+
+    pub fn unknown(rule: SubtypeRule) -> Self {
+        SubtypeReport {
+            result: IsSubtypeOf::Unknown,
+            failure: Some(SubtypeFailure {
+                path: TypePath::empty(),
+                rule
+            })
+        }
+    }
+
+    pub fn no(rule: SubtypeRule) -> Self {
+        SubtypeReport {
+            result: IsSubtypeOf::No,
+            failure: Some(SubtypeFailure {
+                path: TypePath::empty(),
+                rule
+            })
+        }
+    }
+
+    /// A failed check with the given result. If `explain`, it has the rule, otherwise it has no failure
+    /// (for checks which only need the result, so failures don't allocate)
+    pub(crate) fn failed(result: IsSubtypeOf, explain: bool, rule: impl FnOnce() -> SubtypeRule) -> Self {
+        SubtypeReport {
+            result,
+            failure: explain.then(|| SubtypeFailure {
+                path: TypePath::empty(),
+                rule: rule()
+            })
+        }
+    }
+
+    /// The report is for a nested part of the type at the given path component.
+    ///
+    /// While checking, failure paths are built innermost-first, so this adds the component
+    /// to the end. The component is only created if there is a failure.
+    /// [SubtypeReport::outermost_first] puts the path in order when the check is done.
+    pub(crate) fn nested_in(mut self, elem: impl FnOnce() -> TypePathElem) -> Self {
+        if let Some(failure) = &mut self.failure {
+            failure.path.push(elem());
+        }
+        self
+    }
+
+    /// If `self` is `Yes`, returns `Unknown` with [SubtypeRule::Opaque] (see [SubtypeReport::failed]), otherwise returns `self`.
+    pub(crate) fn downgrade_to_unknown(self, explain: bool) -> Self {
+        match self.result {
+            IsSubtypeOf::Yes => SubtypeReport::failed(IsSubtypeOf::Unknown, explain, || SubtypeRule::Opaque),
+            IsSubtypeOf::Unknown | IsSubtypeOf::No => self
+        }
+    }
+
+    /// Reverses the failure path built by [SubtypeReport::nested_in]
+    pub(crate) fn outermost_first(mut self) -> Self {
+        if let Some(failure) = &mut self.failure {
+            failure.path.reverse();
+        }
+        self
+    }
+
+    /// Returns the first report with the lowest result, stopping early on [IsSubtypeOf::No]
+    pub(crate) fn min(reports: impl IntoIterator<Item=SubtypeReport>) -> Self {
+        let mut min = SubtypeReport::yes();
+        for report in reports {
+            if report.result < min.result {
+                min = report;
+                if min.result == IsSubtypeOf::No {
+                    break;
+                }
+            }
+        }
+        min
+    }
+}
+
+impl Display for SubtypeReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.result {
+            IsSubtypeOf::Yes => write!(f, "is a subtype")?,
+            IsSubtypeOf::Unknown => write!(f, "may not be a subtype")?,
+            IsSubtypeOf::No => write!(f, "is not a subtype")?
+        }
+        if let Some(failure) = &self.failure {
+            write!(f, ": {}", failure.rule)?;
+            for elem in failure.path.iter().rev() {
+                match elem {
+                    TypePathElem::Field(name) => write!(f, "\n  in field `{}`", name)?,
+                    TypePathElem::Index(index) => write!(f, "\n  in element {}", index)?,
+                    TypePathElem::Variant(name) => write!(f, "\n  in variant `{}`", name)?,
+                    TypePathElem::Elem => write!(f, "\n  in array element")?,
+                    TypePathElem::Pointee => write!(f, "\n  in pointee")?
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Display for SubtypeFailure {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at {}", self.rule, self.path)
+    }
+}
+
+impl Display for SubtypeRule {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SubtypeRule::NotBottom => write!(f, "only bottom is a subtype of bottom"),
+            SubtypeRule::TypeIdMismatch { type_name, other_type_name } => write!(f, "different types: {} and {}", type_name.qualified(), other_type_name.qualified()),
+            SubtypeRule::Opaque => write!(f, "opaque structure encountered"),
+            SubtypeRule::UnresolvedVar { var } => write!(f, "unresolved type variable {} encountered", var),
+            SubtypeRule::KindMismatch { kind, other_kind } => write!(f, "{} is not a {}", kind, other_kind),
+            SubtypeRule::BodyFormMismatch { form, other_form } => write!(f, "has {} but expected {}", form, other_form),
+            SubtypeRule::PrimitiveMismatch { primitive, other_primitive } => write!(f, "primitive {} is not {}", primitive, other_primitive),
+            SubtypeRule::MissingField { name } => write!(f, "missing field `{}`", name),
+            SubtypeRule::ExtraVariant { name } => write!(f, "has extra variant `{}`", name),
+            SubtypeRule::ExtraField { name } => write!(f, "has extra field `{}`", name),
+            SubtypeRule::MissingVariant { name } => write!(f, "missing variant `{}`", name),
+            SubtypeRule::LengthMismatch { length, other_length } => write!(f, "has {} elements but expected {}", length, other_length),
+            SubtypeRule::PointerKind { ptr_kind, other_ptr_kind } => write!(f, "`{}` pointer is not a `{}` pointer", ptr_kind.to_string().trim_end(), other_ptr_kind.to_string().trim_end()),
+            SubtypeRule::PointerSize { ptr_size, other_ptr_size } => write!(f, "pointer has size {} but expected {}", ptr_size, other_ptr_size),
+            SubtypeRule::PointeeMismatch { refd_name, other_refd_name } => write!(f, "points to {} but expected {}", refd_name.qualified(), other_refd_name.qualified()),
+            SubtypeRule::UnregisteredPointee { refd_name } => write!(f, "pointee {} is not registered", refd_name.qualified()),
+            SubtypeRule::PointeeLayout { refd_name, other_refd_name } => write!(f, "layout of pointee {} doesn't start with layout of {}", refd_name.qualified(), other_refd_name.qualified()),
+            SubtypeRule::SliceNotArray => write!(f, "slice is not an array")
+        }
+    }
+}
@@ -0,0 +1,332 @@
+use std::fmt::{Debug, Formatter};
+use std::mem::{align_of, size_of};
+use std::sync::{Arc, Mutex};
+use crate::{align_up, PrimitiveType, Qualifier, RustPointerKind, RustType, RustTypeName, TypeRegistry, TypeStructure, TypeStructureBody};
+
+/// A generic type (e.g. `Vec<T>`) whose instantiations (e.g. `Vec<u32>`) are created on lookup.
+///
+/// The template matches names with the same qualifier, simple name, and number of generic arguments.
+/// When one is looked up (see [TypeRegistry::lookup_or_instantiate]), the generic arguments are
+/// resolved to types and passed to `instantiate`, along with the instantiated name.
+#[derive(Clone)]
+pub struct TypeTemplate {
+    qualifier: Qualifier,
+    simple_name: String,
+    num_params: usize,
+    instantiate: Arc<InstantiateFn>
+}
+
+type InstantiateFn = dyn Fn(RustTypeName, &[RustType]) -> Option<RustType> + Send + Sync;
+
+/// Identifies the names a [TypeTemplate] matches
+pub(crate) type TypeTemplateKey = (Qualifier, String, usize);
+
+impl TypeTemplate {
+    /// Creates a template for the given name, e.g. `Vec<T>`. Only the number of generic arguments
+    /// matters, not their names.
+    ///
+    /// `instantiate` returns the type given its name and generic arguments,
+    /// or `None` if it can't be instantiated with them.
+    ///
+    /// Panics if `name` isn't an identifier with generic arguments
+    pub fn new(name: RustTypeName, instantiate: impl Fn(RustTypeName, &[RustType]) -> Option<RustType> + Send + Sync + 'static) -> Self {
+        match name {
+            RustTypeName::Ident { qualifier, simple_name, generic_args } if !generic_args.is_empty() => TypeTemplate {
+                qualifier,
+                simple_name,
+                num_params: generic_args.len(),
+                instantiate: Arc::new(instantiate)
+            },
+            _ => panic!("template name must be an identifier with generic arguments: {}", name.qualified())
+        }
+    }
+
+    /// The generic type's name, with parameters named `T0`, `T1`, ...
+    pub fn type_name(&self) -> RustTypeName {
+        RustTypeName::Ident {
+            qualifier: self.qualifier.clone(),
+            simple_name: self.simple_name.clone(),
+            generic_args: (0..self.num_params).map(|index| RustTypeName::simple(format!("T{}", index))).collect()
+        }
+    }
+
+    pub(crate) fn key(&self) -> TypeTemplateKey {
+        (self.qualifier.clone(), self.simple_name.clone(), self.num_params)
+    }
+
+    /// The key of the template which would match the type name, if any could
+    pub(crate) fn key_of(type_name: &RustTypeName) -> Option<TypeTemplateKey> {
+        match type_name {
+            RustTypeName::Ident { qualifier, simple_name, generic_args } if !generic_args.is_empty() => {
+                Some((qualifier.clone(), simple_name.clone(), generic_args.len()))
+            }
+            _ => None
+        }
+    }
+}
+
+impl Debug for TypeTemplate {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TypeTemplate")
+            .field("type_name", &self.type_name())
+            .finish_non_exhaustive()
+    }
+}
+
+impl TypeRegistry {
+    /// Registers the template so that lookups of its instantiations (via [TypeRegistry::lookup_or_instantiate]
+    /// and [RustType::lookup]) create them. Replaces any template with the same name and number of parameters.
+    pub fn register_template(&mut self, template: TypeTemplate) {
+        self.templates.insert(template.key(), template);
+        self.bump_generation();
+    }
+
+    /// Unregisters the template which would match the given name, returning it if there was one.
+    pub fn unregister_template(&mut self, type_name: &RustTypeName) -> Option<TypeTemplate> {
+        let template = self.templates.remove(&TypeTemplate::key_of(type_name)?);
+        if template.is_some() {
+            self.bump_generation();
+        }
+        template
+    }
+
+    /// Returns the template which would match the given name
+    pub fn lookup_template(&self, type_name: &RustTypeName) -> Option<&TypeTemplate> {
+        self.templates.get(&TypeTemplate::key_of(type_name)?)
+    }
+
+    /// Iterate all registered templates
+    pub fn templates(&self) -> impl Iterator<Item=&TypeTemplate> + '_ {
+        self.templates.values()
+    }
+
+    /// Returns the registered type with the given type name, or if there is none, instantiates
+    /// the template which matches it.
+    ///
+    /// The generic arguments are resolved to primitives, registered types, or other template instantiations.
+    /// If any of them are unknown or the template can't instantiate them, this falls back to
+    /// the registered type with erased generics (e.g. `Vec<{unknown}>`).
+    pub fn lookup_or_instantiate(&self, type_name: &RustTypeName) -> Option<Arc<RustType>> {
+        if let Some(rust_type) = self.lookup(type_name) {
+            return Some(rust_type.clone());
+        }
+        let template = self.lookup_template(type_name)?;
+        self.instantiate(template, type_name).map(Arc::new).or_else(|| {
+            let mut erased_name = type_name.clone();
+            erased_name.erase_generics();
+            self.lookup(&erased_name).cloned()
+        })
+    }
+
+    fn instantiate(&self, template: &TypeTemplate, type_name: &RustTypeName) -> Option<RustType> {
+        let RustTypeName::Ident { generic_args, .. } = type_name else {
+            unreachable!("template matched a non-identifier")
+        };
+        let generic_args = generic_args.iter()
+            .map(|generic_arg| self.resolve_generic_arg(generic_arg))
+            .collect::<Option<Vec<_>>>()?;
+        (template.instantiate)(type_name.clone(), &generic_args)
+    }
+
+    fn resolve_generic_arg(&self, type_name: &RustTypeName) -> Option<RustType> {
+        if type_name.is_anonymous() {
+            return None;
+        }
+        if let RustTypeName::Ident { qualifier, simple_name, generic_args } = type_name {
+            if qualifier.is_local() && generic_args.is_empty() {
+                if let Some(primitive) = PrimitiveType::from_name(simple_name) {
+                    return Some(primitive.rust_type());
+                }
+            }
+        }
+        // Don't fall back to the erased type: then the instantiation would be wrong
+        match self.lookup(type_name) {
+            Some(rust_type) => Some(RustType::clone(rust_type)),
+            None => self.instantiate(self.lookup_template(type_name)?, type_name)
+        }
+    }
+
+    /// Registers a template for a builtin generic type whose layout doesn't depend on its (sized)
+    /// generic arguments, e.g. `Vec<T>`. `erased_name` is the registered erased type, e.g. `Vec<{unknown}>`
+    pub(crate) fn register_builtin_template(&mut self, erased_name: &str) {
+        let erased_name = RustTypeName::try_from(erased_name).expect("bad builtin name");
+        let erased_type = self.lookup(&erased_name).expect("builtin template's erased type not registered");
+        let (size, align) = (erased_type.size, erased_type.align);
+        self.register_template(TypeTemplate::new(erased_name, move |type_name, generic_args| {
+            if generic_args.iter().any(|generic_arg| generic_arg.size == usize::MAX) {
+                // Unsized arguments may change the layout (e.g. `Box<[T]>` is a fat pointer)
+                return None;
+            }
+            Some(RustType {
+                type_id: None,
+                type_name,
+                size,
+                align,
+                structure: TypeStructure::Opaque
+            })
+        }));
+    }
+
+    /// Registers a template for a builtin generic type with the same layout as its generic argument,
+    /// e.g. `Cell<T>`. If `transparent`, the type's structure is a tuple struct containing the argument,
+    /// otherwise it's opaque (e.g. `MaybeUninit<T>`, which may not be initialized)
+    pub(crate) fn register_builtin_wrapper_template(&mut self, name: &str, transparent: bool) {
+        let name = RustTypeName::try_from(name).expect("bad builtin name");
+        self.register_template(TypeTemplate::new(name, move |type_name, generic_args| {
+            let [inner] = generic_args else {
+                return None;
+            };
+            if inner.size == usize::MAX {
+                return None;
+            }
+            Some(RustType {
+                type_id: None,
+                type_name,
+                size: inner.size,
+                align: inner.align,
+                structure: match transparent {
+                    false => TypeStructure::Opaque,
+                    true => TypeStructure::CReprStruct {
+                        body: TypeStructureBody::Tuple(vec![inner.clone()])
+                    }
+                }
+            })
+        }));
+    }
+
+    /// Registers a template for a builtin generic type whose layout depends on its generic arguments,
+    /// e.g. `Option<T>`. The type's structure is opaque, and `layout` computes its size and alignment
+    /// from the arguments, or returns `None` if it can't (e.g. it depends on niches it can't see)
+    pub(crate) fn register_builtin_layout_template(&mut self, name: &str, layout: fn(&[RustType]) -> Option<(usize, usize)>) {
+        let name = RustTypeName::try_from(name).expect("bad builtin name");
+        self.register_template(TypeTemplate::new(name, move |type_name, generic_args| {
+            if generic_args.iter().any(|generic_arg| generic_arg.size == usize::MAX) {
+                return None;
+            }
+            let (size, align) = layout(generic_args)?;
+            Some(RustType {
+                type_id: None,
+                type_name,
+                size,
+                align,
+                structure: TypeStructure::Opaque
+            })
+        }));
+    }
+}
+
+// Layouts of builtin generic types, computed the way rustc lays them out.
+// These aren't guaranteed, so tests compare them to the compiler's
+
+/// `Option<T>`: `T` if `T` has a niche to store `None` in, otherwise tagged
+pub(crate) fn option_layout(generic_args: &[RustType]) -> Option<(usize, usize)> {
+    let [some] = generic_args else { return None };
+    match has_niche(some)? {
+        true => Some((some.size, some.align)),
+        false => Some(tagged_layout(&[None, Some(some)]))
+    }
+}
+
+/// `Result<T, E>`, only if neither has a niche (otherwise rustc may store the tag in one)
+pub(crate) fn result_layout(generic_args: &[RustType]) -> Option<(usize, usize)> {
+    let [ok, err] = generic_args else { return None };
+    (!has_niche(ok)? && !has_niche(err)?).then(|| tagged_layout(&[Some(ok), Some(err)]))
+}
+
+/// `Cow<B>` for sized `B`, whose owned type is `B`. Only if `B` has no niche, like [result_layout]
+pub(crate) fn cow_layout(generic_args: &[RustType]) -> Option<(usize, usize)> {
+    let [owned] = generic_args else { return None };
+    let borrowed = RustType::of_dont_register::<&()>();
+    match owned.size {
+        // The reference's niche stores `Owned`
+        0 => Some((borrowed.size, borrowed.align)),
+        _ => (!has_niche(owned)?).then(|| tagged_layout(&[Some(&borrowed), Some(owned)]))
+    }
+}
+
+/// `RefCell<T>`: a borrow flag (`isize`) and `T`
+pub(crate) fn ref_cell_layout(generic_args: &[RustType]) -> Option<(usize, usize)> {
+    let [value] = generic_args else { return None };
+    Some(struct_layout(&[(size_of::<isize>(), align_of::<isize>()), (value.size, value.align)]))
+}
+
+/// `Mutex<T>`: a platform-specific lock, a poison flag (`bool`), and `T`.
+/// The lock's layout is inferred from `Mutex<()>`'s
+pub(crate) fn mutex_layout(generic_args: &[RustType]) -> Option<(usize, usize)> {
+    let [value] = generic_args else { return None };
+    let lock_align = align_of::<Mutex<()>>();
+    // `Mutex<()>` is the lock and the flag, padded to the lock's alignment
+    let lock_size = size_of::<Mutex<()>>() - lock_align;
+    Some(struct_layout(&[(lock_size, lock_align), (1, 1), (value.size, value.align)]))
+}
+
+/// `Range<Idx>`: `start` and `end`
+pub(crate) fn range_layout(generic_args: &[RustType]) -> Option<(usize, usize)> {
+    let [idx] = generic_args else { return None };
+    Some(struct_layout(&[(idx.size, idx.align), (idx.size, idx.align)]))
+}
+
+/// `RangeInclusive<Idx>`: `start`, `end`, and whether it's exhausted (`bool`)
+pub(crate) fn range_inclusive_layout(generic_args: &[RustType]) -> Option<(usize, usize)> {
+    let [idx] = generic_args else { return None };
+    Some(struct_layout(&[(idx.size, idx.align), (idx.size, idx.align), (1, 1)]))
+}
+
+/// `RangeFrom<Idx>`, `RangeTo<Idx>`, and `RangeToInclusive<Idx>`: one bound
+pub(crate) fn range_bound_layout(generic_args: &[RustType]) -> Option<(usize, usize)> {
+    let [idx] = generic_args else { return None };
+    Some((idx.size, idx.align))
+}
+
+/// Layout of a non-`repr(C)` struct with fields of the given sizes and alignments.
+/// rustc sorts the fields by alignment, so there's no padding between them
+fn struct_layout(fields: &[(usize, usize)]) -> (usize, usize) {
+    let align = fields.iter().map(|(_, align)| *align).max().unwrap_or(1);
+    (align_up(fields.iter().map(|(size, _)| size).sum(), align), align)
+}
+
+/// Layout of a non-`repr(C)` enum whose variants have at most one field, and which doesn't store
+/// its tag in a niche: each variant is the tag (`u8`) followed by its field
+fn tagged_layout(variants: &[Option<&RustType>]) -> (usize, usize) {
+    let align = variants.iter().flatten().map(|field| field.align).max().unwrap_or(1);
+    let size = variants.iter().map(|field| match field {
+        None => 1,
+        Some(field) => align_up(1, field.align) + field.size
+    }).max().unwrap_or(0);
+    (align_up(size, align), align)
+}
+
+/// Whether the type has invalid values which rustc can store an enum's tag in, `None` if we don't know
+/// (e.g. the type is opaque)
+fn has_niche(rust_type: &RustType) -> Option<bool> {
+    match &rust_type.structure {
+        TypeStructure::Primitive(primitive) => Some(matches!(primitive, PrimitiveType::Bool | PrimitiveType::Char)),
+        TypeStructure::Pointer { ptr_kind, .. } => Some(matches!(ptr_kind, RustPointerKind::ImmRef | RustPointerKind::MutRef)),
+        TypeStructure::CTuple { elements } => any_has_niche(elements.iter()),
+        TypeStructure::CReprStruct { body: TypeStructureBody::None } => Some(false),
+        TypeStructure::CReprStruct { body: TypeStructureBody::Tuple(elements) } => any_has_niche(elements.iter()),
+        TypeStructure::CReprStruct { body: TypeStructureBody::Fields(fields) } => any_has_niche(fields.iter().map(|field| &field.rust_type)),
+        TypeStructure::Array { elem, length } => match length {
+            0 => Some(false),
+            _ => has_niche(elem)
+        },
+        TypeStructure::Opaque |
+        TypeStructure::OpaqueTuple { .. } |
+        TypeStructure::OpaqueFields { .. } |
+        TypeStructure::CReprEnum { .. } |
+        TypeStructure::Slice { .. } |
+        TypeStructure::Var(_) => None
+    }
+}
+
+fn any_has_niche<'a>(rust_types: impl Iterator<Item=&'a RustType>) -> Option<bool> {
+    let mut known = true;
+    for rust_type in rust_types {
+        match has_niche(rust_type) {
+            Some(true) => return Some(true),
+            Some(false) => {}
+            None => known = false
+        }
+    }
+    known.then_some(false)
+}
@@ -6,6 +6,7 @@ use std::num::{ParseFloatError, ParseIntError, TryFromIntError};
 use join_lazy_fmt::Join;
 use derive_more::{Display, Error};
 use logos::{Lexer, Logos};
+use crate::TypeVarId;
 
 /// A detailed rust type name which lets you extract components like generic args if identifier, or underlying type if a reference.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -20,6 +21,10 @@ pub enum RustTypeName {
     Anonymous {
         desc: Cow<'static, str>
     },
+    /// Name of a type variable ([TypeStructure::Var](crate::TypeStructure::Var)), displayed and parsed like `{?0}`
+    Var {
+        var: TypeVarId
+    },
     /// Constant expression (usually as a generic arg)
     ConstExpr {
         code_as_string: String
@@ -58,6 +63,7 @@ pub macro qualifier {
 
 /// "pointer" encompasses both references and raw pointers, and this enum contains their immutable (shared) and mutable variants.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum RustPointerKind {
     ImmRef,
     MutRef,
@@ -203,6 +209,7 @@ impl RustTypeName {
                 }
             }
             RustTypeName::Anonymous { .. } => {}
+            RustTypeName::Var { .. } => {}
             RustTypeName::ConstExpr { .. } => {}
             RustTypeName::Pointer { .. } => {}
             RustTypeName::Tuple { elems } => {
@@ -219,6 +226,33 @@ impl RustTypeName {
         }
     }
 
+    /// Whether this is the same as `full`, except identifiers here with no qualifier match the same
+    /// identifiers in `full` with any qualifier. e.g. `Vec<Point>` matches `Vec<geo::Point>`
+    pub fn matches_partial(&self, full: &RustTypeName) -> bool {
+        match (self, full) {
+            (RustTypeName::Ident { qualifier, simple_name, generic_args }, RustTypeName::Ident {
+                qualifier: full_qualifier,
+                simple_name: full_simple_name,
+                generic_args: full_generic_args
+            }) => (qualifier.is_local() || qualifier == full_qualifier) &&
+                simple_name == full_simple_name &&
+                generic_args.len() == full_generic_args.len() &&
+                generic_args.iter().zip(full_generic_args).all(|(arg, full_arg)| arg.matches_partial(full_arg)),
+            (RustTypeName::Pointer { ptr_kind, refd }, RustTypeName::Pointer { ptr_kind: full_ptr_kind, refd: full_refd }) => {
+                ptr_kind == full_ptr_kind && refd.matches_partial(full_refd)
+            }
+            (RustTypeName::Tuple { elems }, RustTypeName::Tuple { elems: full_elems }) => {
+                elems.len() == full_elems.len() &&
+                    elems.iter().zip(full_elems).all(|(elem, full_elem)| elem.matches_partial(full_elem))
+            }
+            (RustTypeName::Array { elem, length }, RustTypeName::Array { elem: full_elem, length: full_length }) => {
+                length == full_length && elem.matches_partial(full_elem)
+            }
+            (RustTypeName::Slice { elem }, RustTypeName::Slice { elem: full_elem }) => elem.matches_partial(full_elem),
+            (this, full) => this == full
+        }
+    }
+
     /// Iterate the type's own (if identifier) and nested simple names
     pub fn iter_simple_names(&self) -> impl Iterator<Item=&str> {
         match self {
@@ -232,6 +266,7 @@ impl RustTypeName {
                 )) as Box<dyn Iterator<Item=&str>>
             }
             RustTypeName::Anonymous { .. } => Box::new(empty()) as Box<dyn Iterator<Item=&str>>,
+            RustTypeName::Var { .. } => Box::new(empty()) as Box<dyn Iterator<Item=&str>>,
             RustTypeName::ConstExpr { .. } => Box::new(empty()) as Box<dyn Iterator<Item=&str>>,
             RustTypeName::Pointer { ptr_kind: _, refd } => refd.iter_simple_names(),
             RustTypeName::Tuple { elems } => Box::new(
@@ -354,6 +389,7 @@ impl<'a, 'b> Display for RustTypeNameDisplay<'a, 'b> {
                 Ok(())
             }
             RustTypeName::Anonymous { desc } => write!(f, "{{{}}}", desc),
+            RustTypeName::Var { var } => write!(f, "{{{}}}", var),
             RustTypeName::ConstExpr { code_as_string } => write!(f, "{}", code_as_string),
             RustTypeName::Pointer {
                 ptr_kind,
@@ -372,7 +408,7 @@ impl Display for RustPointerKind {
             RustPointerKind::ImmRef => write!(f, "&"),
             RustPointerKind::MutRef => write!(f, "&mut "),
             RustPointerKind::ImmRaw => write!(f, "*const "),
-            RustPointerKind::MutRaw => write!(f, "&mut ")
+            RustPointerKind::MutRaw => write!(f, "*mut ")
         }
     }
 }
@@ -612,6 +648,17 @@ impl RustTypeName {
                                 None => return Err(unexpected_end(lexer))
                             }
                         },
+                        Some(RustTypeNameToken::Punct('?')) => match lexer.next() {
+                            Some(RustTypeNameToken::Integer(Ok(var))) if var >= 0 => match lexer.next() {
+                                Some(RustTypeNameToken::Punct('}')) => RustTypeNameParseState::Done {
+                                    result: RustTypeName::Var { var: TypeVarId(var as usize) }
+                                },
+                                Some(_) => return Err(unexpected(lexer)),
+                                None => return Err(unexpected_end(lexer))
+                            },
+                            Some(_) => return Err(unexpected(lexer)),
+                            None => return Err(unexpected_end(lexer))
+                        },
                         _ => return Err(unexpected(lexer))
                     },
                     RustTypeNameToken::Integer(integer) => match integer {
@@ -0,0 +1,102 @@
+use std::fmt::{Display, Formatter};
+
+/// Path to a nested type or value inside of a compound type, e.g. `.range.start` or `::Some.0`.
+/// The empty path refers to the type itself.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct TypePath(Vec<TypePathElem>);
+
+/// Component of a [TypePath]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum TypePathElem {
+    /// Field of a field struct or variant
+    Field(String),
+    /// Element of a tuple, tuple struct or variant, or array
+    Index(usize),
+    /// Enum variant
+    Variant(String),
+    /// Element type of an array or slice (when we refer to every element)
+    Elem,
+    /// Type behind a pointer
+    Pointee
+}
+
+impl TypePath {
+    /// The path to the type itself
+    pub fn empty() -> Self {
+        TypePath(Vec::new())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Iterate the components from outermost to innermost
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item=&TypePathElem> + ExactSizeIterator {
+        self.0.iter()
+    }
+
+    /// Add a component to the end (innermost)
+    pub fn push(&mut self, elem: TypePathElem) {
+        self.0.push(elem);
+    }
+
+    /// Remove the last (innermost) component
+    pub fn pop(&mut self) -> Option<TypePathElem> {
+        self.0.pop()
+    }
+
+    /// Reverse the order of the components
+    pub(crate) fn reverse(&mut self) {
+        self.0.reverse();
+    }
+}
+
+impl From<Vec<TypePathElem>> for TypePath {
+    fn from(elems: Vec<TypePathElem>) -> Self {
+        TypePath(elems)
+    }
+}
+
+impl FromIterator<TypePathElem> for TypePath {
+    fn from_iter<T: IntoIterator<Item=TypePathElem>>(iter: T) -> Self {
+        TypePath(iter.into_iter().collect())
+    }
+}
+
+impl<'a> IntoIterator for &'a TypePath {
+    type Item = &'a TypePathElem;
+    type IntoIter = std::slice::Iter<'a, TypePathElem>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+impl Display for TypePath {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.0.is_empty() {
+            write!(f, "<root>")
+        } else {
+            for elem in &self.0 {
+                write!(f, "{}", elem)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+impl Display for TypePathElem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TypePathElem::Field(name) => write!(f, ".{}", name),
+            TypePathElem::Index(index) => write!(f, ".{}", index),
+            TypePathElem::Variant(name) => write!(f, "::{}", name),
+            TypePathElem::Elem => write!(f, "[]"),
+            TypePathElem::Pointee => write!(f, ".*")
+        }
+    }
+}
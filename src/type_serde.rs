@@ -0,0 +1,51 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde::de::Error as _;
+use serde::ser::Error as _;
+use crate::{RustType, RustTypeName, TypeRegistry, TypeStructure, TypeVarId};
+
+/// Serialized as the qualified name
+impl Serialize for RustTypeName {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&self.qualified())
+    }
+}
+
+/// Deserialized by parsing the name
+impl<'de> Deserialize<'de> for RustTypeName {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        RustTypeName::try_from(name.as_str()).map_err(|cause| D::Error::custom(format_args!("bad type name {:?}: {}", name, cause)))
+    }
+}
+
+/// [RustType] without its type id
+#[derive(Deserialize)]
+#[serde(rename = "RustType")]
+struct RustTypeFields {
+    type_name: RustTypeName,
+    size: usize,
+    align: usize,
+    structure: TypeStructure
+}
+
+/// The type id (and pointee id) are those of the registered types with the same names.
+/// The type id is only used if the registered type has the same layout, otherwise the type id would
+/// let values be reinterpreted as the registered type (e.g. by [DynValue::downcast](crate::DynValue::downcast))
+impl<'de> Deserialize<'de> for RustType {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let RustTypeFields { type_name, size, align, mut structure } = RustTypeFields::deserialize(deserializer)?;
+        TypeRegistry::with_global(|registry| {
+            if let TypeStructure::Pointer { refd_id, refd_name, .. } = &mut structure {
+                *refd_id = registry.lookup_id(refd_name);
+            }
+            let mut rust_type = RustType { type_id: None, type_name, size, align, structure };
+            rust_type.type_id = registry.lookup_id_with_same_layout(&rust_type);
+            Ok(rust_type)
+        }).expect("with_global always returns Some")
+    }
+}
+
+/// [TypeStructure::Var] isn't serializable, the variable must be resolved first
+pub(crate) fn serialize_var<S: Serializer>(var: &TypeVarId, _serializer: S) -> Result<S::Ok, S::Error> {
+    Err(S::Error::custom(format_args!("can't serialize type variable {}, resolve it first", var)))
+}
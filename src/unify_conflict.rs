@@ -0,0 +1,102 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use crate::{PrimitiveType, RustTypeName, TypePath, TypeStructureBodyForm};
+
+/// Every place where [RustType::try_unify](crate::RustType::try_unify) or
+/// [TypeStructure::try_unify](crate::TypeStructure::try_unify) found the two types disagree.
+///
+/// Display renders one line per conflict.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnifyConflicts(pub Vec<UnifyConflict>);
+
+/// A place where the two unified types disagree, so that part of `self` was left unchanged
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnifyConflict {
+    /// Path to the conflicting part, which is the same in both types
+    pub path: TypePath,
+    pub kind: UnifyConflictKind
+}
+
+/// How the two unified types disagree.
+/// `self` refers to the "explicitly-provided" type, `other` to the "inferred-by-value" type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UnifyConflictKind {
+    /// Both types have type ids and they are different
+    TypeIdMismatch { type_name: RustTypeName, other_type_name: RustTypeName },
+    /// The types are different kinds of structures (e.g. a struct and an enum)
+    KindMismatch { kind: &'static str, other_kind: &'static str },
+    /// Both types are structs or variants but one is a unit/tuple/field body and the other isn't
+    BodyFormMismatch { form: TypeStructureBodyForm, other_form: TypeStructureBodyForm },
+    /// Both types are primitives and they are different
+    PrimitiveMismatch { primitive: PrimitiveType, other_primitive: PrimitiveType },
+    /// Tuples, tuple structures, or arrays have different lengths
+    LengthMismatch { length: usize, other_length: usize },
+    /// Both types are enums and they share no variants, so the unified enum has none
+    NoCommonVariants,
+    /// Only one of the types has this field, and the policy doesn't allow extra fields
+    FieldMismatch { name: String },
+    /// Only one of the types has this enum variant, and the policy doesn't allow missing variants
+    VariantMismatch { name: String },
+    /// Pointers point to different types
+    PointeeMismatch { refd_name: RustTypeName, other_refd_name: RustTypeName },
+    /// Pointers have different sizes (e.g. one is thin and the other is fat)
+    PointerSize { ptr_size: usize, other_ptr_size: usize }
+}
+
+impl UnifyConflicts {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item=&UnifyConflict> {
+        self.0.iter()
+    }
+}
+
+impl IntoIterator for UnifyConflicts {
+    type Item = UnifyConflict;
+    type IntoIter = std::vec::IntoIter<UnifyConflict>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl Display for UnifyConflicts {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} unify conflict(s)", self.0.len())?;
+        for conflict in &self.0 {
+            write!(f, "\n  {}", conflict)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for UnifyConflicts {}
+
+impl Display for UnifyConflict {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "at {}: {}", self.path, self.kind)
+    }
+}
+
+impl Display for UnifyConflictKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UnifyConflictKind::TypeIdMismatch { type_name, other_type_name } => write!(f, "different types: {} and {}", type_name.qualified(), other_type_name.qualified()),
+            UnifyConflictKind::KindMismatch { kind, other_kind } => write!(f, "{} and {}", kind, other_kind),
+            UnifyConflictKind::BodyFormMismatch { form, other_form } => write!(f, "{} and {}", form, other_form),
+            UnifyConflictKind::PrimitiveMismatch { primitive, other_primitive } => write!(f, "primitive {} and {}", primitive, other_primitive),
+            UnifyConflictKind::LengthMismatch { length, other_length } => write!(f, "{} elements and {}", length, other_length),
+            UnifyConflictKind::NoCommonVariants => write!(f, "enums have no common variants"),
+            UnifyConflictKind::FieldMismatch { name } => write!(f, "field `{}` is only in one type", name),
+            UnifyConflictKind::VariantMismatch { name } => write!(f, "variant `{}` is only in one type", name),
+            UnifyConflictKind::PointeeMismatch { refd_name, other_refd_name } => write!(f, "pointers to {} and {}", refd_name.qualified(), other_refd_name.qualified()),
+            UnifyConflictKind::PointerSize { ptr_size, other_ptr_size } => write!(f, "pointers of size {} and {}", ptr_size, other_ptr_size)
+        }
+    }
+}
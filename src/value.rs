@@ -0,0 +1,558 @@
+use std::borrow::Cow;
+use std::iter::{empty, repeat_n, zip};
+use std::marker::PhantomData;
+use std::mem::size_of;
+use std::ptr::NonNull;
+use derive_more::{Display, Error};
+use crate::{discriminant_size, infer_c_tuple_elem_offsets, infer_enum_payload_offset, infer_slice_offsets, HasValidStructure, LayoutCompat, PrimitiveType, RustType, RustTypeName, TypePath, TypePathElem, TypeStructure, TypeStructureBody};
+
+/// Reference to a value, which can be navigated and read using its [RustType]'s structure.
+///
+/// Only types whose layout is defined by their structure can be navigated: `#[repr(C)]` structs and enums,
+/// c-tuples, and arrays. Every access is checked against the structure, so a value can't be read as
+/// the wrong type or out of bounds.
+#[derive(Clone)]
+pub struct ValueRef<'a> {
+    ptr: NonNull<u8>,
+    rust_type: Cow<'a, RustType>,
+    _data: PhantomData<&'a [u8]>
+}
+
+/// Mutable reference to a value, which can be navigated, read, and written using its [RustType]'s structure.
+///
+/// See [ValueRef]. Only primitives can be written, so the value stays valid.
+pub struct ValueMut<'a> {
+    ptr: NonNull<u8>,
+    rust_type: Cow<'a, RustType>,
+    _data: PhantomData<&'a mut [u8]>
+}
+
+/// Accessing a [ValueRef], [ValueMut], or [DynValue](crate::DynValue) failed
+#[derive(Debug, Clone, Display, Error)]
+pub enum ValueError {
+    /// The type has no field with the name (or isn't a struct or enum)
+    #[display(fmt = "{} has no field {}", "type_name.qualified()", field_name)]
+    NoField { type_name: RustTypeName, field_name: String },
+    /// The type has no element at the index
+    #[display(fmt = "{} has no element {}", "type_name.qualified()", index)]
+    NoIndex { type_name: RustTypeName, index: usize },
+    /// The type's structure doesn't define its layout (e.g. it's opaque or not `#[repr(C)]`)
+    #[display(fmt = "layout of {} is unknown", "type_name.qualified()")]
+    UnknownLayout { type_name: RustTypeName },
+    /// Read or wrote the value as a primitive it isn't
+    #[display(fmt = "{} is not {}", "type_name.qualified()", expected)]
+    TypeMismatch { type_name: RustTypeName, expected: PrimitiveType },
+    /// A field or element is outside of its parent, so the parent's structure is wrong
+    #[display(fmt = "element of {} at offset {} with size {} is out of bounds", "type_name.qualified()", offset, size)]
+    OutOfBounds { type_name: RustTypeName, offset: usize, size: usize },
+    /// The enum's discriminant doesn't correspond to a variant
+    #[display(fmt = "{} has no variant with discriminant {}", "type_name.qualified()", discriminant)]
+    BadDiscriminant { type_name: RustTypeName, discriminant: u32 },
+    /// A path refers to an enum variant which isn't the active one
+    #[display(fmt = "{} is not variant {}", "type_name.qualified()", variant_name)]
+    InactiveVariant { type_name: RustTypeName, variant_name: String },
+    /// A path element which doesn't refer to a single value inside of the type ([TypePathElem::Elem] or [TypePathElem::Pointee])
+    #[display(fmt = "{} can't be accessed in a value of {}", elem, "type_name.qualified()")]
+    UnsupportedPathElem { type_name: RustTypeName, elem: TypePathElem },
+    /// Tried to store or extract a value of a type which isn't the same or identical in layout
+    #[display(fmt = "{} is not compatible with {}", "type_name.qualified()", "other_type_name.qualified()")]
+    IncompatibleType { type_name: Box<RustTypeName>, other_type_name: Box<RustTypeName> },
+    /// The type can't be zero-initialized, because it contains something besides primitives
+    /// (e.g. a pointer or opaque type)
+    #[display(fmt = "{} can't be zero-initialized", "type_name.qualified()")]
+    NotZeroable { type_name: RustTypeName },
+    /// The type's structure doesn't match its size and alignment, so the type is wrong
+    #[display(fmt = "structure of {} doesn't match its size and alignment", "type_name.qualified()")]
+    InconsistentLayout { type_name: RustTypeName },
+    /// The bytes aren't a valid value of the primitive (a `bool` other than 0 or 1, or an invalid `char`),
+    /// so the value wasn't initialized as its type
+    #[display(fmt = "bytes of {} aren't a valid {}", "type_name.qualified()", primitive)]
+    InvalidPrimitive { type_name: RustTypeName, primitive: PrimitiveType }
+}
+
+/// A field or element
+#[derive(Clone, Copy)]
+enum ValueElem<'b> {
+    Field(&'b str),
+    Index(usize)
+}
+
+impl<'a> ValueRef<'a> {
+    /// Reference to the value, with the type [RustType::of] `T`.
+    ///
+    /// Panics if the type's structure doesn't match its size and alignment (`T`'s [HasStructure](crate::HasStructure) impl is wrong)
+    pub fn of<T: HasValidStructure>(value: &'a T) -> Self where T::StaticId: Sized {
+        let rust_type = RustType::of::<T>();
+        if let Err(error) = check_layout(&rust_type) {
+            panic!("HasStructure impl is wrong: {}", error);
+        }
+        ValueRef {
+            ptr: NonNull::from(value).cast(),
+            rust_type: Cow::Owned(rust_type),
+            _data: PhantomData
+        }
+    }
+
+    /// Reference to the value at `ptr` with the given type.
+    ///
+    /// # Safety
+    /// `ptr` must point to an initialized, aligned value whose layout matches `rust_type`'s structure,
+    /// and which isn't mutated for `'a`.
+    pub unsafe fn from_raw(ptr: NonNull<u8>, rust_type: Cow<'a, RustType>) -> Self {
+        ValueRef { ptr, rust_type, _data: PhantomData }
+    }
+
+    /// The value's type
+    pub fn rust_type(&self) -> &RustType {
+        &self.rust_type
+    }
+
+    /// Pointer to the value
+    pub fn as_ptr(&self) -> *const u8 {
+        self.ptr.as_ptr()
+    }
+
+    /// The field with the given name, of a struct or the active variant of an enum
+    pub fn field(&self, field_name: &str) -> Result<ValueRef<'_>, ValueError> {
+        self.elem(ValueElem::Field(field_name))
+    }
+
+    /// The element at the given index: a field or tuple item of a struct or the active variant
+    /// of an enum, a c-tuple element, or an array element
+    pub fn index(&self, index: usize) -> Result<ValueRef<'_>, ValueError> {
+        self.elem(ValueElem::Index(index))
+    }
+
+    /// The nested value at the path: [TypePathElem::Variant] must be the active variant,
+    /// and [TypePathElem::Elem] and [TypePathElem::Pointee] aren't supported
+    pub fn at(&self, path: &TypePath) -> Result<ValueRef<'_>, ValueError> {
+        let (offset, rust_type) = locate_path(self.ptr, &self.rust_type, path)?;
+        Ok(ValueRef {
+            // SAFETY: `locate_path` checked that the nested value is inside the value
+            ptr: unsafe { NonNull::new_unchecked(self.ptr.as_ptr().add(offset)) },
+            rust_type: Cow::Borrowed(rust_type),
+            _data: PhantomData
+        })
+    }
+
+    /// Reference to the value as `T`, if it's a `T`: the types are the same, or the value's type has no id,
+    /// its layout is identical to `T`'s, and its nested types which have ids are the same as `T`'s
+    pub fn downcast_ref<T: HasValidStructure>(&self) -> Option<&'a T> where T::StaticId: Sized {
+        // SAFETY: the value is a valid, aligned `T`
+        is_type::<T>(&self.rust_type).then(|| unsafe { &*self.ptr.as_ptr().cast::<T>() })
+    }
+
+    /// Index of the enum's active variant
+    pub fn variant_index(&self) -> Result<usize, ValueError> {
+        variant_index(self.ptr, &self.rust_type)
+    }
+
+    /// Name of the enum's active variant
+    pub fn variant_name(&self) -> Result<&str, ValueError> {
+        let index = self.variant_index()?;
+        let TypeStructure::CReprEnum { variants } = &self.rust_type.structure else {
+            unreachable!("variant_index succeeded on a non-enum")
+        };
+        Ok(&variants[index].variant_name)
+    }
+
+    fn elem(&self, elem: ValueElem<'_>) -> Result<ValueRef<'_>, ValueError> {
+        let (offset, rust_type) = locate_elem(self.ptr, &self.rust_type, elem)?;
+        Ok(ValueRef {
+            // SAFETY: `locate_elem` checked that the element is inside the value
+            ptr: unsafe { NonNull::new_unchecked(self.ptr.as_ptr().add(offset)) },
+            rust_type: Cow::Borrowed(rust_type),
+            _data: PhantomData
+        })
+    }
+
+    /// Reads the primitive's bytes as `T`, which must be valid for any initialized bytes
+    /// (`bool` and `char` are read as integers and validated by their accessors)
+    fn read<T: Copy>(&self, expected: PrimitiveType) -> Result<T, ValueError> {
+        check_primitive(&self.rust_type, expected)?;
+        check_in_bounds(&self.rust_type, 0, size_of::<T>())?;
+        // SAFETY: the value is the primitive, so it's initialized (see `HasValidStructure` and `from_raw`)
+        Ok(unsafe { self.ptr.as_ptr().cast::<T>().read_unaligned() })
+    }
+
+    /// Read the value as `bool`, if it is one
+    pub fn as_bool(&self) -> Result<bool, ValueError> {
+        match self.read::<u8>(PrimitiveType::Bool)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(self.invalid(PrimitiveType::Bool))
+        }
+    }
+
+    /// Read the value as `char`, if it is one
+    pub fn as_char(&self) -> Result<char, ValueError> {
+        char::from_u32(self.read::<u32>(PrimitiveType::Char)?).ok_or_else(|| self.invalid(PrimitiveType::Char))
+    }
+
+    fn invalid(&self, primitive: PrimitiveType) -> ValueError {
+        ValueError::InvalidPrimitive { type_name: self.rust_type.type_name.clone(), primitive }
+    }
+}
+
+impl<'a> ValueMut<'a> {
+    /// Mutable reference to the value, with the type [RustType::of] `T`, see [ValueRef::of]
+    pub fn of<T: HasValidStructure>(value: &'a mut T) -> Self where T::StaticId: Sized {
+        let rust_type = RustType::of::<T>();
+        if let Err(error) = check_layout(&rust_type) {
+            panic!("HasStructure impl is wrong: {}", error);
+        }
+        ValueMut {
+            ptr: NonNull::from(value).cast(),
+            rust_type: Cow::Owned(rust_type),
+            _data: PhantomData
+        }
+    }
+
+    /// Mutable reference to the value at `ptr` with the given type.
+    ///
+    /// # Safety
+    /// `ptr` must point to an initialized, aligned value whose layout matches `rust_type`'s structure,
+    /// and which isn't accessed except through this for `'a`.
+    pub unsafe fn from_raw(ptr: NonNull<u8>, rust_type: Cow<'a, RustType>) -> Self {
+        ValueMut { ptr, rust_type, _data: PhantomData }
+    }
+
+    /// The value's type
+    pub fn rust_type(&self) -> &RustType {
+        &self.rust_type
+    }
+
+    /// Pointer to the value
+    pub fn as_mut_ptr(&mut self) -> *mut u8 {
+        self.ptr.as_ptr()
+    }
+
+    /// Immutable reference to the value
+    pub fn as_ref(&self) -> ValueRef<'_> {
+        ValueRef {
+            ptr: self.ptr,
+            rust_type: Cow::Borrowed(&self.rust_type),
+            _data: PhantomData
+        }
+    }
+
+    /// Mutable reference to the value as `T`, if it's a `T`, see [ValueRef::downcast_ref]
+    pub fn downcast_mut<T: HasValidStructure>(&mut self) -> Option<&mut T> where T::StaticId: Sized {
+        // SAFETY: the value is a valid, aligned `T`, and we have exclusive access
+        is_type::<T>(&self.rust_type).then(|| unsafe { &mut *self.ptr.as_ptr().cast::<T>() })
+    }
+
+    /// The field with the given name, see [ValueRef::field]
+    pub fn field_mut(&mut self, field_name: &str) -> Result<ValueMut<'_>, ValueError> {
+        self.elem_mut(ValueElem::Field(field_name))
+    }
+
+    /// The element at the given index, see [ValueRef::index]
+    pub fn index_mut(&mut self, index: usize) -> Result<ValueMut<'_>, ValueError> {
+        self.elem_mut(ValueElem::Index(index))
+    }
+
+    /// The nested value at the path, see [ValueRef::at]
+    pub fn at_mut(&mut self, path: &TypePath) -> Result<ValueMut<'_>, ValueError> {
+        let (offset, rust_type) = locate_path(self.ptr, &self.rust_type, path)?;
+        Ok(ValueMut {
+            // SAFETY: `locate_path` checked that the nested value is inside the value
+            ptr: unsafe { NonNull::new_unchecked(self.ptr.as_ptr().add(offset)) },
+            rust_type: Cow::Borrowed(rust_type),
+            _data: PhantomData
+        })
+    }
+
+    fn elem_mut(&mut self, elem: ValueElem<'_>) -> Result<ValueMut<'_>, ValueError> {
+        let (offset, rust_type) = locate_elem(self.ptr, &self.rust_type, elem)?;
+        Ok(ValueMut {
+            // SAFETY: `locate_elem` checked that the element is inside the value
+            ptr: unsafe { NonNull::new_unchecked(self.ptr.as_ptr().add(offset)) },
+            rust_type: Cow::Borrowed(rust_type),
+            _data: PhantomData
+        })
+    }
+
+    fn write<T: Copy>(&mut self, expected: PrimitiveType, value: T) -> Result<(), ValueError> {
+        check_primitive(&self.rust_type, expected)?;
+        check_in_bounds(&self.rust_type, 0, size_of::<T>())?;
+        // SAFETY: the value is the primitive, and we have exclusive access
+        unsafe { self.ptr.as_ptr().cast::<T>().write_unaligned(value) };
+        Ok(())
+    }
+}
+
+macro impl_primitive_accessors($($as_name:ident, $set_name:ident, $ty:ty, $primitive:ident);* $(;)?) {
+    impl<'a> ValueRef<'a> {
+        $(
+            #[doc = concat!("Read the value as `", stringify!($ty), "`, if it is one")]
+            pub fn $as_name(&self) -> Result<$ty, ValueError> {
+                self.read::<$ty>(PrimitiveType::$primitive)
+            }
+        )*
+    }
+
+    impl<'a> ValueMut<'a> {
+        $(
+            #[doc = concat!("Read the value as `", stringify!($ty), "`, if it is one")]
+            pub fn $as_name(&self) -> Result<$ty, ValueError> {
+                self.as_ref().$as_name()
+            }
+
+            #[doc = concat!("Write the value as `", stringify!($ty), "`, if it is one")]
+            pub fn $set_name(&mut self, value: $ty) -> Result<(), ValueError> {
+                self.write::<$ty>(PrimitiveType::$primitive, value)
+            }
+        )*
+    }
+}
+
+impl_primitive_accessors!(
+    as_i8, set_i8, i8, I8;
+    as_i16, set_i16, i16, I16;
+    as_i32, set_i32, i32, I32;
+    as_i64, set_i64, i64, I64;
+    as_i128, set_i128, i128, I128;
+    as_isize, set_isize, isize, Isize;
+    as_u8, set_u8, u8, U8;
+    as_u16, set_u16, u16, U16;
+    as_u32, set_u32, u32, U32;
+    as_u64, set_u64, u64, U64;
+    as_u128, set_u128, u128, U128;
+    as_usize, set_usize, usize, Usize;
+    as_f32, set_f32, f32, F32;
+    as_f64, set_f64, f64, F64;
+);
+
+impl<'a> ValueMut<'a> {
+    /// Read the value as `bool`, if it is one
+    pub fn as_bool(&self) -> Result<bool, ValueError> {
+        self.as_ref().as_bool()
+    }
+
+    /// Write the value as `bool`, if it is one
+    pub fn set_bool(&mut self, value: bool) -> Result<(), ValueError> {
+        self.write::<bool>(PrimitiveType::Bool, value)
+    }
+
+    /// Read the value as `char`, if it is one
+    pub fn as_char(&self) -> Result<char, ValueError> {
+        self.as_ref().as_char()
+    }
+
+    /// Write the value as `char`, if it is one
+    pub fn set_char(&mut self, value: char) -> Result<(), ValueError> {
+        self.write::<char>(PrimitiveType::Char, value)
+    }
+}
+
+/// Whether a value of the type is a `T` (see [can_reinterpret])
+pub(crate) fn is_type<T: HasValidStructure>(rust_type: &RustType) -> bool where T::StaticId: Sized {
+    can_reinterpret(rust_type, &RustType::of::<T>())
+}
+
+/// Whether a value of `rust_type` can be reinterpreted as one of `other`: they're the same type (see [is_same_type]),
+/// or one doesn't have a type id, their layouts are identical, and every nested field and element which has
+/// an id in both types is the same type.
+/// Types whose structure doesn't match their layout (see [check_layout]) can never be reinterpreted
+pub(crate) fn can_reinterpret(rust_type: &RustType, other: &RustType) -> bool {
+    check_layout(rust_type).is_ok() && check_layout(other).is_ok() && match (rust_type.type_id, other.type_id) {
+        (Some(_), Some(_)) => is_same_type(rust_type, other),
+        _ => rust_type.is_layout_compatible_with(other) == LayoutCompat::Identical && are_nested_types_same(rust_type, other)
+    }
+}
+
+/// Whether every nested field and element which has an id in both types is the same type.
+/// The layouts must be identical, so the nested types correspond by position
+fn are_nested_types_same(rust_type: &RustType, other: &RustType) -> bool {
+    if rust_type.type_id.is_some() && other.type_id.is_some() {
+        return is_same_type(rust_type, other);
+    }
+    zip(nested_types(&rust_type.structure), nested_types(&other.structure))
+        .all(|(nested, other_nested)| are_nested_types_same(nested, other_nested))
+}
+
+/// Fields and elements of the structure in layout order, and for enums, each variant's in turn
+fn nested_types(structure: &TypeStructure) -> Box<dyn Iterator<Item=&RustType> + '_> {
+    match structure {
+        TypeStructure::CReprStruct { body } => Box::new(body_nested_types(body)),
+        TypeStructure::CReprEnum { variants } => Box::new(variants.iter().flat_map(|variant| body_nested_types(&variant.body))),
+        TypeStructure::CTuple { elements } => Box::new(elements.iter()),
+        TypeStructure::Array { elem, length } => Box::new(repeat_n(elem.as_ref(), *length)),
+        TypeStructure::Opaque |
+        TypeStructure::OpaqueTuple { .. } |
+        TypeStructure::OpaqueFields { .. } |
+        TypeStructure::Primitive(_) |
+        TypeStructure::Pointer { .. } |
+        TypeStructure::Slice { .. } |
+        TypeStructure::Var(_) => Box::new(empty())
+    }
+}
+
+fn body_nested_types(body: &TypeStructureBody) -> Box<dyn Iterator<Item=&RustType> + '_> {
+    match body {
+        TypeStructureBody::None => Box::new(empty()),
+        TypeStructureBody::Tuple(elements) => Box::new(elements.iter()),
+        TypeStructureBody::Fields(fields) => Box::new(fields.iter().map(|field| &field.rust_type))
+    }
+}
+
+/// Whether the types have the same id, size, alignment, and structure.
+/// Anyone can construct a [RustType] with any id, so the id alone doesn't mean they're the same
+fn is_same_type(rust_type: &RustType, other: &RustType) -> bool {
+    rust_type.type_id == other.type_id &&
+        rust_type.size == other.size &&
+        rust_type.align == other.align &&
+        rust_type.structure == other.structure
+}
+
+/// Checks that the type's structure has the type's size and alignment, and so do the structures
+/// of its fields and elements (pointees aren't checked)
+pub(crate) fn check_layout(rust_type: &RustType) -> Result<(), ValueError> {
+    let structure = &rust_type.structure;
+    let size_and_align = structure.infer_size().zip(structure.infer_align());
+    if size_and_align.is_some_and(|size_and_align| size_and_align != (rust_type.size, rust_type.align)) {
+        return Err(ValueError::InconsistentLayout { type_name: rust_type.type_name.clone() });
+    }
+    match structure {
+        TypeStructure::CReprStruct { body } => check_body_layout(body),
+        TypeStructure::CReprEnum { variants } => variants.iter().try_for_each(|variant| check_body_layout(&variant.body)),
+        TypeStructure::CTuple { elements } => elements.iter().try_for_each(check_layout),
+        TypeStructure::Array { elem, length: _ } => check_layout(elem),
+        TypeStructure::Opaque |
+        TypeStructure::OpaqueTuple { .. } |
+        TypeStructure::OpaqueFields { .. } |
+        TypeStructure::Primitive(_) |
+        TypeStructure::Pointer { .. } |
+        TypeStructure::Slice { .. } |
+        TypeStructure::Var(_) => Ok(())
+    }
+}
+
+fn check_body_layout(body: &TypeStructureBody) -> Result<(), ValueError> {
+    match body {
+        TypeStructureBody::None => Ok(()),
+        TypeStructureBody::Tuple(elements) => elements.iter().try_for_each(check_layout),
+        TypeStructureBody::Fields(fields) => fields.iter().try_for_each(|field| check_layout(&field.rust_type))
+    }
+}
+
+fn check_primitive(rust_type: &RustType, expected: PrimitiveType) -> Result<(), ValueError> {
+    match &rust_type.structure {
+        TypeStructure::Primitive(primitive) if *primitive == expected => Ok(()),
+        _ => Err(ValueError::TypeMismatch { type_name: rust_type.type_name.clone(), expected })
+    }
+}
+
+/// Index of the active variant of the enum at `ptr`
+fn variant_index(ptr: NonNull<u8>, rust_type: &RustType) -> Result<usize, ValueError> {
+    let TypeStructure::CReprEnum { variants } = &rust_type.structure else {
+        return Err(ValueError::UnknownLayout { type_name: rust_type.type_name.clone() });
+    };
+    check_in_bounds(rust_type, 0, discriminant_size(variants.len()))?;
+    // SAFETY: checked that the discriminant is inside the value
+    let discriminant = unsafe { ptr.as_ptr().cast::<u32>().read_unaligned() };
+    match usize::try_from(discriminant) {
+        Ok(index) if index < variants.len() => Ok(index),
+        _ => Err(ValueError::BadDiscriminant { type_name: rust_type.type_name.clone(), discriminant })
+    }
+}
+
+/// Offset and type of the nested value at the path in the value at `ptr`
+fn locate_path<'t>(ptr: NonNull<u8>, rust_type: &'t RustType, path: &TypePath) -> Result<(usize, &'t RustType), ValueError> {
+    let mut offset = 0;
+    let mut rust_type = rust_type;
+    for path_elem in path {
+        // SAFETY: every step checks that the nested value is inside its parent
+        let elem_ptr = unsafe { NonNull::new_unchecked(ptr.as_ptr().add(offset)) };
+        let elem = match path_elem {
+            TypePathElem::Field(field_name) => ValueElem::Field(field_name),
+            TypePathElem::Index(index) => ValueElem::Index(*index),
+            TypePathElem::Variant(variant_name) => {
+                let index = variant_index(elem_ptr, rust_type)?;
+                let TypeStructure::CReprEnum { variants } = &rust_type.structure else {
+                    unreachable!("variant_index succeeded on a non-enum")
+                };
+                if variants[index].variant_name != *variant_name {
+                    return Err(ValueError::InactiveVariant { type_name: rust_type.type_name.clone(), variant_name: variant_name.clone() });
+                }
+                // Fields and indices already refer to the active variant
+                continue
+            }
+            TypePathElem::Elem | TypePathElem::Pointee => {
+                return Err(ValueError::UnsupportedPathElem { type_name: rust_type.type_name.clone(), elem: path_elem.clone() })
+            }
+        };
+        let (elem_offset, elem_type) = locate_elem(elem_ptr, rust_type, elem)?;
+        offset += elem_offset;
+        rust_type = elem_type;
+    }
+    Ok((offset, rust_type))
+}
+
+/// Offset and type of the field or element of the value at `ptr`
+fn locate_elem<'t>(ptr: NonNull<u8>, rust_type: &'t RustType, elem: ValueElem<'_>) -> Result<(usize, &'t RustType), ValueError> {
+    let (offset, elem_type) = match &rust_type.structure {
+        TypeStructure::CReprStruct { body } => locate_body_elem(rust_type, body, 0, elem)?,
+        TypeStructure::CReprEnum { variants } => {
+            let variant = &variants[variant_index(ptr, rust_type)?];
+            locate_body_elem(rust_type, &variant.body, infer_enum_payload_offset(variants), elem)?
+        }
+        TypeStructure::CTuple { elements } => match elem {
+            ValueElem::Index(index) => locate_tuple_elem(rust_type, elements.iter(), 0, index)?,
+            ValueElem::Field(field_name) => return Err(no_field(rust_type, field_name))
+        },
+        TypeStructure::Array { elem: elem_type, length } => match elem {
+            ValueElem::Index(index) if index < *length => {
+                (infer_slice_offsets(elem_type).nth(index).unwrap(), elem_type.as_ref())
+            }
+            ValueElem::Index(index) => return Err(ValueError::NoIndex { type_name: rust_type.type_name.clone(), index }),
+            ValueElem::Field(field_name) => return Err(no_field(rust_type, field_name))
+        },
+        TypeStructure::Primitive(_) | TypeStructure::Pointer { .. } => return Err(match elem {
+            ValueElem::Field(field_name) => no_field(rust_type, field_name),
+            ValueElem::Index(index) => ValueError::NoIndex { type_name: rust_type.type_name.clone(), index }
+        }),
+        TypeStructure::Opaque |
+        TypeStructure::OpaqueTuple { .. } |
+        TypeStructure::OpaqueFields { .. } |
+        TypeStructure::Slice { .. } |
+        TypeStructure::Var(_) => return Err(ValueError::UnknownLayout { type_name: rust_type.type_name.clone() })
+    };
+    check_in_bounds(rust_type, offset, elem_type.size)?;
+    Ok((offset, elem_type))
+}
+
+fn locate_body_elem<'t>(rust_type: &RustType, body: &'t TypeStructureBody, base_offset: usize, elem: ValueElem<'_>) -> Result<(usize, &'t RustType), ValueError> {
+    match (body, elem) {
+        (TypeStructureBody::Fields(fields), ValueElem::Field(field_name)) => {
+            let index = fields.iter().position(|field| field.name == field_name).ok_or_else(|| no_field(rust_type, field_name))?;
+            locate_tuple_elem(rust_type, fields.iter().map(|field| &field.rust_type), base_offset, index)
+        }
+        (TypeStructureBody::Fields(fields), ValueElem::Index(index)) => {
+            locate_tuple_elem(rust_type, fields.iter().map(|field| &field.rust_type), base_offset, index)
+        }
+        (TypeStructureBody::Tuple(elements), ValueElem::Index(index)) => locate_tuple_elem(rust_type, elements.iter(), base_offset, index),
+        (TypeStructureBody::None, ValueElem::Index(index)) => Err(ValueError::NoIndex { type_name: rust_type.type_name.clone(), index }),
+        (TypeStructureBody::Tuple(_) | TypeStructureBody::None, ValueElem::Field(field_name)) => Err(no_field(rust_type, field_name))
+    }
+}
+
+fn locate_tuple_elem<'t>(rust_type: &RustType, elements: impl Iterator<Item=&'t RustType> + Clone + 't, base_offset: usize, index: usize) -> Result<(usize, &'t RustType), ValueError> {
+    let offset = infer_c_tuple_elem_offsets(elements.clone()).nth(index);
+    match (offset, elements.clone().nth(index)) {
+        (Some(offset), Some(elem_type)) => Ok((base_offset + offset, elem_type)),
+        _ => Err(ValueError::NoIndex { type_name: rust_type.type_name.clone(), index })
+    }
+}
+
+pub(crate) fn check_in_bounds(rust_type: &RustType, offset: usize, size: usize) -> Result<(), ValueError> {
+    match offset.checked_add(size) {
+        Some(end) if end <= rust_type.size => Ok(()),
+        _ => Err(ValueError::OutOfBounds { type_name: rust_type.type_name.clone(), offset, size })
+    }
+}
+
+fn no_field(rust_type: &RustType, field_name: &str) -> ValueError {
+    ValueError::NoField { type_name: rust_type.type_name.clone(), field_name: field_name.to_string() }
+}
@@ -0,0 +1,121 @@
+use std::fmt::{Debug, DebugList, DebugTuple, Formatter};
+use std::mem::size_of;
+use crate::{DynValue, PrimitiveType, RustTypeName, TypeStructure, TypeStructureBody, ValueError, ValueMut, ValueRef};
+use crate::value::check_in_bounds;
+
+/// Formats the value like `#[derive(Debug)]` would, by walking its structure.
+///
+/// Enums are formatted as their active variant, and pointers as their address.
+/// Parts whose layout is unknown are formatted as `<opaque TypeName>`, and parts which can't be read as `<error>`.
+impl<'a> Debug for ValueRef<'a> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let rust_type = self.rust_type();
+        match &rust_type.structure {
+            TypeStructure::Primitive(primitive) => fmt_primitive(self, *primitive, f),
+            TypeStructure::Pointer { .. } => {
+                // The address is the first word, even for fat pointers
+                if let Err(error) = check_in_bounds(rust_type, 0, size_of::<*const ()>()) {
+                    return fmt_error(error, f);
+                }
+                // SAFETY: checked that the value is at least a word
+                let address = unsafe { self.as_ptr().cast::<*const ()>().read_unaligned() };
+                Debug::fmt(&address, f)
+            }
+            TypeStructure::CReprStruct { body } => fmt_body(self, &debug_name(&rust_type.type_name), body, f),
+            TypeStructure::CReprEnum { variants } => match self.variant_index() {
+                Ok(index) => fmt_body(self, &variants[index].variant_name, &variants[index].body, f),
+                Err(error) => fmt_error(error, f)
+            },
+            TypeStructure::CTuple { elements } if elements.is_empty() => write!(f, "()"),
+            TypeStructure::CTuple { elements } => fmt_elems(self, f.debug_tuple(""), DebugTuple::field, elements.len()).finish(),
+            TypeStructure::Array { elem: _, length } => fmt_elems(self, f.debug_list(), DebugList::entry, *length).finish(),
+            TypeStructure::Opaque |
+            TypeStructure::OpaqueTuple { .. } |
+            TypeStructure::OpaqueFields { .. } |
+            TypeStructure::Slice { .. } |
+            TypeStructure::Var(_) => write!(f, "<opaque {}>", rust_type.type_name.unqualified())
+        }
+    }
+}
+
+/// Formats the value like [ValueRef]
+impl<'a> Debug for ValueMut<'a> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(&self.as_ref(), f)
+    }
+}
+
+/// Formats the value like [ValueRef]
+impl Debug for DynValue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(&self.as_ref(), f)
+    }
+}
+
+/// The name `#[derive(Debug)]` would print: the simple name without qualifier or generic arguments
+fn debug_name(type_name: &RustTypeName) -> String {
+    match type_name {
+        RustTypeName::Ident { simple_name, .. } => simple_name.clone(),
+        type_name => type_name.unqualified().to_string()
+    }
+}
+
+fn fmt_body(value: &ValueRef<'_>, name: &str, body: &TypeStructureBody, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match body {
+        TypeStructureBody::None => write!(f, "{}", name),
+        TypeStructureBody::Tuple(elements) => fmt_elems(value, f.debug_tuple(name), DebugTuple::field, elements.len()).finish(),
+        TypeStructureBody::Fields(fields) => {
+            let mut debug_struct = f.debug_struct(name);
+            for (index, field) in fields.iter().enumerate() {
+                match value.index(index) {
+                    Ok(field_value) => debug_struct.field(&field.name, &field_value),
+                    Err(error) => debug_struct.field(&field.name, &format_args!("<error: {}>", error))
+                };
+            }
+            debug_struct.finish()
+        }
+    }
+}
+
+/// Adds the elements at indices `0..length` to the tuple or list builder
+fn fmt_elems<B>(value: &ValueRef<'_>, mut builder: B, add: impl for<'b> Fn(&'b mut B, &dyn Debug) -> &'b mut B, length: usize) -> B {
+    for index in 0..length {
+        match value.index(index) {
+            Ok(elem) => add(&mut builder, &elem),
+            Err(error) => add(&mut builder, &format_args!("<error: {}>", error))
+        };
+    }
+    builder
+}
+
+fn fmt_error(error: ValueError, f: &mut Formatter<'_>) -> std::fmt::Result {
+    write!(f, "<error: {}>", error)
+}
+
+fn fmt_primitive(value: &ValueRef<'_>, primitive: PrimitiveType, f: &mut Formatter<'_>) -> std::fmt::Result {
+    fn fmt_read<T: Debug>(read: Result<T, ValueError>, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match read {
+            Ok(value) => Debug::fmt(&value, f),
+            Err(error) => fmt_error(error, f)
+        }
+    }
+
+    match primitive {
+        PrimitiveType::I8 => fmt_read(value.as_i8(), f),
+        PrimitiveType::I16 => fmt_read(value.as_i16(), f),
+        PrimitiveType::I32 => fmt_read(value.as_i32(), f),
+        PrimitiveType::I64 => fmt_read(value.as_i64(), f),
+        PrimitiveType::I128 => fmt_read(value.as_i128(), f),
+        PrimitiveType::Isize => fmt_read(value.as_isize(), f),
+        PrimitiveType::U8 => fmt_read(value.as_u8(), f),
+        PrimitiveType::U16 => fmt_read(value.as_u16(), f),
+        PrimitiveType::U32 => fmt_read(value.as_u32(), f),
+        PrimitiveType::U64 => fmt_read(value.as_u64(), f),
+        PrimitiveType::U128 => fmt_read(value.as_u128(), f),
+        PrimitiveType::Usize => fmt_read(value.as_usize(), f),
+        PrimitiveType::F32 => fmt_read(value.as_f32(), f),
+        PrimitiveType::F64 => fmt_read(value.as_f64(), f),
+        PrimitiveType::Bool => fmt_read(value.as_bool(), f),
+        PrimitiveType::Char => fmt_read(value.as_char(), f)
+    }
+}
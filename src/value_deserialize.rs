@@ -0,0 +1,333 @@
+use std::any::TypeId;
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
+use std::iter::repeat_n;
+use std::sync::Arc;
+use serde::{Deserialize, Deserializer};
+use serde::de::{DeserializeOwned, DeserializeSeed, Error as _, Expected, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use crate::{infer_c_tuple_elem_offsets, infer_enum_payload_offset, DynValue, HasValidStructure, PrimitiveType, RustType, RustTypeName, TypeEnumVariant, TypeRegistry, TypeStructure, TypeStructureBody, TypeStructureBodyField};
+use crate::dyn_value::is_zeroable;
+use crate::value::check_in_bounds;
+
+/// Deserializes a value of the type into a [DynValue] by walking its structure,
+/// so the type doesn't need to implement `Deserialize`.
+///
+/// Accepts what [ReflectSerialize](crate::ReflectSerialize) outputs. Primitives must be in range,
+/// enum variant names must exist, and arrays and tuples must have the exact length. Struct fields which are
+/// missing are zero-initialized if their type can be (see [DynValue::zeroed]), otherwise they're an error,
+/// as are unknown fields.
+///
+/// Values whose type has a custom deserializer registered in the global registry
+/// (see [TypeRegistry::register_deserializer]) are deserialized with it. Other opaque values and pointers can't be deserialized.
+pub struct ReflectDeserialize(pub Arc<RustType>);
+
+/// Custom deserializer for a type, see [TypeRegistry::register_deserializer_fn]
+pub type CustomDeserializeFn = dyn for<'de> Fn(&mut dyn erased_serde::Deserializer<'de>) -> Result<DynValue, erased_serde::Error> + Send + Sync;
+
+/// Custom deserializers registered by type id and name
+#[derive(Clone, Default)]
+pub(crate) struct CustomDeserializers {
+    by_id: HashMap<TypeId, Arc<CustomDeserializeFn>>,
+    by_name: HashMap<RustTypeName, Arc<CustomDeserializeFn>>
+}
+
+impl TypeRegistry {
+    /// Deserialize values of `T` with its `Deserialize` implementation in [ReflectDeserialize],
+    /// e.g. for opaque types like `String`. Replaces any custom deserializer `T` already has.
+    pub fn register_deserializer<T: DeserializeOwned + HasValidStructure + 'static>(&mut self) where T::StaticId: Sized {
+        self.deserializers.by_id.insert(T::static_type_id(), Arc::new(|deserializer: &mut dyn erased_serde::Deserializer<'_>| {
+            erased_serde::deserialize::<T>(deserializer).map(DynValue::new)
+        }));
+    }
+
+    /// Deserialize values of the type with the given name with `deserialize` in [ReflectDeserialize],
+    /// which returns a value with the same type id or identical layout (see [DynValue::is]).
+    /// Replaces any custom deserializer the type already has, but deserializers registered by type id
+    /// (see [TypeRegistry::register_deserializer]) take precedence.
+    pub fn register_deserializer_fn(
+        &mut self,
+        type_name: RustTypeName,
+        deserialize: impl for<'de> Fn(&mut dyn erased_serde::Deserializer<'de>) -> Result<DynValue, erased_serde::Error> + Send + Sync + 'static
+    ) {
+        self.deserializers.by_name.insert(type_name, Arc::new(deserialize));
+    }
+
+    /// Removes the custom deserializers for the type's id and name. Returns `false` if it had none
+    pub fn unregister_deserializer(&mut self, rust_type: &RustType) -> bool {
+        let removed_by_id = rust_type.type_id.and_then(|type_id| self.deserializers.by_id.remove(&type_id)).is_some();
+        let removed_by_name = self.deserializers.by_name.remove(&rust_type.type_name).is_some();
+        removed_by_id || removed_by_name
+    }
+
+    /// The custom deserializer for the type, by id or else by name
+    pub fn custom_deserializer(&self, rust_type: &RustType) -> Option<&Arc<CustomDeserializeFn>> {
+        rust_type.type_id.and_then(|type_id| self.deserializers.by_id.get(&type_id))
+            .or_else(|| self.deserializers.by_name.get(&rust_type.type_name))
+    }
+}
+
+impl Debug for CustomDeserializers {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CustomDeserializers")
+            .field("by_id", &self.by_id.keys().collect::<Vec<_>>())
+            .field("by_name", &self.by_name.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl DynValue {
+    /// Deserializes a value of the type, see [ReflectDeserialize]
+    pub fn deserialize<'de, D: Deserializer<'de>>(rust_type: impl Into<Arc<RustType>>, deserializer: D) -> Result<Self, D::Error> {
+        ReflectDeserialize(rust_type.into()).deserialize(deserializer)
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for ReflectDeserialize {
+    type Value = DynValue;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<DynValue, D::Error> {
+        let mut value = DynValue::uninit(self.0.clone()).map_err(D::Error::custom)?;
+        // If this fails, the value is dropped, which only frees the buffer
+        Place { ptr: value.as_mut_ptr(), rust_type: &self.0 }.deserialize(deserializer)?;
+        Ok(value)
+    }
+}
+
+/// Deserializes a value of `rust_type` into `ptr`, which points to a buffer of `rust_type`'s size.
+/// Nested places are checked to be inside of their parent
+#[derive(Clone, Copy)]
+struct Place<'t> {
+    ptr: *mut u8,
+    rust_type: &'t RustType
+}
+
+impl<'t> Place<'t> {
+    /// The places of the elements with the given types, after `base_offset`
+    fn elems<E: serde::de::Error>(&self, base_offset: usize, elem_types: impl Iterator<Item=&'t RustType> + Clone + 't) -> Result<Vec<Place<'t>>, E> {
+        infer_c_tuple_elem_offsets(elem_types.clone()).zip(elem_types).map(|(offset, elem_type)| {
+            let offset = base_offset + offset;
+            check_in_bounds(self.rust_type, offset, elem_type.size).map_err(E::custom)?;
+            // SAFETY: checked that the element is inside the buffer
+            Ok(Place { ptr: unsafe { self.ptr.add(offset) }, rust_type: elem_type })
+        }).collect()
+    }
+
+    fn write<T, E: serde::de::Error>(&self, value: T) -> Result<(), E> {
+        check_in_bounds(self.rust_type, 0, std::mem::size_of::<T>()).map_err(E::custom)?;
+        // SAFETY: checked that the value fits in the buffer
+        unsafe { self.ptr.cast::<T>().write_unaligned(value) };
+        Ok(())
+    }
+}
+
+impl<'de, 't> DeserializeSeed<'de> for Place<'t> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        let rust_type = self.rust_type;
+        let custom_deserializer = TypeRegistry::with_global(|registry| registry.custom_deserializer(rust_type).cloned()).flatten();
+        if let Some(custom_deserializer) = custom_deserializer {
+            let mut deserializer = <dyn erased_serde::Deserializer>::erase(deserializer);
+            let value = custom_deserializer(&mut deserializer).map_err(D::Error::custom)?;
+            // SAFETY: the place is a buffer of its type's size, and the value is in its own buffer
+            return unsafe { value.move_to(self.ptr, rust_type) }.map_err(D::Error::custom);
+        }
+        match &rust_type.structure {
+            TypeStructure::Primitive(primitive) => deserialize_primitive(self, *primitive, deserializer),
+            TypeStructure::CReprStruct { body } => BodyPlace { place: self, body, base_offset: 0 }.deserialize(deserializer),
+            TypeStructure::CReprEnum { variants } => deserializer.deserialize_any(EnumVisitor { place: self, variants }),
+            TypeStructure::CTuple { elements } if elements.is_empty() => <()>::deserialize(deserializer),
+            TypeStructure::CTuple { elements } => deserializer.deserialize_tuple(elements.len(), TupleVisitor {
+                places: self.elems(0, elements.iter())?
+            }),
+            TypeStructure::Array { elem, length } => deserializer.deserialize_tuple(*length, TupleVisitor {
+                places: self.elems(0, repeat_n(elem.as_ref(), *length))?
+            }),
+            TypeStructure::Opaque |
+            TypeStructure::OpaqueTuple { .. } |
+            TypeStructure::OpaqueFields { .. } |
+            TypeStructure::Slice { .. } |
+            TypeStructure::Var(_) |
+            TypeStructure::Pointer { .. } => Err(D::Error::custom(format_args!(
+                "can't deserialize {} ({}) without a custom deserializer",
+                rust_type.type_name.qualified(),
+                rust_type.structure.kind_name()
+            )))
+        }
+    }
+}
+
+/// The body of a struct or enum variant, whose elements are after `base_offset` in the place
+struct BodyPlace<'t> {
+    place: Place<'t>,
+    body: &'t TypeStructureBody,
+    base_offset: usize
+}
+
+impl<'de, 't> DeserializeSeed<'de> for BodyPlace<'t> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        match self.body {
+            TypeStructureBody::None => <()>::deserialize(deserializer),
+            TypeStructureBody::Tuple(elements) => {
+                let places = self.place.elems(self.base_offset, elements.iter())?;
+                match places.as_slice() {
+                    // Newtype
+                    [place] => place.deserialize(deserializer),
+                    _ => deserializer.deserialize_tuple(places.len(), TupleVisitor { places })
+                }
+            }
+            TypeStructureBody::Fields(fields) => deserializer.deserialize_map(FieldsVisitor {
+                places: self.place.elems(self.base_offset, fields.iter().map(|field| &field.rust_type))?,
+                fields
+            })
+        }
+    }
+}
+
+struct TupleVisitor<'t> {
+    places: Vec<Place<'t>>
+}
+
+impl<'de, 't> Visitor<'de> for TupleVisitor<'t> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "a tuple of length {}", self.places.len())
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        for (index, place) in self.places.iter().enumerate() {
+            if seq.next_element_seed(*place)?.is_none() {
+                return Err(A::Error::invalid_length(index, &self));
+            }
+        }
+        if seq.next_element::<IgnoredAny>()?.is_some() {
+            return Err(A::Error::invalid_length(self.places.len() + 1, &self));
+        }
+        Ok(())
+    }
+}
+
+struct FieldsVisitor<'t> {
+    places: Vec<Place<'t>>,
+    fields: &'t [TypeStructureBodyField]
+}
+
+impl<'de, 't> Visitor<'de> for FieldsVisitor<'t> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "a map with fields ")?;
+        for (index, field) in self.fields.iter().enumerate() {
+            if index > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "`{}`", field.name)?;
+        }
+        Ok(())
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        let mut is_initialized = vec![false; self.fields.len()];
+        while let Some(field_name) = map.next_key::<String>()? {
+            let Some(index) = self.fields.iter().position(|field| field.name == field_name) else {
+                return Err(A::Error::custom(format_args!("unknown field `{}`, expected {}", field_name, &self as &dyn Expected)));
+            };
+            if is_initialized[index] {
+                return Err(A::Error::custom(format_args!("duplicate field `{}`", field_name)));
+            }
+            map.next_value_seed(self.places[index])?;
+            is_initialized[index] = true;
+        }
+        for ((place, field), is_initialized) in self.places.iter().zip(self.fields).zip(is_initialized) {
+            if is_initialized {
+                continue;
+            }
+            if !is_zeroable(&field.rust_type) {
+                return Err(A::Error::custom(format_args!("missing field `{}`", field.name)));
+            }
+            // SAFETY: the place is a buffer of the field type's size, and zero is a valid value of it
+            unsafe { place.ptr.write_bytes(0, field.rust_type.size) };
+        }
+        Ok(())
+    }
+}
+
+/// Externally tagged: unit variants are their name, other variants are a map from their name to their body
+struct EnumVisitor<'t> {
+    place: Place<'t>,
+    variants: &'t [TypeEnumVariant]
+}
+
+impl<'t> EnumVisitor<'t> {
+    /// Writes the discriminant of the variant with the given name, and returns the variant
+    fn write_variant<E: serde::de::Error>(&self, variant_name: &str) -> Result<&'t TypeEnumVariant, E> {
+        let Some(index) = self.variants.iter().position(|variant| variant.variant_name == variant_name) else {
+            return Err(E::custom(format_args!("unknown variant `{}`, expected {}", variant_name, self as &dyn Expected)));
+        };
+        self.place.write(index as u32)?;
+        Ok(&self.variants[index])
+    }
+}
+
+impl<'de, 't> Visitor<'de> for EnumVisitor<'t> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "a variant of {}: ", self.place.rust_type.type_name.unqualified())?;
+        for (index, variant) in self.variants.iter().enumerate() {
+            if index > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "`{}`", variant.variant_name)?;
+        }
+        Ok(())
+    }
+
+    fn visit_str<E: serde::de::Error>(self, variant_name: &str) -> Result<(), E> {
+        let variant = self.write_variant(variant_name)?;
+        match variant.body {
+            TypeStructureBody::None => Ok(()),
+            _ => Err(E::custom(format_args!("variant `{}` isn't a unit variant, so it must be a map from its name to its data", variant_name)))
+        }
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        let Some(variant_name) = map.next_key::<String>()? else {
+            return Err(A::Error::invalid_length(0, &self));
+        };
+        let variant = self.write_variant::<A::Error>(&variant_name)?;
+        map.next_value_seed(BodyPlace {
+            place: self.place,
+            body: &variant.body,
+            base_offset: infer_enum_payload_offset(self.variants)
+        })?;
+        if map.next_key::<IgnoredAny>()?.is_some() {
+            return Err(A::Error::invalid_length(2, &self));
+        }
+        Ok(())
+    }
+}
+
+fn deserialize_primitive<'de, D: Deserializer<'de>>(place: Place<'_>, primitive: PrimitiveType, deserializer: D) -> Result<(), D::Error> {
+    match primitive {
+        PrimitiveType::I8 => place.write(i8::deserialize(deserializer)?),
+        PrimitiveType::I16 => place.write(i16::deserialize(deserializer)?),
+        PrimitiveType::I32 => place.write(i32::deserialize(deserializer)?),
+        PrimitiveType::I64 => place.write(i64::deserialize(deserializer)?),
+        PrimitiveType::I128 => place.write(i128::deserialize(deserializer)?),
+        PrimitiveType::Isize => place.write(isize::deserialize(deserializer)?),
+        PrimitiveType::U8 => place.write(u8::deserialize(deserializer)?),
+        PrimitiveType::U16 => place.write(u16::deserialize(deserializer)?),
+        PrimitiveType::U32 => place.write(u32::deserialize(deserializer)?),
+        PrimitiveType::U64 => place.write(u64::deserialize(deserializer)?),
+        PrimitiveType::U128 => place.write(u128::deserialize(deserializer)?),
+        PrimitiveType::Usize => place.write(usize::deserialize(deserializer)?),
+        PrimitiveType::F32 => place.write(f32::deserialize(deserializer)?),
+        PrimitiveType::F64 => place.write(f64::deserialize(deserializer)?),
+        PrimitiveType::Bool => place.write(bool::deserialize(deserializer)?),
+        PrimitiveType::Char => place.write(char::deserialize(deserializer)?)
+    }
+}
@@ -0,0 +1,177 @@
+use std::any::TypeId;
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
+use serde::{Serialize, Serializer};
+use serde::ser::{Error as _, SerializeMap, SerializeTuple};
+use crate::{HasValidStructure, PrimitiveType, RustType, RustTypeName, TypeRegistry, TypeStructure, TypeStructureBody, ValueError, ValueRef};
+
+pub use erased_serde;
+
+/// Serializes a value by walking its structure, so its type doesn't need to implement `Serialize`.
+///
+/// The output matches `#[derive(Serialize)]`'s in self-describing formats (e.g. JSON):
+/// structs with fields are maps, tuple structs, tuples, and arrays are tuples, newtype structs are their field,
+/// and enums are externally tagged (unit variants are their name, other variants are a map from their name to their data).
+///
+/// Values whose type has a custom serializer registered in the global registry
+/// (see [TypeRegistry::register_serializer]) are serialized with it. Other opaque values and pointers can't be serialized.
+pub struct ReflectSerialize<'a>(pub ValueRef<'a>);
+
+/// Custom serializer for a type, see [TypeRegistry::register_serializer_fn]
+pub type CustomSerializeFn = dyn for<'v> Fn(&'v ValueRef<'v>) -> Result<Box<dyn erased_serde::Serialize + 'v>, ValueError> + Send + Sync;
+
+/// Custom serializers registered by type id and name
+#[derive(Clone, Default)]
+pub(crate) struct CustomSerializers {
+    by_id: HashMap<TypeId, Arc<CustomSerializeFn>>,
+    by_name: HashMap<RustTypeName, Arc<CustomSerializeFn>>
+}
+
+impl TypeRegistry {
+    /// Serialize values of `T` with its `Serialize` implementation in [ReflectSerialize],
+    /// e.g. for opaque types like `String`. Replaces any custom serializer `T` already has.
+    pub fn register_serializer<T: Serialize + HasValidStructure + 'static>(&mut self) where T::StaticId: Sized {
+        self.serializers.by_id.insert(T::static_type_id(), Arc::new(|value: &ValueRef<'_>| {
+            match value.downcast_ref::<T>() {
+                None => Err(ValueError::IncompatibleType {
+                    type_name: Box::new(value.rust_type().type_name.clone()),
+                    other_type_name: Box::new(T::type_name())
+                }),
+                Some(value) => Ok(Box::new(value) as Box<dyn erased_serde::Serialize>)
+            }
+        }));
+    }
+
+    /// Serialize values of the type with the given name with `serialize` in [ReflectSerialize],
+    /// which returns what to serialize in place of the value.
+    /// Replaces any custom serializer the type already has, but serializers registered by type id
+    /// (see [TypeRegistry::register_serializer]) take precedence.
+    pub fn register_serializer_fn(
+        &mut self,
+        type_name: RustTypeName,
+        serialize: impl for<'v> Fn(&'v ValueRef<'v>) -> Result<Box<dyn erased_serde::Serialize + 'v>, ValueError> + Send + Sync + 'static
+    ) {
+        self.serializers.by_name.insert(type_name, Arc::new(serialize));
+    }
+
+    /// Removes the custom serializers for the type's id and name. Returns `false` if it had none
+    pub fn unregister_serializer(&mut self, rust_type: &RustType) -> bool {
+        let removed_by_id = rust_type.type_id.and_then(|type_id| self.serializers.by_id.remove(&type_id)).is_some();
+        let removed_by_name = self.serializers.by_name.remove(&rust_type.type_name).is_some();
+        removed_by_id || removed_by_name
+    }
+
+    /// The custom serializer for the type, by id or else by name
+    pub fn custom_serializer(&self, rust_type: &RustType) -> Option<&Arc<CustomSerializeFn>> {
+        rust_type.type_id.and_then(|type_id| self.serializers.by_id.get(&type_id))
+            .or_else(|| self.serializers.by_name.get(&rust_type.type_name))
+    }
+}
+
+impl Debug for CustomSerializers {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CustomSerializers")
+            .field("by_id", &self.by_id.keys().collect::<Vec<_>>())
+            .field("by_name", &self.by_name.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl<'a> Serialize for ReflectSerialize<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let value = &self.0;
+        let rust_type = value.rust_type();
+        let custom_serializer = TypeRegistry::with_global(|registry| registry.custom_serializer(rust_type).cloned()).flatten();
+        if let Some(custom_serializer) = custom_serializer {
+            let custom_value = custom_serializer(value).map_err(S::Error::custom)?;
+            return erased_serde::serialize(custom_value.as_ref(), serializer);
+        }
+        match &rust_type.structure {
+            TypeStructure::Primitive(primitive) => serialize_primitive(value, *primitive, serializer),
+            TypeStructure::CReprStruct { body } => serialize_body(value, body, serializer),
+            TypeStructure::CReprEnum { variants } => {
+                let variant = &variants[value.variant_index().map_err(S::Error::custom)?];
+                match &variant.body {
+                    TypeStructureBody::None => serializer.serialize_str(&variant.variant_name),
+                    body => {
+                        let mut map = serializer.serialize_map(Some(1))?;
+                        map.serialize_entry(&variant.variant_name, &ReflectSerializeBody { value, body })?;
+                        map.end()
+                    }
+                }
+            }
+            TypeStructure::CTuple { elements } if elements.is_empty() => serializer.serialize_unit(),
+            TypeStructure::CTuple { elements } => serialize_elems(value, elements.len(), serializer),
+            TypeStructure::Array { elem: _, length } => serialize_elems(value, *length, serializer),
+            TypeStructure::Opaque |
+            TypeStructure::OpaqueTuple { .. } |
+            TypeStructure::OpaqueFields { .. } |
+            TypeStructure::Slice { .. } |
+            TypeStructure::Var(_) |
+            TypeStructure::Pointer { .. } => Err(S::Error::custom(format_args!(
+                "can't serialize {} ({}) without a custom serializer",
+                rust_type.type_name.qualified(),
+                rust_type.structure.kind_name()
+            )))
+        }
+    }
+}
+
+/// The body of a struct or the active variant of an enum
+struct ReflectSerializeBody<'v, 'a> {
+    value: &'v ValueRef<'a>,
+    body: &'v TypeStructureBody
+}
+
+impl<'v, 'a> Serialize for ReflectSerializeBody<'v, 'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_body(self.value, self.body, serializer)
+    }
+}
+
+fn serialize_body<S: Serializer>(value: &ValueRef<'_>, body: &TypeStructureBody, serializer: S) -> Result<S::Ok, S::Error> {
+    match body {
+        TypeStructureBody::None => serializer.serialize_unit(),
+        TypeStructureBody::Tuple(elements) if elements.len() == 1 => {
+            ReflectSerialize(value.index(0).map_err(S::Error::custom)?).serialize(serializer)
+        }
+        TypeStructureBody::Tuple(elements) => serialize_elems(value, elements.len(), serializer),
+        TypeStructureBody::Fields(fields) => {
+            let mut map = serializer.serialize_map(Some(fields.len()))?;
+            for (index, field) in fields.iter().enumerate() {
+                map.serialize_entry(&field.name, &ReflectSerialize(value.index(index).map_err(S::Error::custom)?))?;
+            }
+            map.end()
+        }
+    }
+}
+
+fn serialize_elems<S: Serializer>(value: &ValueRef<'_>, length: usize, serializer: S) -> Result<S::Ok, S::Error> {
+    let mut tuple = serializer.serialize_tuple(length)?;
+    for index in 0..length {
+        tuple.serialize_element(&ReflectSerialize(value.index(index).map_err(S::Error::custom)?))?;
+    }
+    tuple.end()
+}
+
+fn serialize_primitive<S: Serializer>(value: &ValueRef<'_>, primitive: PrimitiveType, serializer: S) -> Result<S::Ok, S::Error> {
+    match primitive {
+        PrimitiveType::I8 => serializer.serialize_i8(value.as_i8().map_err(S::Error::custom)?),
+        PrimitiveType::I16 => serializer.serialize_i16(value.as_i16().map_err(S::Error::custom)?),
+        PrimitiveType::I32 => serializer.serialize_i32(value.as_i32().map_err(S::Error::custom)?),
+        PrimitiveType::I64 => serializer.serialize_i64(value.as_i64().map_err(S::Error::custom)?),
+        PrimitiveType::I128 => serializer.serialize_i128(value.as_i128().map_err(S::Error::custom)?),
+        PrimitiveType::Isize => serializer.serialize_i64(value.as_isize().map_err(S::Error::custom)? as i64),
+        PrimitiveType::U8 => serializer.serialize_u8(value.as_u8().map_err(S::Error::custom)?),
+        PrimitiveType::U16 => serializer.serialize_u16(value.as_u16().map_err(S::Error::custom)?),
+        PrimitiveType::U32 => serializer.serialize_u32(value.as_u32().map_err(S::Error::custom)?),
+        PrimitiveType::U64 => serializer.serialize_u64(value.as_u64().map_err(S::Error::custom)?),
+        PrimitiveType::U128 => serializer.serialize_u128(value.as_u128().map_err(S::Error::custom)?),
+        PrimitiveType::Usize => serializer.serialize_u64(value.as_usize().map_err(S::Error::custom)? as u64),
+        PrimitiveType::F32 => serializer.serialize_f32(value.as_f32().map_err(S::Error::custom)?),
+        PrimitiveType::F64 => serializer.serialize_f64(value.as_f64().map_err(S::Error::custom)?),
+        PrimitiveType::Bool => serializer.serialize_bool(value.as_bool().map_err(S::Error::custom)?),
+        PrimitiveType::Char => serializer.serialize_char(value.as_char().map_err(S::Error::custom)?)
+    }
+}
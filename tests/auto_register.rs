@@ -0,0 +1,33 @@
+#![cfg(feature = "auto-register")]
+
+use structural_reflection::{AutoRegister, HasTypeName, RustType, RustTypeName, TypeRegistry};
+use structural_reflection::derive::{HasStructure, HasTypeName};
+
+#[derive(HasTypeName, HasStructure)]
+#[has_structure(register)]
+#[repr(C)]
+pub struct AutoRegistered {
+    pub id: u64,
+    pub inner: AutoRegisteredInner
+}
+
+#[derive(HasTypeName, HasStructure)]
+#[has_structure(register)]
+#[repr(C)]
+pub enum AutoRegisteredInner {
+    A,
+    B(u8)
+}
+
+#[test]
+fn registered_before_access() {
+    assert!(AutoRegister::iter().count() >= 2);
+    let rust_type = RustType::lookup(&RustTypeName::simple("AutoRegistered".to_string())).unwrap();
+    assert_eq!(rust_type.type_id, Some(AutoRegistered::static_type_id()));
+    assert_eq!(RustTypeName::lookup(AutoRegisteredInner::static_type_id()), Some(RustTypeName::simple("AutoRegisteredInner".to_string())));
+
+    let mut registry = TypeRegistry::new();
+    assert_eq!(registry.lookup_from_id(AutoRegistered::static_type_id()), None);
+    registry.register_auto_registered();
+    assert_eq!(registry.lookup_from_id(AutoRegistered::static_type_id()), Some(&rust_type));
+}
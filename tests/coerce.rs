@@ -0,0 +1,86 @@
+mod common;
+
+use std::mem::MaybeUninit;
+use structural_reflection::{PrimitiveType, RustType, TypeEnumVariant, TypeStructure, TypeStructureBody};
+use common::{anonymous, field};
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Extended {
+    b: u64,
+    a: u32,
+    c: u8
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Base {
+    a: u32,
+    b: u64
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Narrow {
+    A(u8),
+    B(u64)
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[allow(dead_code)]
+enum Wide {
+    C,
+    B(u64),
+    A(u8)
+}
+
+unsafe fn coerce<S, T>(plan_source: &RustType, plan_target: &RustType, source: S) -> T {
+    let plan = plan_source.coercion_plan(plan_target).expect("no coercion plan");
+    assert_eq!(plan.source_size, std::mem::size_of::<S>());
+    assert_eq!(plan.target_size, std::mem::size_of::<T>());
+    let mut target = MaybeUninit::<T>::uninit();
+    plan.execute(&source as *const S as *const u8, target.as_mut_ptr() as *mut u8);
+    target.assume_init()
+}
+
+#[test]
+fn coerce_reordered_struct() {
+    let extended = anonymous("Extended", TypeStructure::CReprStruct {
+        body: TypeStructureBody::Fields(vec![
+            field("b", PrimitiveType::U64.rust_type()),
+            field("a", PrimitiveType::U32.rust_type()),
+            field("c", PrimitiveType::U8.rust_type())
+        ])
+    });
+    let base = anonymous("Base", TypeStructure::CReprStruct {
+        body: TypeStructureBody::Fields(vec![
+            field("a", PrimitiveType::U32.rust_type()),
+            field("b", PrimitiveType::U64.rust_type())
+        ])
+    });
+    let coerced: Base = unsafe { coerce(&extended, &base, Extended { b: 1 << 40, a: 7, c: 3 }) };
+    assert_eq!(coerced, Base { a: 7, b: 1 << 40 });
+    assert_eq!(base.coercion_plan(&extended), None);
+}
+
+#[test]
+fn coerce_enum_variants() {
+    let narrow = anonymous("Narrow", TypeStructure::CReprEnum {
+        variants: vec![
+            TypeEnumVariant { variant_name: "A".to_string(), body: TypeStructureBody::Tuple(vec![PrimitiveType::U8.rust_type()]) },
+            TypeEnumVariant { variant_name: "B".to_string(), body: TypeStructureBody::Tuple(vec![PrimitiveType::U64.rust_type()]) }
+        ]
+    });
+    let wide = anonymous("Wide", TypeStructure::CReprEnum {
+        variants: vec![
+            TypeEnumVariant { variant_name: "C".to_string(), body: TypeStructureBody::None },
+            TypeEnumVariant { variant_name: "B".to_string(), body: TypeStructureBody::Tuple(vec![PrimitiveType::U64.rust_type()]) },
+            TypeEnumVariant { variant_name: "A".to_string(), body: TypeStructureBody::Tuple(vec![PrimitiveType::U8.rust_type()]) }
+        ]
+    });
+    unsafe {
+        assert_eq!(coerce::<Narrow, Wide>(&narrow, &wide, Narrow::A(5)), Wide::A(5));
+        assert_eq!(coerce::<Narrow, Wide>(&narrow, &wide, Narrow::B(1 << 40)), Wide::B(1 << 40));
+    }
+}
@@ -0,0 +1,28 @@
+//! Builders for types constructed at runtime, shared by the integration tests
+#![allow(dead_code)]
+
+use structural_reflection::{RustType, RustTypeName, TypeStructure, TypeStructureBody, TypeStructureBodyField};
+
+pub fn field(name: &str, rust_type: RustType) -> TypeStructureBodyField {
+    TypeStructureBodyField {
+        name: name.to_string(),
+        rust_type
+    }
+}
+
+/// A type without a type id, with the size and alignment inferred from the structure
+pub fn anonymous(name: &str, structure: TypeStructure) -> RustType {
+    RustType {
+        type_id: None,
+        type_name: RustTypeName::simple(name.to_string()),
+        size: structure.infer_size().unwrap(),
+        align: structure.infer_align().unwrap(),
+        structure
+    }
+}
+
+pub fn c_struct(name: &str, fields: Vec<TypeStructureBodyField>) -> RustType {
+    anonymous(name, TypeStructure::CReprStruct {
+        body: TypeStructureBody::Fields(fields)
+    })
+}
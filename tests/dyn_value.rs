@@ -0,0 +1,98 @@
+#![cfg(feature = "derive")]
+
+mod common;
+
+use std::any::TypeId;
+use structural_reflection::{DynValue, PrimitiveType, RustType, TypePath, TypePathElem, ValueError};
+use structural_reflection::derive::{HasStructure, HasTypeName};
+use common::{c_struct, field};
+
+#[derive(Debug, PartialEq, HasTypeName, HasStructure)]
+#[repr(C)]
+pub struct Segment {
+    pub start: Point,
+    pub end: Point,
+    pub width: u8
+}
+
+#[derive(Debug, PartialEq, HasTypeName, HasStructure)]
+#[repr(C)]
+pub struct Reference(&'static u64);
+
+#[derive(Debug, PartialEq, HasTypeName, HasStructure)]
+#[repr(C)]
+pub struct Point {
+    pub x: f32,
+    pub y: f32
+}
+
+fn path(fields: &[&str]) -> TypePath {
+    fields.iter().map(|field| TypePathElem::Field(field.to_string())).collect()
+}
+
+#[test]
+fn dyn_value() {
+    // Built at runtime, so it has no type id
+    let script_point = c_struct("ScriptPoint", vec![
+        field("x", PrimitiveType::F32.rust_type()),
+        field("y", PrimitiveType::F32.rust_type())
+    ]);
+    let script_segment = c_struct("ScriptSegment", vec![
+        field("start", script_point.clone()),
+        field("end", script_point.clone()),
+        field("width", PrimitiveType::U8.rust_type())
+    ]);
+
+    let mut segment = DynValue::zeroed(script_segment).unwrap();
+    segment.set(&path(&["end"]), Point { x: 1.0, y: 2.0 }).unwrap();
+    segment.set(&path(&["start", "y"]), -1.0f32).unwrap();
+    segment.as_mut().field_mut("width").unwrap().set_u8(3).unwrap();
+    assert!(matches!(segment.set(&path(&["width"]), 3u32), Err(ValueError::IncompatibleType { .. })));
+    assert!(matches!(segment.set(&path(&["height"]), 3u8), Err(ValueError::NoField { .. })));
+
+    let mut end = DynValue::zeroed(script_point).unwrap();
+    end.as_mut().field_mut("x").unwrap().set_f32(4.0).unwrap();
+    segment.set_dyn(&path(&["end"]), end).unwrap();
+
+    assert!(!segment.is::<Point>());
+    let segment = segment.downcast::<Point>().unwrap_err();
+    assert_eq!(segment.downcast_ref::<Segment>().unwrap().end.x, 4.0);
+    assert_eq!(segment.downcast::<Segment>().unwrap(), Segment {
+        start: Point { x: 0.0, y: -1.0 },
+        end: Point { x: 4.0, y: 0.0 },
+        width: 3
+    });
+
+    // Type ids must match
+    let point = DynValue::new(Point { x: 5.0, y: 6.0 });
+    assert_eq!(point.as_ref().field("y").unwrap().as_f32().unwrap(), 6.0);
+    assert!(point.downcast::<[f32; 2]>().is_err());
+
+    let with_pointer = c_struct("WithPointer", vec![field("pointer", RustType::of::<&u32>())]);
+    assert!(matches!(DynValue::zeroed(with_pointer), Err(ValueError::NotZeroable { .. })));
+
+    // Types with a forged id or a structure which doesn't match their size aren't trusted
+    let forged_u64 = RustType {
+        type_id: Some(TypeId::of::<u64>()),
+        ..PrimitiveType::U8.rust_type()
+    };
+    assert!(!DynValue::zeroed(forged_u64).unwrap().is::<u64>());
+    let oversized_u8 = RustType {
+        size: 8,
+        align: 8,
+        ..PrimitiveType::U8.rust_type()
+    };
+    assert!(matches!(DynValue::zeroed(oversized_u8), Err(ValueError::InconsistentLayout { .. })));
+
+    // A nested forged id isn't trusted either: the field claims to be `&u64`, but is zeroable
+    let forged_reference = c_struct("ForgedReference", vec![field("0", RustType {
+        type_id: Some(TypeId::of::<&u64>()),
+        ..PrimitiveType::U64.rust_type()
+    })]);
+    let reference = DynValue::zeroed(forged_reference).unwrap();
+    assert!(!reference.is::<Reference>());
+    assert!(reference.as_ref().downcast_ref::<Reference>().is_none());
+    let reference = reference.downcast::<Reference>().unwrap_err();
+    let mut holder = DynValue::new(Reference(&0));
+    assert!(matches!(holder.set_dyn(&TypePath::empty(), reference), Err(ValueError::IncompatibleType { .. })));
+}
@@ -0,0 +1,82 @@
+mod common;
+
+use std::borrow::Cow;
+use structural_reflection::{InferenceContext, InferenceError, PrimitiveType, RustPointerKind, RustType, RustTypeName, TypeStructure};
+use common::{anonymous, field};
+
+#[test]
+fn infer_tuple_vars() {
+    let mut cx = InferenceContext::new();
+    let a = cx.fresh_var();
+    let b = cx.fresh_var();
+    let tuple = RustType::c_tuple(vec![a.clone(), b.clone()]);
+    cx.constrain_subtype(tuple.clone(), RustType::c_tuple(vec![PrimitiveType::U32.rust_type(), a.clone()]));
+    cx.solve().unwrap();
+    assert_eq!(cx.resolve(&b).unwrap(), PrimitiveType::U32.rust_type());
+    let resolved = cx.resolve(&tuple).unwrap();
+    assert_eq!(resolved, RustType::c_tuple(vec![PrimitiveType::U32.rust_type(), PrimitiveType::U32.rust_type()]));
+    assert_eq!(resolved.size, 8);
+
+    // Anonymous types aren't confused with variables
+    let anonymous = RustType {
+        type_id: None,
+        type_name: RustTypeName::Anonymous { desc: Cow::Borrowed("?0") },
+        size: 4,
+        align: 4,
+        structure: TypeStructure::Opaque
+    };
+    assert_eq!(cx.resolve(&anonymous).unwrap().type_name, anonymous.type_name);
+
+    let unconstrained = cx.fresh_var();
+    assert!(matches!(cx.resolve(&unconstrained), Err(InferenceError::Unresolved { .. })));
+}
+
+#[test]
+fn infer_mismatch() {
+    let mut cx = InferenceContext::new();
+    let a = cx.fresh_var();
+    cx.constrain_subtype(a.clone(), PrimitiveType::U32.rust_type());
+    cx.constrain_subtype(PrimitiveType::U16.rust_type(), a);
+    assert!(matches!(cx.solve(), Err(InferenceError::Mismatch { .. })));
+}
+
+#[test]
+fn infer_upper_bounds_meet() {
+    let mut cx = InferenceContext::new();
+    let a = cx.fresh_var();
+    let has_x = anonymous("HasX", TypeStructure::OpaqueFields { fields: vec![field("x", PrimitiveType::U32.rust_type())] });
+    let has_y = anonymous("HasY", TypeStructure::OpaqueFields { fields: vec![field("y", PrimitiveType::U16.rust_type())] });
+    cx.constrain_subtype(a.clone(), has_x);
+    cx.constrain_subtype(a.clone(), has_y);
+    cx.solve().unwrap();
+    let TypeStructure::OpaqueFields { fields } = cx.resolve(&a).unwrap().structure else {
+        panic!("expected fields")
+    };
+    assert_eq!(fields.iter().map(|field| field.name.as_str()).collect::<Vec<_>>(), vec!["x", "y"]);
+}
+
+#[test]
+fn infer_pointee_var() {
+    let pointer = |refd_name: RustTypeName| RustType {
+        type_id: None,
+        type_name: RustTypeName::Pointer { refd: Box::new(refd_name.clone()), ptr_kind: RustPointerKind::ImmRef },
+        size: std::mem::size_of::<*const ()>(),
+        align: std::mem::align_of::<*const ()>(),
+        structure: TypeStructure::Pointer {
+            ptr_kind: RustPointerKind::ImmRef,
+            ptr_size: std::mem::size_of::<*const ()>(),
+            refd_id: None,
+            refd_name
+        }
+    };
+    let mut cx = InferenceContext::new();
+    let a = cx.fresh_var();
+    let a_ptr = pointer(a.type_name.clone());
+    assert!(a_ptr.has_vars());
+    assert!(matches!(cx.resolve(&a_ptr), Err(InferenceError::Unresolved { .. })));
+
+    cx.constrain_subtype(a_ptr.clone(), pointer(PrimitiveType::U32.rust_type_name()));
+    cx.solve().unwrap();
+    assert_eq!(cx.resolve(&a).unwrap(), PrimitiveType::U32.rust_type());
+    assert_eq!(cx.resolve(&a_ptr).unwrap().type_name, pointer(PrimitiveType::U32.rust_type_name()).type_name);
+}
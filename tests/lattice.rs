@@ -0,0 +1,74 @@
+mod common;
+
+use structural_reflection::{IsSubtypeOf, PrimitiveType, RustType, TypeEnumVariant, TypeStructure, TypeStructureBody};
+use common::{anonymous, c_struct, field};
+
+fn c_enum(name: &str, variants: Vec<(&str, TypeStructureBody)>) -> RustType {
+    anonymous(name, TypeStructure::CReprEnum {
+        variants: variants.into_iter().map(|(variant_name, body)| TypeEnumVariant {
+            variant_name: variant_name.to_string(),
+            body
+        }).collect()
+    })
+}
+
+fn assert_meet_and_join(a: &RustType, b: &RustType) -> (RustType, RustType) {
+    let meet = a.meet(b);
+    assert_eq!(meet.is_rough_subtype_of(a), IsSubtypeOf::Yes, "{:?} is not a subtype of {:?}", meet, a);
+    assert_eq!(meet.is_rough_subtype_of(b), IsSubtypeOf::Yes, "{:?} is not a subtype of {:?}", meet, b);
+    let join = a.join(b);
+    assert_eq!(a.is_rough_subtype_of(&join), IsSubtypeOf::Yes, "{:?} is not a subtype of {:?}", a, join);
+    assert_eq!(b.is_rough_subtype_of(&join), IsSubtypeOf::Yes, "{:?} is not a subtype of {:?}", b, join);
+    (meet, join)
+}
+
+#[test]
+fn meet_and_join_structs() {
+    let a = c_struct("A", vec![
+        field("x", PrimitiveType::U32.rust_type()),
+        field("y", PrimitiveType::F64.rust_type())
+    ]);
+    let b = c_struct("B", vec![
+        field("y", PrimitiveType::F64.rust_type()),
+        field("z", PrimitiveType::Bool.rust_type())
+    ]);
+    let (meet, join) = assert_meet_and_join(&a, &b);
+    assert_eq!(meet.structure.general_field_compound_field_types().unwrap().iter().map(|field| field.name.as_str()).collect::<Vec<_>>(), vec!["x", "y", "z"]);
+    assert_eq!(join.structure.general_field_compound_field_types().unwrap().iter().map(|field| field.name.as_str()).collect::<Vec<_>>(), vec!["y"]);
+
+    let c = c_struct("C", vec![
+        field("x", PrimitiveType::U64.rust_type())
+    ]);
+    assert_eq!(a.meet(&c), RustType::bottom());
+    assert_eq!(a.meet(&RustType::unknown()), a);
+    assert_eq!(a.join(&RustType::bottom()), a);
+}
+
+#[test]
+fn meet_and_join_enums() {
+    let a = c_enum("A", vec![
+        ("Foo", TypeStructureBody::None),
+        ("Bar", TypeStructureBody::Tuple(vec![PrimitiveType::U8.rust_type()]))
+    ]);
+    let b = c_enum("B", vec![
+        ("Bar", TypeStructureBody::Tuple(vec![PrimitiveType::U8.rust_type()])),
+        ("Baz", TypeStructureBody::None)
+    ]);
+    let (meet, join) = assert_meet_and_join(&a, &b);
+    assert!(matches!(&meet.structure, TypeStructure::CReprEnum { variants } if variants.len() == 1));
+    assert!(matches!(&join.structure, TypeStructure::CReprEnum { variants } if variants.len() == 3));
+
+    let c = c_enum("C", vec![
+        ("Qux", TypeStructureBody::None)
+    ]);
+    assert_eq!(a.meet(&c), RustType::bottom());
+}
+
+#[test]
+fn meet_opaque() {
+    let string = RustType::of_dont_register::<String>();
+    assert_eq!(string.meet(&PrimitiveType::U32.rust_type()), RustType::bottom());
+    assert_eq!(PrimitiveType::U32.rust_type().meet(&string), RustType::bottom());
+    assert_eq!(string.meet(&string), string);
+    assert_eq!(string.meet(&RustType::unknown()), string);
+}
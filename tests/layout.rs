@@ -0,0 +1,63 @@
+mod common;
+
+use std::any::TypeId;
+use structural_reflection::{LayoutCompat, LayoutIncompatibility, PrimitiveType, RustType, TypePath, TypePathElem, TypeStructure};
+use common::{c_struct, field};
+
+#[test]
+fn layout_compatibility() {
+    let base = c_struct("Base", vec![
+        field("a", PrimitiveType::U32.rust_type()),
+        field("b", PrimitiveType::U32.rust_type())
+    ]);
+    let renamed = c_struct("Renamed", vec![
+        field("x", PrimitiveType::U32.rust_type()),
+        field("y", PrimitiveType::U32.rust_type())
+    ]);
+    let derived = c_struct("Derived", vec![
+        field("a", PrimitiveType::U32.rust_type()),
+        field("b", PrimitiveType::U32.rust_type()),
+        field("c", PrimitiveType::U64.rust_type())
+    ]);
+    let reordered = c_struct("Reordered", vec![
+        field("b", PrimitiveType::U64.rust_type()),
+        field("a", PrimitiveType::U32.rust_type())
+    ]);
+    assert_eq!(base.is_layout_compatible_with(&renamed), LayoutCompat::Identical);
+    assert_eq!(derived.is_layout_compatible_with(&base), LayoutCompat::PrefixCompatible);
+    assert_eq!(base.is_layout_compatible_with(&derived), LayoutCompat::Incompatible {
+        path: TypePath::empty(),
+        reason: LayoutIncompatibility::Size { size: 8, other_size: 16 }
+    });
+    assert_eq!(reordered.is_layout_compatible_with(&base), LayoutCompat::Incompatible {
+        path: TypePath::from(vec![TypePathElem::Index(0)]),
+        reason: LayoutIncompatibility::PrimitiveMismatch {
+            primitive: PrimitiveType::U64,
+            other_primitive: PrimitiveType::U32
+        }
+    });
+
+    // Rust-repr fields may be reordered
+    let opaque_fields = RustType {
+        structure: TypeStructure::OpaqueFields {
+            fields: vec![field("a", PrimitiveType::U32.rust_type()), field("b", PrimitiveType::U32.rust_type())]
+        },
+        ..base.clone()
+    };
+    assert!(matches!(
+        opaque_fields.is_layout_compatible_with(&base),
+        LayoutCompat::Incompatible { reason: LayoutIncompatibility::UnknownLayout { .. }, .. }
+    ));
+
+    // A field with a forged id isn't identical to the real type
+    let reference = c_struct("Reference", vec![field("0", RustType::of::<&u64>())]);
+    let forged_reference = c_struct("ForgedReference", vec![field("0", RustType {
+        type_id: Some(TypeId::of::<&u64>()),
+        ..PrimitiveType::U64.rust_type()
+    })]);
+    assert!(matches!(
+        forged_reference.is_layout_compatible_with(&reference),
+        LayoutCompat::Incompatible { reason: LayoutIncompatibility::KindMismatch { .. }, .. }
+    ));
+    assert_eq!(reference.is_layout_compatible_with(&reference.clone()), LayoutCompat::Identical);
+}
@@ -0,0 +1,98 @@
+#![cfg(feature = "persist")]
+
+mod common;
+
+use std::any::TypeId;
+use std::borrow::Cow;
+use structural_reflection::{PrimitiveType, RegistryPersistError, RustPointerKind, RustType, RustTypeName, TypeEnumVariant, TypeRegistry, TypeStructure, TypeStructureBody, TypeStructureBodyField};
+use common::{c_struct, field};
+
+#[test]
+fn export_and_import() {
+    let mut producer = TypeRegistry::new();
+    let message_name = RustTypeName::simple("PersistMessage".to_string());
+    let structure = TypeStructure::CReprEnum {
+        variants: vec![
+            TypeEnumVariant { variant_name: "Empty".to_string(), body: TypeStructureBody::None },
+            TypeEnumVariant {
+                variant_name: "Data".to_string(),
+                body: TypeStructureBody::Fields(vec![
+                    TypeStructureBodyField { name: "len".to_string(), rust_type: RustType::of_in::<u32>(&mut producer) },
+                    TypeStructureBodyField {
+                        name: "ptr".to_string(),
+                        rust_type: RustType::of_dont_register::<*mut u8>()
+                    }
+                ])
+            }
+        ]
+    };
+    let message = RustType {
+        type_id: None,
+        type_name: message_name.clone(),
+        size: structure.infer_size().unwrap(),
+        align: structure.infer_align().unwrap(),
+        structure
+    };
+    producer.register(Cow::Borrowed(&message), None);
+    let mut file = Vec::new();
+    producer.export(&mut file).unwrap();
+    let mut file2 = Vec::new();
+    producer.export(&mut file2).unwrap();
+    assert_eq!(file, file2);
+
+    let mut consumer = TypeRegistry::new();
+    consumer.import(file.as_slice()).unwrap();
+    let imported = consumer.lookup(&message_name).unwrap();
+    assert_eq!((imported.size, imported.align), (message.size, message.align));
+    assert_eq!(imported.structure, message.structure);
+    let imported_len = consumer.lookup(&PrimitiveType::U32.rust_type_name()).unwrap();
+    assert_eq!(imported_len.type_id, None);
+    assert!(matches!(
+        imported.structure.clone(),
+        TypeStructure::CReprEnum { variants } if matches!(&variants[1].body, TypeStructureBody::Fields(fields) if matches!(fields[1].rust_type.structure, TypeStructure::Pointer { ptr_kind: RustPointerKind::MutRaw, .. }))
+    ));
+    // Builtins keep their type ids
+    let string_name = RustTypeName::try_from("std::string::String").unwrap();
+    assert_eq!(consumer.lookup(&string_name).unwrap().type_id, Some(TypeId::of::<String>()));
+
+    let newer = String::from_utf8(file).unwrap().replacen("\"version\": 1", "\"version\": 1000", 1);
+    assert!(matches!(consumer.import(newer.as_bytes()), Err(RegistryPersistError::UnsupportedVersion { version: 1000 })));
+    assert!(matches!(consumer.import("{}".as_bytes()), Err(RegistryPersistError::NotARegistryFile)));
+}
+
+#[test]
+fn import_mismatched_builtin() {
+    let string_name = RustTypeName::try_from("std::string::String").unwrap();
+    let holder_name = RustTypeName::simple("StringHolder".to_string());
+    let holder = c_struct("StringHolder", vec![field("string", RustType::of_dont_register::<*const String>())]);
+    let refd_id = |registry: &TypeRegistry| match &registry.lookup(&holder_name).unwrap().structure {
+        TypeStructure::CReprStruct { body: TypeStructureBody::Fields(fields) } => match fields[0].rust_type.structure {
+            TypeStructure::Pointer { refd_id, .. } => refd_id,
+            _ => unreachable!()
+        },
+        _ => unreachable!()
+    };
+
+    let mut producer = TypeRegistry::new();
+    producer.register(Cow::Borrowed(&holder), None);
+    let mut file = Vec::new();
+    producer.export(&mut file).unwrap();
+    let mut consumer = TypeRegistry::new();
+    consumer.import(file.as_slice()).unwrap();
+    assert_eq!(refd_id(&consumer), Some(TypeId::of::<String>()));
+
+    // Another process's `String` has a different structure, so it's a different type
+    let forged_string = RustType {
+        type_name: string_name.clone(),
+        ..c_struct("", vec![field("len", PrimitiveType::U8.rust_type())])
+    };
+    producer.register(Cow::Borrowed(&forged_string), None);
+    let mut file = Vec::new();
+    producer.export(&mut file).unwrap();
+    let mut consumer = TypeRegistry::new();
+    consumer.import(file.as_slice()).unwrap();
+    let string = consumer.lookup(&string_name).unwrap();
+    assert_eq!(string.type_id, Some(TypeId::of::<String>()));
+    assert_eq!(string.structure, TypeStructure::Opaque);
+    assert_eq!(refd_id(&consumer), None);
+}
@@ -0,0 +1,302 @@
+#![cfg(feature = "registry")]
+
+use std::any::TypeId;
+use std::borrow::Cow;
+use std::sync::{Arc, Mutex};
+use structural_reflection::{ConflictPolicy, PrimitiveType, RegistryConflict, RegistryEvent, RustType, RustTypeName, TypeRegistry, TypeStructure, TypeStructureBody, TypeTemplate};
+
+#[test]
+fn separate_registries() {
+    let mut plugin_a = TypeRegistry::new();
+    let mut plugin_b = TypeRegistry::new();
+    let string_name = RustTypeName::try_from("std::string::String").unwrap();
+    assert!(plugin_a.lookup(&string_name).is_some());
+
+    let u32_type = RustType::of_in::<u32>(&mut plugin_a);
+    assert_eq!(plugin_a.lookup_from_id(TypeId::of::<u32>()).map(Arc::as_ref), Some(&u32_type));
+    assert_eq!(plugin_a.lookup_id(&u32_type.type_name), Some(TypeId::of::<u32>()));
+    assert!(plugin_a.lookup_intrinsic(TypeId::of::<u32>()).is_some());
+    assert_eq!(plugin_b.lookup_from_id(TypeId::of::<u32>()), None);
+
+    let point_name = RustTypeName::simple("RegistryPoint".to_string());
+    let point = RustType {
+        type_id: None,
+        type_name: point_name.clone(),
+        size: 8,
+        align: 4,
+        structure: TypeStructure::Opaque
+    };
+    plugin_b.register(Cow::Borrowed(&point), None);
+    assert_eq!(plugin_b.lookup(&point_name).map(Arc::as_ref), Some(&point));
+    assert_eq!(plugin_a.lookup(&point_name), None);
+    assert_eq!(RustType::lookup(&point_name), None);
+}
+
+#[test]
+fn registry_conflicts() {
+    let name = RustTypeName::simple("RegistryConflicting".to_string());
+    let opaque = RustType {
+        type_id: None,
+        type_name: name.clone(),
+        size: 4,
+        align: 4,
+        structure: TypeStructure::OpaqueTuple { elements: vec![RustType::unknown()] }
+    };
+    let tuple = RustType {
+        structure: TypeStructure::CReprStruct { body: TypeStructureBody::Tuple(vec![PrimitiveType::U32.rust_type()]) },
+        ..opaque.clone()
+    };
+    let other_tuple = RustType {
+        structure: TypeStructure::CReprStruct { body: TypeStructureBody::Tuple(vec![PrimitiveType::I32.rust_type()]) },
+        ..opaque.clone()
+    };
+
+    let mut registry = TypeRegistry::new();
+    registry.set_conflict_policy(ConflictPolicy::Error);
+    registry.try_register(Cow::Borrowed(&opaque), None).unwrap();
+    registry.try_register(Cow::Borrowed(&opaque), None).unwrap();
+    match registry.try_register(Cow::Borrowed(&tuple), None) {
+        Err(RegistryConflict::Type { old, new }) => {
+            assert_eq!(old.structure, opaque.structure);
+            assert_eq!(new.structure, tuple.structure);
+        }
+        result => panic!("expected a type conflict, got {:?}", result)
+    }
+    assert_eq!(registry.lookup(&name).unwrap().structure, opaque.structure);
+    // A failed registration doesn't register the type name either
+    let tuple_with_id = RustType { type_id: Some(TypeId::of::<[u32; 1]>()), ..tuple.clone() };
+    assert!(registry.try_register(Cow::Borrowed(&tuple_with_id), None).is_err());
+    assert_eq!(registry.lookup_name(TypeId::of::<[u32; 1]>()), None);
+
+    registry.set_conflict_policy(ConflictPolicy::Unify);
+    registry.try_register(Cow::Borrowed(&tuple), None).unwrap();
+    assert_eq!(registry.lookup(&name).unwrap().structure, tuple.structure);
+    assert!(registry.try_register(Cow::Borrowed(&other_tuple), None).is_err());
+
+    registry.set_conflict_policy(ConflictPolicy::KeepOld);
+    registry.try_register(Cow::Borrowed(&other_tuple), None).unwrap();
+    assert_eq!(registry.lookup(&name).unwrap().structure, tuple.structure);
+
+    registry.set_conflict_policy(ConflictPolicy::Replace);
+    registry.try_register(Cow::Borrowed(&other_tuple), None).unwrap();
+    assert_eq!(registry.lookup(&name).unwrap().structure, other_tuple.structure);
+
+    registry.set_conflict_policy(ConflictPolicy::Error);
+    let u32_type = RustType::of_in::<u32>(&mut registry);
+    assert!(matches!(
+        registry.try_register_type_name(TypeId::of::<u32>(), Cow::Borrowed(&name)),
+        Err(RegistryConflict::TypeName { old, .. }) if *old == u32_type.type_name
+    ));
+    assert_eq!(registry.lookup_name(TypeId::of::<u32>()), Some(&u32_type.type_name));
+}
+
+#[test]
+fn unregister_and_restore() {
+    let mut registry = TypeRegistry::new();
+    let builtins = registry.types().count();
+    let snapshot = registry.snapshot();
+
+    let u32_type = RustType::of_in::<u32>(&mut registry);
+    let u8_type = RustType::of_in::<u8>(&mut registry);
+    assert_eq!(registry.types().count(), builtins + 2);
+    assert!(registry.type_names().any(|(type_id, type_name)| type_id == TypeId::of::<u32>() && type_name == &u32_type.type_name));
+    assert!(registry.intrinsics().any(|intrinsic| intrinsic.type_id == TypeId::of::<u8>()));
+
+    assert_eq!(registry.unregister(&u32_type.type_name).as_deref(), Some(&u32_type));
+    assert_eq!(registry.lookup_name(TypeId::of::<u32>()), None);
+    assert_eq!(registry.lookup_intrinsic(TypeId::of::<u32>()), None);
+    assert_eq!(registry.unregister(&u32_type.type_name), None);
+    assert_eq!(registry.unregister_from_id(TypeId::of::<u8>()).as_deref(), Some(&u8_type));
+    assert_eq!(registry.lookup_id(&u8_type.type_name), None);
+    assert_eq!(registry.types().count(), builtins);
+
+    RustType::of_in::<u32>(&mut registry);
+    registry.restore(snapshot);
+    assert_eq!(registry.lookup(&u32_type.type_name), None);
+    assert_eq!(registry.types().count(), builtins);
+}
+
+#[test]
+fn generic_templates() {
+    let mut registry = TypeRegistry::new();
+    let vec_u32 = registry.lookup_or_instantiate(&RustTypeName::try_from("std::vec::Vec<u32>").unwrap()).unwrap();
+    assert_eq!(vec_u32.type_name, RustTypeName::try_from("std::vec::Vec<u32>").unwrap());
+    assert_eq!(vec_u32.size, std::mem::size_of::<Vec<u32>>());
+    assert_eq!(vec_u32.align, std::mem::align_of::<Vec<u32>>());
+    // The template matches the names `HasTypeName` produces
+    let vec_u64 = registry.lookup_or_instantiate(&RustType::of_in::<Vec<u64>>(&mut TypeRegistry::new()).type_name).unwrap();
+    assert_eq!(vec_u64.size, std::mem::size_of::<Vec<u64>>());
+
+    let vec_unknown = registry.lookup_or_instantiate(&RustTypeName::try_from("std::vec::Vec<TemplateMissing>").unwrap()).unwrap();
+    assert_eq!(vec_unknown.type_name, RustTypeName::try_from("std::vec::Vec<{unknown}>").unwrap());
+    assert_eq!(registry.lookup_or_instantiate(&RustTypeName::try_from("TemplateMissing<u32>").unwrap()), None);
+
+    registry.register_template(TypeTemplate::new(RustTypeName::try_from("TemplatePair<A, B>").unwrap(), |type_name, generic_args| {
+        Some(RustType {
+            type_name,
+            ..RustType::c_tuple(generic_args.to_vec())
+        })
+    }));
+    let pair_name = RustTypeName::try_from("TemplatePair<u8, u32>").unwrap();
+    let pair = registry.lookup_or_instantiate(&pair_name).unwrap();
+    assert_eq!((pair.size, pair.align), (8, 4));
+    assert_eq!(pair.structure.tuple_elem_types().unwrap(), &vec![PrimitiveType::U8.rust_type(), PrimitiveType::U32.rust_type()]);
+    let vec_pair = registry.lookup_or_instantiate(&RustTypeName::try_from("std::vec::Vec<TemplatePair<u8, u32>>").unwrap()).unwrap();
+    assert_eq!(vec_pair.size, std::mem::size_of::<Vec<(u8, u32)>>());
+
+    assert!(registry.unregister_template(&pair_name).is_some());
+    assert_eq!(registry.lookup_or_instantiate(&pair_name), None);
+}
+
+#[test]
+fn partial_lookup_and_suggestions() {
+    let mut registry = TypeRegistry::new();
+    let point = |qualifier: &str| RustType {
+        type_id: None,
+        type_name: RustTypeName::try_from(format!("{}::SearchPoint", qualifier).as_str()).unwrap(),
+        size: 8,
+        align: 4,
+        structure: TypeStructure::Opaque
+    };
+    registry.register(Cow::Owned(point("geo")), None);
+    registry.register(Cow::Owned(point("gfx")), None);
+    let candidates = registry.lookup_simple("SearchPoint");
+    assert_eq!(candidates, vec![&Arc::new(point("geo")), &Arc::new(point("gfx"))]);
+    assert_eq!(registry.lookup_partial(&RustTypeName::try_from("SearchPoint").unwrap()).len(), 2);
+    assert_eq!(registry.lookup_partial(&RustTypeName::try_from("gfx::SearchPoint").unwrap()), vec![Arc::new(point("gfx"))]);
+    // Ambiguous argument
+    assert!(registry.lookup_partial(&RustTypeName::try_from("Vec<SearchPoint>").unwrap()).is_empty());
+
+    registry.unregister(&point("gfx").type_name);
+    let vec_points = registry.lookup_partial(&RustTypeName::try_from("Vec<SearchPoint>").unwrap());
+    assert_eq!(vec_points.len(), 1);
+    assert_eq!(vec_points[0].type_name, RustTypeName::try_from("std::vec::Vec<geo::SearchPoint>").unwrap());
+
+    assert_eq!(registry.suggest_names("SerchPoint", 3), vec![&point("geo").type_name]);
+    assert_eq!(registry.suggest_names("Strng", 3), vec![&RustTypeName::try_from("std::string::String").unwrap()]);
+    assert!(registry.suggest_names("Unrelated", 3).is_empty());
+}
+
+#[test]
+fn observers() {
+    let mut registry = TypeRegistry::new();
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let observer_events = events.clone();
+    let observer = registry.subscribe(move |event| observer_events.lock().unwrap().push(event.clone()));
+    let snapshot = registry.snapshot();
+    let name = RustTypeName::simple("ObservedType".to_string());
+    let observed = |size: usize| RustType {
+        type_id: None,
+        type_name: name.clone(),
+        size,
+        align: 4,
+        structure: TypeStructure::Opaque
+    };
+    // Clones don't have the observers
+    registry.clone().register(Cow::Owned(observed(16)), None);
+    registry.register(Cow::Owned(observed(4)), None);
+    registry.register(Cow::Owned(observed(8)), None);
+    registry.unregister(&name);
+    registry.register(Cow::Owned(observed(4)), None);
+    registry.restore(snapshot);
+    assert!(registry.unsubscribe(observer));
+    registry.register(Cow::Owned(observed(4)), None);
+    let events = events.lock().unwrap();
+    assert!(matches!(&events[..], [
+        RegistryEvent::Registered { new: a },
+        RegistryEvent::Replaced { old: b, new: c },
+        RegistryEvent::Unregistered { old: d },
+        RegistryEvent::Registered { .. },
+        RegistryEvent::Unregistered { old: e }
+    ] if a.size == 4 && b.size == 4 && c.size == 8 && d.size == 8 && e.size == 4));
+    drop(events);
+
+    // Global observers can access the registry
+    let looked_up = Arc::new(Mutex::new(Vec::new()));
+    let observer_looked_up = looked_up.clone();
+    let global_name = RustTypeName::simple("GlobalObservedType".to_string());
+    let observer_global_name = global_name.clone();
+    let observer = TypeRegistry::subscribe_global(move |event| {
+        if let RegistryEvent::Registered { new } = event {
+            // Other tests may register types concurrently
+            if new.type_name == observer_global_name {
+                observer_looked_up.lock().unwrap().push(RustType::lookup(&new.type_name));
+            }
+        }
+    }).unwrap();
+    RustType::register(Cow::Owned(RustType { type_name: global_name.clone(), ..observed(4) }), None);
+    assert!(TypeRegistry::unsubscribe_global(observer));
+    assert!(matches!(&looked_up.lock().unwrap()[..], [Some(rust_type)] if rust_type.type_name == global_name));
+}
+
+#[test]
+fn global_registry_recovers_from_panics() {
+    let name = RustTypeName::simple("PanicRecoveredType".to_string());
+    let rust_type = RustType {
+        type_id: None,
+        type_name: name.clone(),
+        size: 4,
+        align: 4,
+        structure: TypeStructure::Opaque
+    };
+    let panicked = std::panic::catch_unwind(|| TypeRegistry::with_global_mut(|_| panic!("modification failed")));
+    assert!(panicked.is_err());
+    assert!(RustType::try_register(Cow::Borrowed(&rust_type), None).is_ok());
+    assert_eq!(RustType::lookup(&name).as_deref(), Some(&rust_type));
+}
+
+#[test]
+fn std_builtins() {
+    let registry = TypeRegistry::new();
+    let lookup = |name: &str| registry.lookup_or_instantiate(&RustTypeName::try_from(name).unwrap());
+    assert!(lookup("std::collections::HashMap<{unknown}, {unknown}>").is_some());
+    assert!(lookup("std::collections::HashSet<{unknown}, {unknown}>").is_none());
+    assert_eq!(lookup("std::collections::HashMap<u32, u8>").unwrap().size, std::mem::size_of::<std::collections::HashMap<u32, u8>>());
+    assert_eq!(lookup("std::sync::Arc<std::string::String>").unwrap().size, std::mem::size_of::<Arc<String>>());
+    assert_eq!(lookup("std::time::Duration").unwrap().size, std::mem::size_of::<std::time::Duration>());
+
+    let cell = lookup("std::cell::Cell<u64>").unwrap();
+    assert_eq!((cell.size, cell.align), (8, std::mem::align_of::<u64>()));
+    assert_eq!(cell.structure.tuple_struct_item_types(), Some(&vec![PrimitiveType::U64.rust_type()]));
+    assert_eq!(lookup("std::mem::MaybeUninit<u16>").unwrap().structure, TypeStructure::Opaque);
+
+    // Layouts computed from the generic arguments
+    macro_rules! assert_layout {
+        ($name:literal, $type:ty) => {
+            let rust_type = lookup($name).unwrap();
+            assert_eq!((rust_type.size, rust_type.align), (std::mem::size_of::<$type>(), std::mem::align_of::<$type>()), "{}", $name);
+        }
+    }
+    assert_layout!("std::option::Option<u32>", Option<u32>);
+    assert_layout!("std::option::Option<bool>", Option<bool>);
+    assert_layout!("std::option::Option<char>", Option<char>);
+    assert_layout!("std::result::Result<u8, u64>", Result<u8, u64>);
+    assert_layout!("std::result::Result<u16, i8>", Result<u16, i8>);
+    assert_layout!("std::borrow::Cow<u64>", Cow<'static, u64>);
+    assert_layout!("std::cell::RefCell<u16>", std::cell::RefCell<u16>);
+    assert_layout!("std::cell::RefCell<u128>", std::cell::RefCell<u128>);
+    assert_layout!("std::sync::Mutex<u8>", Mutex<u8>);
+    assert_layout!("std::sync::Mutex<u64>", Mutex<u64>);
+    assert_layout!("std::ops::Range<u64>", std::ops::Range<u64>);
+    assert_layout!("std::ops::RangeInclusive<u8>", std::ops::RangeInclusive<u8>);
+    assert_layout!("std::ops::RangeFrom<u32>", std::ops::RangeFrom<u32>);
+    assert_layout!("std::ops::RangeTo<i16>", std::ops::RangeTo<i16>);
+    assert_layout!("std::ops::RangeToInclusive<f64>", std::ops::RangeToInclusive<f64>);
+    // Niches in opaque types aren't known, so the layout isn't either
+    let option_string = lookup("std::option::Option<std::string::String>").unwrap();
+    assert_eq!(option_string.type_name, RustTypeName::try_from("std::option::Option<{unknown}>").unwrap());
+    assert_eq!(option_string.size, usize::MAX);
+    assert_eq!(RustType::lookup(&RustTypeName::try_from("std::option::Option<u32>").unwrap()).unwrap().size, std::mem::size_of::<Option<u32>>());
+
+    let cell = RustType::of::<std::cell::Cell<u64>>();
+    assert_eq!(cell.type_name, RustTypeName::try_from("std::cell::Cell<u64>").unwrap());
+    assert_eq!(cell.structure.tuple_struct_item_types(), Some(&vec![PrimitiveType::U64.rust_type()]));
+    assert_eq!(
+        RustType::of::<Option<Cow<'static, str>>>().type_name,
+        RustTypeName::try_from("std::option::Option<std::borrow::Cow<str>>").unwrap()
+    );
+    assert!(matches!(
+        RustType::of::<std::ptr::NonNull<[u8]>>().structure.tuple_struct_item_types().map(|elems| &elems[0].structure),
+        Some(TypeStructure::Pointer { .. })
+    ));
+}
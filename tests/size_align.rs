@@ -0,0 +1,42 @@
+use std::mem::{align_of, size_of};
+use structural_reflection::{PrimitiveType, TypeEnumVariant, TypeStructure, TypeStructureBody};
+
+#[allow(dead_code)]
+#[repr(C)]
+enum WithU64 {
+    Value(u64),
+    Empty
+}
+
+#[allow(dead_code)]
+#[repr(C)]
+enum WithU8 {
+    Value(u8),
+    Empty
+}
+
+fn c_enum(payload: PrimitiveType) -> TypeStructure {
+    TypeStructure::CReprEnum {
+        variants: vec![
+            TypeEnumVariant {
+                variant_name: "Value".to_string(),
+                body: TypeStructureBody::Tuple(vec![payload.rust_type()])
+            },
+            TypeEnumVariant {
+                variant_name: "Empty".to_string(),
+                body: TypeStructureBody::None
+            }
+        ]
+    }
+}
+
+#[test]
+fn c_repr_enum_layout() {
+    // The payload is aligned after the discriminant, and the size is a multiple of the alignment
+    let with_u64 = c_enum(PrimitiveType::U64);
+    assert_eq!(with_u64.infer_size(), Some(size_of::<WithU64>()));
+    assert_eq!(with_u64.infer_align(), Some(align_of::<WithU64>()));
+    let with_u8 = c_enum(PrimitiveType::U8);
+    assert_eq!(with_u8.infer_size(), Some(size_of::<WithU8>()));
+    assert_eq!(with_u8.infer_align(), Some(align_of::<WithU8>()));
+}
@@ -0,0 +1,280 @@
+mod common;
+
+use structural_reflection::{IsSubtypeOf, SubtypeRule, TypeEnumVariant, UnifyConflict, UnifyConflictKind, TypePath, TypePathElem, SubtypePolicy, PrimitiveType, RustType, TypeStructure, TypeStructureBody};
+use common::{anonymous, c_struct, field};
+
+#[test]
+fn explain_subtype() {
+    let server = c_struct("ExplainServer", vec![
+        field("host", PrimitiveType::U32.rust_type()),
+        field("port", PrimitiveType::U16.rust_type())
+    ]);
+    let server_schema = c_struct("ExplainServerSchema", vec![
+        field("port", PrimitiveType::U32.rust_type())
+    ]);
+    let config = c_struct("ExplainConfig", vec![field("server", server.clone())]);
+    let config_schema = c_struct("ExplainConfigSchema", vec![field("server", server_schema)]);
+
+    let report = config.explain_subtype(&config_schema);
+    assert_eq!(report.result, IsSubtypeOf::No);
+    let failure = report.failure.as_ref().unwrap();
+    assert_eq!(failure.path, TypePath::from(vec![TypePathElem::Field("server".to_string()), TypePathElem::Field("port".to_string())]));
+    assert_eq!(failure.rule, SubtypeRule::TypeIdMismatch { type_name: PrimitiveType::U16.rust_type_name(), other_type_name: PrimitiveType::U32.rust_type_name() });
+    assert_eq!(report.to_string(), "is not a subtype: different types: u16 and u32\n  in field `port`\n  in field `server`");
+
+    let missing = c_struct("ExplainMissing", vec![field("server", c_struct("ExplainPartial", vec![field("port", PrimitiveType::U16.rust_type())]))]);
+    let report = missing.explain_subtype(&config);
+    assert_eq!(report.failure.unwrap().rule, SubtypeRule::MissingField { name: "host".to_string() });
+
+    assert_eq!(config.explain_subtype(&config), structural_reflection::SubtypeReport::yes());
+    let opaque = RustType { structure: TypeStructure::Opaque, ..server };
+    let report = c_struct("ExplainOpaque", vec![field("server", opaque)]).explain_subtype(&config);
+    assert_eq!(report.result, IsSubtypeOf::Unknown);
+    assert_eq!(report.failure.unwrap().rule, SubtypeRule::Opaque);
+}
+
+#[test]
+fn enum_variant_bodies_are_covariant() {
+    let c_enum = |name: &str, fields| anonymous(name, TypeStructure::CReprEnum {
+        variants: vec![TypeEnumVariant {
+            variant_name: "Point".to_string(),
+            body: TypeStructureBody::Fields(fields)
+        }]
+    });
+    let point3 = c_enum("VariantPoint3", vec![
+        field("x", PrimitiveType::U32.rust_type()),
+        field("y", PrimitiveType::U32.rust_type()),
+        field("z", PrimitiveType::U32.rust_type())
+    ]);
+    let point2 = c_enum("VariantPoint2", vec![
+        field("x", PrimitiveType::U32.rust_type()),
+        field("y", PrimitiveType::U32.rust_type())
+    ]);
+    assert_eq!(point3.is_structural_subtype_of(&point2), IsSubtypeOf::Yes);
+    assert_eq!(point2.is_structural_subtype_of(&point3), IsSubtypeOf::No);
+    let failure = point2.explain_subtype(&point3).failure.unwrap();
+    assert_eq!(failure.path, TypePath::from(vec![TypePathElem::Variant("Point".to_string())]));
+    assert_eq!(failure.rule, SubtypeRule::MissingField { name: "z".to_string() });
+}
+
+#[test]
+fn try_unify_reports_conflicts() {
+    let mut explicit = TypeStructure::OpaqueFields {
+        fields: vec![
+            field("a", RustType::unknown()),
+            field("b", PrimitiveType::U32.rust_type())
+        ]
+    };
+    let inferred = TypeStructure::CReprStruct {
+        body: TypeStructureBody::Fields(vec![
+            field("a", PrimitiveType::U64.rust_type()),
+            field("b", PrimitiveType::U16.rust_type())
+        ])
+    };
+    let conflicts = explicit.try_unify(inferred).unwrap_err();
+    assert_eq!(conflicts.0, vec![UnifyConflict {
+        path: TypePath::from(vec![TypePathElem::Field("b".to_string())]),
+        kind: UnifyConflictKind::TypeIdMismatch {
+            type_name: PrimitiveType::U32.rust_type().type_name,
+            other_type_name: PrimitiveType::U16.rust_type().type_name
+        }
+    }]);
+    // The compatible parts are still unified
+    assert_eq!(explicit, TypeStructure::CReprStruct {
+        body: TypeStructureBody::Fields(vec![
+            field("a", PrimitiveType::U64.rust_type()),
+            field("b", PrimitiveType::U32.rust_type())
+        ])
+    });
+    assert_eq!(explicit.clone().try_unify(explicit), Ok(()));
+}
+
+#[test]
+fn subtype_policy() {
+    let widening = SubtypePolicy { numeric_widening: true, ..SubtypePolicy::default() };
+    let small = c_struct("PolicySmall", vec![
+        field("a", PrimitiveType::U8.rust_type()),
+        field("b", PrimitiveType::I16.rust_type())
+    ]);
+    let wide = c_struct("PolicyWide", vec![
+        field("a", PrimitiveType::U32.rust_type()),
+        field("b", PrimitiveType::F32.rust_type())
+    ]);
+    assert_eq!(small.is_rough_subtype_of(&wide), IsSubtypeOf::No);
+    assert_eq!(small.is_rough_subtype_of_with_policy(&wide, widening), IsSubtypeOf::Yes);
+    assert_eq!(wide.is_rough_subtype_of_with_policy(&small, widening), IsSubtypeOf::No);
+    assert_eq!(wide.clone().try_unify_with_policy(small.clone(), widening), Ok(()));
+
+    let exact = SubtypePolicy::exact();
+    let extra = c_struct("PolicyExtra", vec![
+        field("x", PrimitiveType::U8.rust_type()),
+        field("y", PrimitiveType::I16.rust_type()),
+        field("z", PrimitiveType::U8.rust_type())
+    ]);
+    // Names are ignored, but the extra field isn't allowed
+    assert_eq!(extra.is_rough_subtype_of(&small), IsSubtypeOf::No);
+    assert_eq!(extra.is_rough_subtype_of_with_policy(&small, SubtypePolicy { fields_by_position: true, ..SubtypePolicy::default() }), IsSubtypeOf::Yes);
+    assert_eq!(extra.explain_subtype_with_policy(&small, exact).failure.unwrap().rule, SubtypeRule::LengthMismatch { length: 3, other_length: 2 });
+
+    let by_name = SubtypePolicy { allow_extra_fields: false, ..SubtypePolicy::default() };
+    let renamed = c_struct("PolicyRenamed", vec![
+        field("a", PrimitiveType::U8.rust_type()),
+        field("b", PrimitiveType::I16.rust_type()),
+        field("c", PrimitiveType::U8.rust_type())
+    ]);
+    assert_eq!(renamed.explain_subtype_with_policy(&small, by_name).failure.unwrap().rule, SubtypeRule::ExtraField { name: "c".to_string() });
+}
+
+/// Covariant pointers and the cache look up pointees in a registry
+#[cfg(feature = "registry")]
+mod registry {
+    use std::borrow::Cow;
+    use structural_reflection::{TypeRegistry, IsSubtypeOf, PointerVariance, SubtypeCache, SubtypePolicy, PrimitiveType, RustPointerKind, RustType, RustTypeName, TypeStructure};
+    use crate::common::{c_struct, field};
+
+    fn pointer(ptr_kind: RustPointerKind, refd: &RustType) -> RustType {
+        let structure = TypeStructure::Pointer {
+            ptr_kind,
+            ptr_size: std::mem::size_of::<*const ()>(),
+            refd_id: None,
+            refd_name: refd.type_name.clone()
+        };
+        RustType {
+            type_id: None,
+            type_name: RustTypeName::Pointer {
+                refd: Box::new(refd.type_name.clone()),
+                ptr_kind
+            },
+            size: structure.infer_size().unwrap(),
+            align: structure.infer_align().unwrap(),
+            structure
+        }
+    }
+
+    #[test]
+    fn covariant_pointers() {
+        let base = c_struct("CovariantBase", vec![
+            field("a", PrimitiveType::U32.rust_type()),
+            field("b", PrimitiveType::U64.rust_type())
+        ]);
+        let derived = c_struct("CovariantDerived", vec![
+            field("a", PrimitiveType::U32.rust_type()),
+            field("b", PrimitiveType::U64.rust_type()),
+            field("c", PrimitiveType::Bool.rust_type())
+        ]);
+        let reordered = c_struct("CovariantReordered", vec![
+            field("b", PrimitiveType::U64.rust_type()),
+            field("a", PrimitiveType::U32.rust_type())
+        ]);
+        // Same layout as a prefix of `pair`, but fields are swapped
+        let pair = c_struct("CovariantPair", vec![
+            field("a", PrimitiveType::U32.rust_type()),
+            field("b", PrimitiveType::U32.rust_type())
+        ]);
+        let swapped = c_struct("CovariantSwapped", vec![
+            field("b", PrimitiveType::U32.rust_type()),
+            field("a", PrimitiveType::U32.rust_type()),
+            field("c", PrimitiveType::Bool.rust_type())
+        ]);
+        let unregistered = c_struct("CovariantUnregistered", vec![
+            field("a", PrimitiveType::U32.rust_type()),
+            field("b", PrimitiveType::U64.rust_type())
+        ]);
+        for rust_type in [&base, &derived, &reordered, &pair, &swapped] {
+            RustType::register(Cow::Borrowed(rust_type), None);
+        }
+
+        let imm = |refd| pointer(RustPointerKind::ImmRef, refd);
+        let mut_ = |refd| pointer(RustPointerKind::MutRef, refd);
+        let covariant = SubtypePolicy { pointer_variance: PointerVariance::Covariant, ..SubtypePolicy::default() };
+        assert_eq!(imm(&derived).is_structural_subtype_of(&imm(&base)), IsSubtypeOf::No);
+        assert_eq!(imm(&derived).is_structural_subtype_of_with_policy(&imm(&base), covariant), IsSubtypeOf::Yes);
+        assert_eq!(imm(&base).is_structural_subtype_of_with_policy(&imm(&derived), covariant), IsSubtypeOf::No);
+        assert_eq!(imm(&reordered).is_structural_subtype_of_with_policy(&imm(&base), covariant), IsSubtypeOf::No);
+        assert_eq!(swapped.is_structural_subtype_of(&pair), IsSubtypeOf::Yes);
+        assert_eq!(imm(&swapped).is_structural_subtype_of_with_policy(&imm(&pair), covariant), IsSubtypeOf::No);
+        assert_eq!(mut_(&derived).is_structural_subtype_of_with_policy(&mut_(&base), covariant), IsSubtypeOf::No);
+        assert_eq!(imm(&unregistered).is_structural_subtype_of_with_policy(&imm(&base), covariant), IsSubtypeOf::Unknown);
+    }
+
+    #[test]
+    fn covariant_pointers_in_registry() {
+        let base = c_struct("PluginBase", vec![field("a", PrimitiveType::U32.rust_type())]);
+        let derived = c_struct("PluginDerived", vec![
+            field("a", PrimitiveType::U32.rust_type()),
+            field("b", PrimitiveType::U32.rust_type())
+        ]);
+        let mut registry = TypeRegistry::new();
+        for rust_type in [&base, &derived] {
+            registry.register(Cow::Borrowed(rust_type), None);
+        }
+
+        let covariant = SubtypePolicy { pointer_variance: PointerVariance::Covariant, ..SubtypePolicy::default() };
+        let [base_ptr, derived_ptr] = [&base, &derived].map(|refd| pointer(RustPointerKind::ImmRef, refd));
+        // The pointees are only in `registry`
+        assert_eq!(derived_ptr.is_rough_subtype_of_with_policy(&base_ptr, covariant), IsSubtypeOf::Unknown);
+        assert_eq!(derived_ptr.explain_subtype_in_registry(&base_ptr, covariant, &registry).result, IsSubtypeOf::Yes);
+
+        let mut cache = SubtypeCache::new(covariant);
+        assert_eq!(derived_ptr.explain_subtype_cached(&base_ptr, &mut cache).result, IsSubtypeOf::Unknown);
+        assert_eq!(derived_ptr.explain_subtype_cached_in_registry(&base_ptr, &mut cache, &registry).result, IsSubtypeOf::Yes);
+        assert_eq!(derived_ptr.explain_subtype_cached(&base_ptr, &mut cache).result, IsSubtypeOf::Unknown);
+
+        // A clone is a different registry, so the cache is cleared before it's used with one
+        assert_eq!(derived_ptr.explain_subtype_cached_in_registry(&base_ptr, &mut cache, &registry).result, IsSubtypeOf::Yes);
+        assert!(!cache.is_empty());
+        let clone = registry.clone();
+        let mut fresh_cache = SubtypeCache::new(covariant);
+        assert_eq!(base_ptr.explain_subtype_cached_in_registry(&derived_ptr, &mut fresh_cache, &clone).result, IsSubtypeOf::No);
+        assert_eq!(base_ptr.explain_subtype_cached_in_registry(&derived_ptr, &mut cache, &clone).result, IsSubtypeOf::No);
+        assert_eq!(cache.len(), fresh_cache.len());
+
+        assert!(base_ptr.clone().try_unify_with_policy(derived_ptr.clone(), covariant).is_err());
+        let mut unified = base_ptr.clone();
+        assert_eq!(unified.try_unify_in_registry(derived_ptr, covariant, &registry), Ok(()));
+        assert_eq!(unified, base_ptr);
+    }
+
+    #[test]
+    fn subtype_cache() {
+        // Recursive through covariant pointers
+        let name_only = |name: &str| c_struct(name, vec![]);
+        let node = c_struct("CacheNode", vec![
+            field("value", PrimitiveType::U32.rust_type()),
+            field("next", pointer(RustPointerKind::ImmRaw, &name_only("CacheNode")))
+        ]);
+        let node2 = c_struct("CacheNode2", vec![
+            field("value", PrimitiveType::U32.rust_type()),
+            field("next", pointer(RustPointerKind::ImmRaw, &name_only("CacheNode2"))),
+            field("extra", PrimitiveType::U8.rust_type())
+        ]);
+        let bad_node = c_struct("CacheBadNode", vec![
+            field("value", PrimitiveType::U16.rust_type()),
+            field("next", pointer(RustPointerKind::ImmRaw, &name_only("CacheBadNode")))
+        ]);
+        for rust_type in [&node, &node2, &bad_node] {
+            RustType::register(Cow::Borrowed(rust_type), None);
+        }
+        let policy = SubtypePolicy { pointer_variance: PointerVariance::Covariant, ..SubtypePolicy::default() };
+        let mut cache = SubtypeCache::new(policy);
+        let pointers = [&node, &node2, &bad_node].map(|refd| pointer(RustPointerKind::ImmRaw, refd));
+        for _ in 0..2 {
+            for (rust_type, other) in [(&node2, &node), (&bad_node, &node), (&node, &node), (&pointers[1], &pointers[0]), (&pointers[2], &pointers[0])] {
+                assert_eq!(rust_type.explain_subtype_cached(other, &mut cache), rust_type.explain_subtype_with_policy(other, policy));
+            }
+        }
+        let cached = cache.len();
+        assert!(cached > 0);
+
+        // Registering a new type clears the cache
+        RustType::register(Cow::Owned(c_struct("CacheOther", vec![])), None);
+        assert_eq!(node.is_rough_subtype_of_cached(&node, &mut cache), IsSubtypeOf::Yes);
+        assert!(cache.len() < cached);
+
+        // Result-only checks are cached without the failure, so explaining recomputes it
+        let mut cache = SubtypeCache::new(policy);
+        assert_eq!(bad_node.is_rough_subtype_of_cached(&node, &mut cache), IsSubtypeOf::No);
+        assert_eq!(bad_node.explain_subtype_cached(&node, &mut cache), bad_node.explain_subtype_with_policy(&node, policy));
+        assert_eq!(bad_node.is_rough_subtype_of_cached(&node, &mut cache), IsSubtypeOf::No);
+    }
+}
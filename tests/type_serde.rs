@@ -0,0 +1,60 @@
+#![cfg(all(feature = "serde", feature = "derive"))]
+
+use serde_json::json;
+use structural_reflection::{HasTypeName, InferenceContext, PrimitiveType, RustType, RustTypeName, TypeStructure};
+use structural_reflection::derive::{HasStructure, HasTypeName};
+
+#[derive(HasTypeName, HasStructure)]
+#[repr(C)]
+pub struct Waypoint {
+    pub x: f32,
+    pub y: f32,
+    pub next: Option<Box<Waypoint>>
+}
+
+#[derive(HasTypeName, HasStructure)]
+#[repr(C)]
+pub struct Marker(pub u8, pub &'static u16);
+
+#[test]
+fn type_name_serde() {
+    let type_name = RustTypeName::try_from("std::vec::Vec<u8>").unwrap();
+    let json = serde_json::to_value(&type_name).unwrap();
+    assert_eq!(json, json!("std::vec::Vec<u8>"));
+    assert_eq!(serde_json::from_value::<RustTypeName>(json).unwrap(), type_name);
+    assert!(serde_json::from_value::<RustTypeName>(json!("Vec<")).is_err());
+    assert_eq!(serde_json::to_value(PrimitiveType::U16).unwrap(), json!("U16"));
+
+    let var = InferenceContext::new().fresh_var();
+    let json = serde_json::to_value(&var.type_name).unwrap();
+    assert_eq!(json, json!("{?0}"));
+    assert_eq!(serde_json::from_value::<RustTypeName>(json).unwrap(), var.type_name);
+    let error = serde_json::to_string(&var).unwrap_err();
+    assert!(error.to_string().contains("can't serialize type variable ?0"), "{}", error);
+}
+
+#[test]
+fn rust_type_round_trip() {
+    for rust_type in [RustType::of::<Waypoint>(), RustType::of::<Marker>(), RustType::of::<String>()] {
+        let json = serde_json::to_string(&rust_type).unwrap();
+        let deserialized = serde_json::from_str::<RustType>(&json).unwrap();
+        // Type ids (including the pointee's) are restored from the registry
+        assert_eq!(deserialized, rust_type);
+        assert_eq!(deserialized.type_id, rust_type.type_id);
+        assert_eq!(deserialized.structure, rust_type.structure);
+    }
+
+    // Mismatched layout: same name, but no id
+    let mut json = serde_json::to_value(RustType::of::<Marker>()).unwrap();
+    json["size"] = json!(32);
+    let forged = serde_json::from_value::<RustType>(json).unwrap();
+    assert_eq!(forged.type_name, Marker::type_name());
+    assert_eq!(forged.type_id, None);
+
+    // Unregistered name: no id
+    let mut json = serde_json::to_value(RustType::of::<u32>()).unwrap();
+    json["type_name"] = json!("unregistered::Word");
+    let unregistered = serde_json::from_value::<RustType>(json).unwrap();
+    assert_eq!(unregistered.type_id, None);
+    assert_eq!(unregistered.structure, TypeStructure::Primitive(PrimitiveType::U32));
+}
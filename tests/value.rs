@@ -0,0 +1,143 @@
+#![cfg(feature = "derive")]
+
+use std::borrow::Cow;
+use std::ptr::NonNull;
+use structural_reflection::{HasStructure, HasTypeName, HasValidStructure, PrimitiveType, RustType, RustTypeName, TypeStructure, ValueError, ValueMut, ValueRef};
+use structural_reflection::c_tuple::CTuple2;
+use structural_reflection::derive::{HasStructure, HasTypeName};
+
+#[derive(Debug, HasTypeName, HasStructure)]
+#[repr(C)]
+pub struct Particle {
+    pub id: u8,
+    pub position: [f32; 2],
+    pub alive: bool,
+    pub kind: ParticleKind
+}
+
+#[derive(Debug, HasTypeName, HasStructure)]
+#[repr(C)]
+pub enum ParticleKind {
+    Dust,
+    Charged { charge: i32 }
+}
+
+#[derive(HasTypeName, HasStructure)]
+#[repr(C)]
+pub struct Labeled<'a> {
+    pub label: String,
+    pub target: &'a u32,
+    pub pair: CTuple2<u8, char>
+}
+
+/// Claims to be a `u64`, but is empty
+pub struct WrongStructure;
+
+impl HasTypeName for WrongStructure {
+    type StaticId = WrongStructure;
+
+    fn type_name() -> RustTypeName {
+        RustTypeName::simple("WrongStructure".to_string())
+    }
+}
+
+impl HasStructure for WrongStructure {
+    fn structure() -> TypeStructure {
+        TypeStructure::Primitive(PrimitiveType::U64)
+    }
+}
+
+// SAFETY: it isn't valid, but `ValueRef::of` checks the size before anything is read
+unsafe impl HasValidStructure for WrongStructure {}
+
+#[test]
+fn value_ref_and_mut() {
+    let mut particle = Particle {
+        id: 7,
+        position: [1.5, -2.0],
+        alive: true,
+        kind: ParticleKind::Charged { charge: -3 }
+    };
+
+    let value = ValueRef::of(&particle);
+    assert_eq!(value.field("id").unwrap().as_u8().unwrap(), 7);
+    assert_eq!(value.field("position").unwrap().index(1).unwrap().as_f32().unwrap(), -2.0);
+    assert!(value.index(2).unwrap().as_bool().unwrap());
+    let kind = value.field("kind").unwrap();
+    assert_eq!(kind.variant_name().unwrap(), "Charged");
+    assert_eq!(kind.field("charge").unwrap().as_i32().unwrap(), -3);
+
+    assert!(matches!(value.field("id").unwrap().as_u32(), Err(ValueError::TypeMismatch { .. })));
+    assert!(matches!(value.field("velocity"), Err(ValueError::NoField { .. })));
+    assert!(matches!(value.field("position").unwrap().index(2), Err(ValueError::NoIndex { .. })));
+    assert!(matches!(value.field("id").unwrap().index(0), Err(ValueError::NoIndex { .. })));
+
+    let mut value = ValueMut::of(&mut particle);
+    value.field_mut("id").unwrap().set_u8(8).unwrap();
+    value.field_mut("position").unwrap().index_mut(0).unwrap().set_f32(0.5).unwrap();
+    value.field_mut("kind").unwrap().field_mut("charge").unwrap().set_i32(4).unwrap();
+    assert!(matches!(value.field_mut("alive").unwrap().set_u8(2), Err(ValueError::TypeMismatch { .. })));
+    assert_eq!(particle.id, 8);
+    assert_eq!(particle.position, [0.5, -2.0]);
+    assert!(particle.alive);
+    assert!(matches!(particle.kind, ParticleKind::Charged { charge: 4 }));
+
+    particle.kind = ParticleKind::Dust;
+    let value = ValueRef::of(&particle);
+    let kind = value.field("kind").unwrap();
+    assert_eq!(kind.variant_index().unwrap(), 0);
+    assert!(matches!(kind.field("charge"), Err(ValueError::NoField { .. })));
+}
+
+#[test]
+fn value_debug() {
+    let particle = Particle {
+        id: 7,
+        position: [1.5, -2.0],
+        alive: true,
+        kind: ParticleKind::Charged { charge: -3 }
+    };
+    let value = ValueRef::of(&particle);
+    assert_eq!(format!("{:?}", value), format!("{:?}", particle));
+    assert_eq!(format!("{:#?}", value), format!("{:#?}", particle));
+
+    let target = 5;
+    let labeled = Labeled {
+        label: "five".to_string(),
+        target: &target,
+        pair: CTuple2(1, 'a')
+    };
+    assert_eq!(
+        format!("{:?}", ValueRef::of(&labeled)),
+        format!("Labeled {{ label: <opaque String>, target: {:?}, pair: (1, 'a') }}", &target as *const u32)
+    );
+    // A pointer type smaller than a pointer isn't read
+    let truncated_pointer = RustType {
+        size: 1,
+        align: 1,
+        ..RustType::of::<&u32>()
+    };
+    let address = &target as *const u32;
+    // SAFETY: the value is a valid pointer, which is larger than its type
+    let value = unsafe { ValueRef::from_raw(NonNull::from(&address).cast(), Cow::Owned(truncated_pointer)) };
+    assert!(format!("{:?}", value).starts_with("<error: "));
+}
+
+#[test]
+fn invalid_primitives() {
+    let byte = 2u8;
+    // SAFETY: the value is initialized, and reading validates it
+    let value = unsafe { ValueRef::from_raw(NonNull::from(&byte).cast(), Cow::Owned(PrimitiveType::Bool.rust_type())) };
+    assert!(matches!(value.as_bool(), Err(ValueError::InvalidPrimitive { primitive: PrimitiveType::Bool, .. })));
+    let surrogate = 0xD800u32;
+    // SAFETY: same as above
+    let value = unsafe { ValueRef::from_raw(NonNull::from(&surrogate).cast(), Cow::Owned(PrimitiveType::Char.rust_type())) };
+    assert!(matches!(value.as_char(), Err(ValueError::InvalidPrimitive { primitive: PrimitiveType::Char, .. })));
+    assert_eq!(value.as_u32().unwrap_err().to_string(), "char is not u32");
+}
+
+#[test]
+#[should_panic(expected = "structure of WrongStructure doesn't match its size and alignment")]
+fn value_ref_of_wrong_structure() {
+    let _ = ValueRef::of(&WrongStructure).as_u64();
+}
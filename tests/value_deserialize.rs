@@ -0,0 +1,89 @@
+#![cfg(all(feature = "serde", feature = "derive"))]
+
+use serde::Deserialize;
+use serde_json::json;
+use structural_reflection::{erased_serde, DynValue, RustType, TypeRegistry};
+use structural_reflection::derive::{HasStructure, HasTypeName};
+
+#[derive(Debug, PartialEq, Deserialize, HasTypeName, HasStructure)]
+#[repr(C)]
+pub struct Sprite {
+    pub name: String,
+    pub frames: [Frame; 2],
+    pub blend: Blend,
+    pub layer: Layer,
+    pub hidden: bool
+}
+
+#[derive(Debug, PartialEq, Deserialize, HasTypeName, HasStructure)]
+#[repr(C)]
+pub struct Frame(pub u16, pub u16);
+
+#[derive(Debug, PartialEq, Deserialize, HasTypeName, HasStructure)]
+#[repr(C)]
+pub struct Layer(pub i8);
+
+#[derive(Debug, PartialEq, Deserialize, HasTypeName, HasStructure)]
+#[repr(C)]
+pub enum Blend {
+    Normal,
+    Alpha { opacity: f32 },
+    Tint(u8, u8, u8)
+}
+
+#[derive(Debug, PartialEq, Deserialize, HasTypeName, HasStructure)]
+#[repr(C)]
+pub struct Tagged {
+    pub tags: Vec<u8>
+}
+
+fn deserialize(rust_type: &RustType, json: serde_json::Value) -> Result<DynValue, serde_json::Error> {
+    DynValue::deserialize(rust_type.clone(), json)
+}
+
+#[test]
+fn reflect_deserialize() {
+    let sprite_type = RustType::of::<Sprite>();
+    let sprite = json!({
+        "name": "player",
+        "frames": [[0, 1], [2, 3]],
+        "blend": { "Alpha": { "opacity": 0.5 } },
+        "layer": -2,
+        "hidden": false
+    });
+    assert_eq!(deserialize(&sprite_type, sprite.clone()).unwrap().downcast::<Sprite>().unwrap(), Sprite::deserialize(&sprite).unwrap());
+
+    // Missing fields are zero if they can be
+    let value = deserialize(&sprite_type, json!({ "name": "enemy", "blend": { "Tint": [1, 2, 3] } })).unwrap();
+    assert_eq!(value.downcast::<Sprite>().unwrap(), Sprite {
+        name: "enemy".to_string(),
+        frames: [Frame(0, 0), Frame(0, 0)],
+        blend: Blend::Tint(1, 2, 3),
+        layer: Layer(0),
+        hidden: false
+    });
+
+    for (invalid, message) in [
+        (json!({ "name": "a", "layer": 128 }), "invalid value"),
+        (json!({ "name": "a", "blend": "Multiply" }), "unknown variant `Multiply`"),
+        (json!({ "name": "a", "blend": "Tint" }), "isn't a unit variant"),
+        (json!({ "name": "a", "frames": [[0, 1]] }), "invalid length 1"),
+        (json!({ "name": "a", "frames": [[0, 1, 2], [3, 4]] }), "invalid length 3"),
+        (json!({ "name": "a", "speed": 1 }), "unknown field `speed`"),
+        (json!({ "layer": 1 }), "missing field `name`")
+    ] {
+        let error = deserialize(&sprite_type, invalid).unwrap_err().to_string();
+        assert!(error.contains(message), "expected {:?}, got {:?}", message, error);
+    }
+
+    // `Vec<u8>` is opaque, so it needs a custom deserializer
+    let tagged_type = RustType::of::<Tagged>();
+    let error = deserialize(&tagged_type, json!({ "tags": [1] })).unwrap_err().to_string();
+    assert!(error.contains("without a custom deserializer"), "{}", error);
+    TypeRegistry::with_global_mut(|registry| {
+        registry.register_deserializer_fn(RustType::of::<Vec<u8>>().type_name, |deserializer| {
+            erased_serde::deserialize::<Vec<u8>>(deserializer).map(|tags| DynValue::new(tags.into_iter().rev().collect::<Vec<_>>()))
+        })
+    });
+    assert_eq!(deserialize(&tagged_type, json!({ "tags": [1, 2] })).unwrap().downcast::<Tagged>().unwrap(), Tagged { tags: vec![2, 1] });
+}
@@ -0,0 +1,91 @@
+#![cfg(all(feature = "serde", feature = "derive"))]
+
+use serde::Serialize;
+use structural_reflection::{ReflectSerialize, RustType, TypeRegistry, ValueRef};
+use structural_reflection::derive::{HasStructure, HasTypeName};
+
+#[derive(Serialize, HasTypeName, HasStructure)]
+#[repr(C)]
+pub struct Shape {
+    pub name: String,
+    pub corners: [Corner; 2],
+    pub fill: Fill,
+    pub outline: Fill,
+    pub visible: bool,
+    pub layer: Layer,
+    pub tag: SerializeTag
+}
+
+#[derive(Serialize, HasTypeName, HasStructure)]
+#[repr(C)]
+pub struct Corner(pub i32, pub i32);
+
+#[derive(Serialize, HasTypeName, HasStructure)]
+#[repr(C)]
+pub struct Layer(pub u8);
+
+#[derive(Serialize, HasTypeName, HasStructure)]
+#[repr(C)]
+pub enum Fill {
+    None,
+    Solid { color: u32 },
+    Gradient(u32, u32)
+}
+
+#[derive(HasTypeName, HasStructure)]
+#[repr(C)]
+pub struct SerializeTag {
+    pub ids: Vec<u8>
+}
+
+impl Serialize for SerializeTag {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str("tag")
+    }
+}
+
+#[test]
+fn reflect_serialize() {
+    let shape = Shape {
+        name: "square".to_string(),
+        corners: [Corner(0, 0), Corner(2, -2)],
+        fill: Fill::Solid { color: 0xff0000 },
+        outline: Fill::None,
+        visible: true,
+        layer: Layer(3),
+        tag: SerializeTag { ids: vec![1, 2] }
+    };
+    let value = ValueRef::of(&shape);
+
+    // `Vec<u8>` is opaque, and `SerializeTag`'s serializer isn't registered yet
+    let error = serde_json::to_value(ReflectSerialize(value.clone())).unwrap_err();
+    assert!(error.to_string().contains("can't serialize"), "{}", error);
+
+    let tag_type = RustType::of::<SerializeTag>();
+    TypeRegistry::with_global_mut(|registry| registry.register_serializer::<SerializeTag>());
+    assert_eq!(
+        serde_json::to_value(ReflectSerialize(value.clone())).unwrap(),
+        serde_json::to_value(&shape).unwrap()
+    );
+    assert_eq!(
+        serde_json::to_value(ReflectSerialize(value.field("corners").unwrap())).unwrap(),
+        serde_json::json!([[0, 0], [2, -2]])
+    );
+
+    // Registered by name
+    TypeRegistry::with_global_mut(|registry| {
+        registry.unregister_serializer(&tag_type);
+        registry.register_serializer_fn(tag_type.type_name.clone(), |value| {
+            Ok(Box::new(value.field("ids")?.rust_type().type_name.unqualified().to_string()))
+        });
+    });
+    assert_eq!(
+        serde_json::to_value(ReflectSerialize(value.field("tag").unwrap())).unwrap(),
+        serde_json::json!("Vec<u8>")
+    );
+    let fill = Fill::Gradient(1, 2);
+    assert_eq!(
+        serde_json::to_value(ReflectSerialize(ValueRef::of(&fill))).unwrap(),
+        serde_json::json!({ "Gradient": [1, 2] })
+    );
+}